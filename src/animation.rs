@@ -0,0 +1,380 @@
+//! Keyframe animation of the camera and of objects, for rendering frame
+//! sequences. Static objects go into a BVH that is built once and shared by
+//! the scenes of all frames.
+
+use std::ops::{Add, Mul, Sub};
+use std::sync::Arc;
+
+use crate::bvh::Bvh;
+use crate::hittable::{Aabb, HitRecord, Hittable, HittableList};
+use crate::light::Light;
+use crate::scene::Scene;
+use crate::types::{Ray, Vec3};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    /// Catmull–Rom spline, which passes through every key smoothly
+    Spline,
+}
+
+/// Values that can be interpolated between keys
+pub trait Animatable:
+    Copy + Add<Output = Self> + Sub<Output = Self> + Mul<f64, Output = Self>
+{
+}
+
+impl<T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T>> Animatable for T {}
+
+/// Value changing over time, given at key times and interpolated in between.
+/// It holds the first and last values before and after the keys.
+#[derive(Clone)]
+pub struct Keyframes<T> {
+    keys: Vec<(f64, T)>,
+    interpolation: Interpolation,
+}
+
+impl<T: Animatable> Keyframes<T> {
+    /// Linearly interpolated keys starting with `value` at `time`
+    pub fn new(time: f64, value: T) -> Keyframes<T> {
+        Keyframes {
+            keys: vec![(time, value)],
+            interpolation: Interpolation::Linear,
+        }
+    }
+
+    /// Adds a key, replacing any at the same time
+    pub fn key(mut self, time: f64, value: T) -> Keyframes<T> {
+        let index = self.keys.partition_point(|&(t, _)| t < time);
+        if self.keys.get(index).is_some_and(|&(t, _)| t == time) {
+            self.keys[index].1 = value;
+        } else {
+            self.keys.insert(index, (time, value));
+        }
+        self
+    }
+
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> Keyframes<T> {
+        self.interpolation = interpolation;
+        self
+    }
+
+    pub fn at(&self, time: f64) -> T {
+        let last = self.keys.len() - 1;
+        let next = self.keys.partition_point(|&(t, _)| t <= time);
+        if next == 0 {
+            return self.keys[0].1;
+        }
+        if next > last {
+            return self.keys[last].1;
+        }
+
+        let (i, j) = (next - 1, next);
+        let ((t0, p0), (t1, p1)) = (self.keys[i], self.keys[j]);
+        let h = t1 - t0;
+        let u = (time - t0) / h;
+        match self.interpolation {
+            Interpolation::Linear => p0 + (p1 - p0) * u,
+            Interpolation::Spline => {
+                // Hermite curve with the tangents of a Catmull–Rom spline,
+                // one-sided at the ends, scaled to this segment
+                let tangent = |k: usize| {
+                    let (before, after) = (k.saturating_sub(1), usize::min(k + 1, last));
+                    let (ta, pa) = self.keys[before];
+                    let (tb, pb) = self.keys[after];
+                    (pb - pa) * (h / (tb - ta))
+                };
+                let (u2, u3) = (u * u, u * u * u);
+                p0 * (2. * u3 - 3. * u2 + 1.)
+                    + tangent(i) * (u3 - 2. * u2 + u)
+                    + p1 * (3. * u2 - 2. * u3)
+                    + tangent(j) * (u3 - u2)
+            }
+        }
+    }
+}
+
+/// Where the camera is and where it looks at one moment
+#[derive(Clone, Copy)]
+pub struct CameraPose {
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    /// Top to bottom in degrees
+    pub vert_fov: f64,
+}
+
+#[derive(Clone)]
+pub struct CameraAnimation {
+    look_from: Keyframes<Vec3>,
+    look_at: Keyframes<Vec3>,
+    vert_fov: Keyframes<f64>,
+}
+
+impl CameraAnimation {
+    pub fn new(
+        look_from: Keyframes<Vec3>,
+        look_at: Keyframes<Vec3>,
+        vert_fov: Keyframes<f64>,
+    ) -> CameraAnimation {
+        CameraAnimation {
+            look_from,
+            look_at,
+            vert_fov,
+        }
+    }
+
+    /// Holds `pose` until given more keys
+    pub fn still(time: f64, pose: CameraPose) -> CameraAnimation {
+        CameraAnimation::new(
+            Keyframes::new(time, pose.look_from),
+            Keyframes::new(time, pose.look_at),
+            Keyframes::new(time, pose.vert_fov),
+        )
+    }
+
+    /// Adds a key, replacing any at the same time
+    pub fn key(self, time: f64, pose: CameraPose) -> CameraAnimation {
+        CameraAnimation::new(
+            self.look_from.key(time, pose.look_from),
+            self.look_at.key(time, pose.look_at),
+            self.vert_fov.key(time, pose.vert_fov),
+        )
+    }
+
+    pub fn with_interpolation(self, interpolation: Interpolation) -> CameraAnimation {
+        CameraAnimation::new(
+            self.look_from.with_interpolation(interpolation),
+            self.look_at.with_interpolation(interpolation),
+            self.vert_fov.with_interpolation(interpolation),
+        )
+    }
+
+    /// Circles `look_at` once counterclockwise from `start` to `end`,
+    /// starting from `look_from` and keeping its height, as on a turntable.
+    /// It keeps going a little way beyond both ends.
+    pub fn turntable(
+        look_from: Vec3,
+        look_at: Vec3,
+        vert_fov: f64,
+        (start, end): (f64, f64),
+    ) -> CameraAnimation {
+        // Enough keys for the spline to stay close to the circle, plus one
+        // beyond each end so the tangents there follow it too
+        let keys = 16;
+        let offset = look_from - look_at;
+        let mut path = Keyframes::new(start, look_from).with_interpolation(Interpolation::Spline);
+        for k in -1..=keys + 1 {
+            let fraction = k as f64 / keys as f64;
+            path = path.key(
+                start + (end - start) * fraction,
+                look_at + rotate_y(&offset, 360. * fraction),
+            );
+        }
+        CameraAnimation::new(
+            path,
+            Keyframes::new(start, look_at),
+            Keyframes::new(start, vert_fov),
+        )
+    }
+
+    pub fn pose(&self, time: f64) -> CameraPose {
+        CameraPose {
+            look_from: self.look_from.at(time),
+            look_at: self.look_at.at(time),
+            vert_fov: self.vert_fov.at(time),
+        }
+    }
+}
+
+/// Uniform scale, then rotation around the y axis, then translation
+#[derive(Clone, Copy)]
+pub struct Transform {
+    pub translation: Vec3,
+    /// Counterclockwise seen from above, in degrees
+    pub rotation: f64,
+    pub scale: f64,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translation: Vec3::default(),
+            rotation: 0.,
+            scale: 1.,
+        }
+    }
+}
+
+impl Transform {
+    pub fn point(&self, p: &Vec3) -> Vec3 {
+        self.translation + self.scale * rotate_y(p, self.rotation)
+    }
+
+    pub fn inverse_point(&self, p: &Vec3) -> Vec3 {
+        rotate_y(&(*p - self.translation), -self.rotation) / self.scale
+    }
+}
+
+// Rotates counterclockwise around the y axis, seen from above
+fn rotate_y(v: &Vec3, degrees: f64) -> Vec3 {
+    let (sin, cos) = f64::sin_cos(degrees.to_radians());
+    Vec3::new(cos * v.x() + sin * v.z(), v.y(), -sin * v.x() + cos * v.z())
+}
+
+/// An object placed by a transform
+pub struct Transformed {
+    object: Arc<dyn Hittable>,
+    transform: Transform,
+}
+
+impl Transformed {
+    pub fn new(object: Arc<dyn Hittable>, transform: Transform) -> Transformed {
+        Transformed { object, transform }
+    }
+}
+
+impl Hittable for Transformed {
+    fn hit(&self, t_range: (f64, f64), ray: &Ray) -> Option<HitRecord> {
+        // Scaling the direction too keeps distances along the ray the same
+        let transform = &self.transform;
+        let local = Ray {
+            origin: transform.inverse_point(&ray.origin),
+            direction: rotate_y(&ray.direction, -transform.rotation) / transform.scale,
+            ..*ray
+        };
+        let mut hit_rec = self.object.hit(t_range, &local)?;
+        hit_rec.p = transform.point(&hit_rec.p);
+        hit_rec.normal = rotate_y(&hit_rec.normal, transform.rotation);
+        Some(hit_rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let Aabb { min, max } = self.object.bounding_box();
+        let mut bbox = Aabb::empty();
+        for corner in 0..8 {
+            let p = Vec3::new(
+                if corner & 1 == 0 { min.x() } else { max.x() },
+                if corner & 2 == 0 { min.y() } else { max.y() },
+                if corner & 4 == 0 { min.z() } else { max.z() },
+            );
+            let p = self.transform.point(&p);
+            bbox = bbox.union(&Aabb::new(p, p));
+        }
+        bbox
+    }
+}
+
+/// An object moved, turned and scaled by keyframes
+pub struct AnimatedObject {
+    object: Arc<dyn Hittable>,
+    translation: Keyframes<Vec3>,
+    rotation: Keyframes<f64>,
+    scale: Keyframes<f64>,
+}
+
+impl AnimatedObject {
+    /// Starts out where `object` is, until given keys
+    pub fn new(object: Arc<dyn Hittable>) -> AnimatedObject {
+        AnimatedObject {
+            object,
+            translation: Keyframes::new(0., Vec3::default()),
+            rotation: Keyframes::new(0., 0.),
+            scale: Keyframes::new(0., 1.),
+        }
+    }
+
+    /// Placed by `transform` until given more keys
+    pub fn starting_at(
+        object: Arc<dyn Hittable>,
+        time: f64,
+        transform: Transform,
+    ) -> AnimatedObject {
+        AnimatedObject {
+            object,
+            translation: Keyframes::new(time, transform.translation),
+            rotation: Keyframes::new(time, transform.rotation),
+            scale: Keyframes::new(time, transform.scale),
+        }
+    }
+
+    /// Adds a key for the whole transform, replacing any at the same time
+    pub fn key(mut self, time: f64, transform: Transform) -> AnimatedObject {
+        self.translation = self.translation.key(time, transform.translation);
+        self.rotation = self.rotation.key(time, transform.rotation);
+        self.scale = self.scale.key(time, transform.scale);
+        self
+    }
+
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> AnimatedObject {
+        self.translation = self.translation.with_interpolation(interpolation);
+        self.rotation = self.rotation.with_interpolation(interpolation);
+        self.scale = self.scale.with_interpolation(interpolation);
+        self
+    }
+
+    pub fn with_translation(mut self, translation: Keyframes<Vec3>) -> AnimatedObject {
+        self.translation = translation;
+        self
+    }
+
+    /// Degrees around the y axis, see `Transform`
+    pub fn with_rotation(mut self, rotation: Keyframes<f64>) -> AnimatedObject {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Keyframes<f64>) -> AnimatedObject {
+        self.scale = scale;
+        self
+    }
+
+    pub fn transform(&self, time: f64) -> Transform {
+        Transform {
+            translation: self.translation.at(time),
+            rotation: self.rotation.at(time),
+            scale: self.scale.at(time),
+        }
+    }
+
+    /// The object where it is at `time`
+    pub fn at(&self, time: f64) -> Transformed {
+        Transformed::new(Arc::clone(&self.object), self.transform(time))
+    }
+}
+
+/// A scene whose animated objects move while the rest stays put
+pub struct Animation {
+    static_world: Arc<Bvh>,
+    objects: Vec<AnimatedObject>,
+    lights: Vec<Arc<dyn Light>>,
+}
+
+impl Animation {
+    pub fn new(static_world: HittableList, lights: Vec<Arc<dyn Light>>) -> Animation {
+        Animation {
+            static_world: Arc::new(Bvh::new(static_world)),
+            objects: Vec::new(),
+            lights,
+        }
+    }
+
+    pub fn with_object(mut self, object: AnimatedObject) -> Animation {
+        self.objects.push(object);
+        self
+    }
+
+    /// The scene at `time`, sharing the BVH of the static objects
+    pub fn scene_at(&self, time: f64) -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Arc::clone(&self.static_world)));
+        for object in &self.objects {
+            world.add(Box::new(object.at(time)));
+        }
+        Scene::new(world, self.lights.clone())
+    }
+}
+
+/// Time of frame `frame` of `frames` evenly spread over [start, end)
+pub fn frame_time((start, end): (f64, f64), frame: usize, frames: usize) -> f64 {
+    start + (end - start) * frame as f64 / usize::max(frames, 1) as f64
+}
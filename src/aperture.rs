@@ -0,0 +1,127 @@
+//! Shapes of the aperture of a thin lens, which out of focus highlights take
+//! on. Points on the aperture are in lens coordinates, scaled so that the
+//! aperture fits in the unit disk, or for masks in the square around it.
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::sampling::{self, Distribution2D};
+use crate::Ppm;
+
+#[derive(Clone, Default)]
+pub enum Aperture {
+    #[default]
+    Circular,
+    /// Regular polygon formed by `blades` straight blades, with corners on
+    /// the unit circle. A rotation of 0 puts a corner on the right.
+    Polygonal {
+        blades: usize,
+        rotation: f64,
+    },
+    Mask(Arc<ApertureMask>),
+}
+
+impl Aperture {
+    /// Polygon with `blades` blades, rotated by `degrees` counterclockwise.
+    /// Fewer than three blades give a circle.
+    pub fn polygonal(blades: usize, degrees: f64) -> Aperture {
+        if blades < 3 {
+            return Aperture::Circular;
+        }
+        Aperture::Polygonal {
+            blades,
+            rotation: degrees.to_radians(),
+        }
+    }
+
+    /// Point on the aperture, sampled proportionally to how much light it
+    /// lets through
+    pub fn sample(&self, u: (f64, f64)) -> (f64, f64) {
+        match self {
+            Aperture::Circular => sampling::concentric_disk(u),
+            Aperture::Polygonal { blades, rotation } => {
+                // Pick one of the triangles between the centre and an edge,
+                // reusing the rest of u.0 to sample it uniformly
+                let scaled = u.0 * *blades as f64;
+                let blade = f64::min(scaled.floor(), (*blades - 1) as f64);
+                let (u0, u1) = (scaled - blade, u.1);
+                let corner = |i: f64| {
+                    let angle = rotation + 2. * PI * i / *blades as f64;
+                    (f64::cos(angle), f64::sin(angle))
+                };
+                let (a, b) = (corner(blade), corner(blade + 1.));
+                let r = f64::sqrt(u0);
+                (
+                    r * ((1. - u1) * a.0 + u1 * b.0),
+                    r * ((1. - u1) * a.1 + u1 * b.1),
+                )
+            }
+            Aperture::Mask(mask) => {
+                let ((x, y), _) = mask.distribution.sample(u);
+                (2. * x - 1., 2. * y - 1.)
+            }
+        }
+    }
+
+    /// Density of `sample` per unit area at point (x, y)
+    pub fn pdf(&self, (x, y): (f64, f64)) -> f64 {
+        match self {
+            Aperture::Circular => {
+                if x * x + y * y <= 1. {
+                    1. / PI
+                } else {
+                    0.
+                }
+            }
+            Aperture::Polygonal { blades, rotation } => {
+                let n = *blades as f64;
+                let wedge = 2. * PI / n;
+                let angle = (f64::atan2(y, x) - rotation).rem_euclid(2. * PI);
+                let middle = (f64::floor(angle / wedge) + 0.5) * wedge;
+                // Distance from the centre to the edge along the middle of
+                // the wedge the point is in
+                let apothem = f64::cos(PI / n);
+                if f64::hypot(x, y) * f64::cos(angle - middle) <= apothem {
+                    1. / (0.5 * n * f64::sin(wedge))
+                } else {
+                    0.
+                }
+            }
+            Aperture::Mask(mask) => {
+                if x.abs() >= 1. || y.abs() >= 1. {
+                    return 0.;
+                }
+                // The mask covers the square [-1, 1]^2
+                mask.distribution.pdf(((x + 1.) / 2., (y + 1.) / 2.)) / 4.
+            }
+        }
+    }
+}
+
+/// How much light every part of the aperture lets through, from the
+/// brightness of an image stretched over the square around the unit disk
+pub struct ApertureMask {
+    distribution: Distribution2D,
+}
+
+impl ApertureMask {
+    /// Returns None for an image that is black everywhere
+    pub fn from_image(image: &Ppm) -> Option<ApertureMask> {
+        let (width, height) = (image.get_width(), image.get_height());
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let mut transmission = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                transmission.push(image.get_pixel(x, y).luminance() / 255.);
+            }
+        }
+        let distribution = Distribution2D::new(&transmission, width, height);
+        if distribution.integral() > 0. {
+            Some(ApertureMask { distribution })
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,700 @@
+//! Bidirectional path tracing. Every camera sample traces one subpath from the
+//! camera and one from a light, then connects every prefix of the two and
+//! combines the resulting strategies with the balance heuristic. Both
+//! subpaths carry the same wavelength, so that dispersive glass refracts them
+//! alike.
+
+use std::sync::Arc;
+
+use crate::camera::Camera;
+use crate::film::Film;
+use crate::hittable::HitRecord;
+use crate::integrator::Integrator;
+use crate::light::Light;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::spectrum::{self, Wavelengths};
+use crate::stats::{self, Counter};
+use crate::types::{Ray, Vec3};
+
+#[derive(Clone, Copy, PartialEq)]
+enum VertexKind {
+    Camera,
+    Light,
+    Surface,
+}
+
+/// What a subpath carries: radiance towards the camera or importance towards
+/// the lights, which refraction scales differently
+#[derive(Clone, Copy, PartialEq)]
+enum Transport {
+    Radiance,
+    Importance,
+}
+
+/// A subpath vertex. `pdf_fwd` is the area density with which the vertex was
+/// sampled by its own subpath and `pdf_rev` the density with which the other
+/// subpath would have sampled it. Infinite lights use solid angle densities.
+#[derive(Clone)]
+struct Vertex {
+    kind: VertexKind,
+    p: Vec3,
+    // Zero for vertices that are not on a surface
+    n: Vec3,
+    // Unit direction towards the previous vertex of the subpath
+    wo: Vec3,
+    beta: Vec3,
+    delta: bool,
+    pdf_fwd: f64,
+    pdf_rev: f64,
+    hit: Option<HitRecord>,
+    // None for the vertex created when a camera path escapes the scene
+    light: Option<Arc<dyn Light>>,
+}
+
+struct Context<'a> {
+    scene: &'a Scene,
+    camera: &'a dyn Camera,
+    // Time of the camera ray, which the light path is traced at too
+    time: f64,
+    // Wavelength of both subpaths and the color it adds to paths that pass
+    // through dispersive materials
+    wavelength: Option<Wavelengths>,
+    wavelength_rgb: Vec3,
+}
+
+impl Context<'_> {
+    fn light_pdf(&self) -> f64 {
+        self.scene.light_pdf()
+    }
+
+    /// Density of picking direction `w` into the scene from any infinite light
+    fn infinite_light_density(&self, w: &Vec3) -> f64 {
+        let origin = Vec3::default();
+        self.scene
+            .lights()
+            .iter()
+            .filter(|light| light.is_infinite())
+            .map(|light| self.light_pdf() * light.pdf_li(&origin, &-*w))
+            .sum()
+    }
+}
+
+impl Vertex {
+    fn camera(p: Vec3, beta: Vec3) -> Vertex {
+        Vertex {
+            kind: VertexKind::Camera,
+            p,
+            n: Vec3::default(),
+            wo: Vec3::default(),
+            beta,
+            delta: false,
+            pdf_fwd: 0.,
+            pdf_rev: 0.,
+            hit: None,
+            light: None,
+        }
+    }
+
+    fn light(light: Option<Arc<dyn Light>>, p: Vec3, n: Vec3, beta: Vec3, pdf_fwd: f64) -> Vertex {
+        Vertex {
+            kind: VertexKind::Light,
+            p,
+            n,
+            wo: Vec3::default(),
+            beta,
+            delta: false,
+            pdf_fwd,
+            pdf_rev: 0.,
+            hit: None,
+            light,
+        }
+    }
+
+    fn surface(hit: HitRecord, wo: Vec3, beta: Vec3) -> Vertex {
+        Vertex {
+            kind: VertexKind::Surface,
+            p: hit.p,
+            n: hit.normal,
+            wo,
+            beta,
+            delta: false,
+            pdf_fwd: 0.,
+            pdf_rev: 0.,
+            light: hit.light.clone(),
+            hit: Some(hit),
+        }
+    }
+
+    fn is_on_surface(&self) -> bool {
+        !self.n.is_zero()
+    }
+
+    fn is_light(&self) -> bool {
+        self.kind == VertexKind::Light || self.light.is_some()
+    }
+
+    fn is_infinite_light(&self) -> bool {
+        self.kind == VertexKind::Light && self.light.as_ref().is_none_or(|l| l.is_infinite())
+    }
+
+    fn disperses(&self) -> bool {
+        self.hit.as_ref().is_some_and(|hit| hit.mat.disperses())
+    }
+
+    /// Fraction of the light that survives the way from `v` to this vertex,
+    /// or None if it arrives from the front and so not through the material
+    fn transmittance(&self, v: &Vertex) -> Option<Vec3> {
+        let hit = self.hit.as_ref()?;
+        let d = self.p - v.p;
+        (Vec3::dot(&d, &hit.normal) > 0.).then(|| hit.mat.transmittance(d.length(), hit))
+    }
+
+    fn is_connectible(&self) -> bool {
+        match &self.hit {
+            Some(hit) => !hit.mat.is_specular(),
+            None => true,
+        }
+    }
+
+    /// BSDF value for scattering from the previous vertex towards `next`
+    fn f(&self, next: &Vertex, transport: Transport) -> Vec3 {
+        match &self.hit {
+            Some(hit) => {
+                let wi = Vec3::unit_vector(&(next.p - self.p));
+                let f = hit.mat.eval(&self.wo, &wi, hit);
+                match transport {
+                    Transport::Radiance => f * hit.mat.radiance_scale(&self.wo, &wi, hit),
+                    Transport::Importance => f,
+                }
+            }
+            None => Vec3::default(),
+        }
+    }
+
+    /// Converts a solid angle density at this vertex into an area density at `next`
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        if next.is_infinite_light() {
+            return pdf;
+        }
+        let w = next.p - self.p;
+        let dist_squared = w.squared_len();
+        if dist_squared == 0. {
+            return 0.;
+        }
+        let mut pdf = pdf / dist_squared;
+        if next.is_on_surface() {
+            pdf *= f64::abs(Vec3::dot(&next.n, &(w / dist_squared.sqrt())));
+        }
+        pdf
+    }
+
+    /// Area density of sampling `next` from this vertex, having arrived from `prev`
+    fn pdf(&self, ctx: &Context, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        if self.kind == VertexKind::Light {
+            return self.pdf_light(ctx, next);
+        }
+
+        let wn = next.p - self.p;
+        if wn.squared_len() == 0. {
+            return 0.;
+        }
+        let wn = Vec3::unit_vector(&wn);
+        let pdf = match (&self.hit, prev) {
+            (Some(hit), Some(prev)) => {
+                let wp = Vec3::unit_vector(&(prev.p - self.p));
+                hit.mat.pdf(&wp, &wn, hit)
+            }
+            (Some(_), None) => 0.,
+            (None, _) => ctx.camera.pdf_we(&Ray::from(self.p, wn)).1,
+        };
+        self.convert_density(pdf, next)
+    }
+
+    /// Area density with which this light vertex emits towards `v`
+    fn pdf_light(&self, ctx: &Context, v: &Vertex) -> f64 {
+        let w = v.p - self.p;
+        let dist_squared = w.squared_len();
+        let w = w / dist_squared.sqrt();
+        let mut pdf = if self.is_infinite_light() {
+            let radius = ctx.scene.bounds().1;
+            1. / (std::f64::consts::PI * radius * radius)
+        } else {
+            match &self.light {
+                Some(light) => {
+                    let (_, pdf_dir) =
+                        light.pdf_le(&Ray::from(self.p, w), &self.n, ctx.scene.bounds());
+                    pdf_dir / dist_squared
+                }
+                None => 0.,
+            }
+        };
+        if v.is_on_surface() {
+            pdf *= f64::abs(Vec3::dot(&v.n, &w));
+        }
+        pdf
+    }
+
+    /// Density of choosing this light vertex as the start of a light subpath
+    /// heading towards `v`
+    fn pdf_light_origin(&self, ctx: &Context, v: &Vertex) -> f64 {
+        let w = Vec3::unit_vector(&(v.p - self.p));
+        if self.is_infinite_light() {
+            return ctx.infinite_light_density(&w);
+        }
+        match &self.light {
+            Some(light) => {
+                let (pdf_pos, _) = light.pdf_le(&Ray::from(self.p, w), &self.n, ctx.scene.bounds());
+                ctx.light_pdf() * pdf_pos
+            }
+            None => 0.,
+        }
+    }
+
+    /// Radiance emitted from this vertex towards `v`
+    fn le(&self, ctx: &Context, v: &Vertex) -> Vec3 {
+        if !self.is_light() {
+            return Vec3::default();
+        }
+        let w = Vec3::unit_vector(&(v.p - self.p));
+        if self.is_infinite_light() {
+            return ctx.scene.escaped(&Ray::from(v.p, -w));
+        }
+        match &self.hit {
+            Some(hit) => hit.mat.emitted(&Ray::from(v.p, -w), hit),
+            None => Vec3::default(),
+        }
+    }
+}
+
+pub struct BidirectionalPathTracer {
+    max_depth: usize,
+}
+
+impl BidirectionalPathTracer {
+    pub fn new(max_depth: usize) -> BidirectionalPathTracer {
+        BidirectionalPathTracer { max_depth }
+    }
+
+    fn random_walk(
+        &self,
+        ctx: &Context,
+        mut ray: Ray,
+        mut beta: Vec3,
+        pdf: f64,
+        path: &mut Vec<Vertex>,
+        sampler: &mut dyn Sampler,
+    ) {
+        let from_camera = path[0].kind == VertexKind::Camera;
+        // Camera paths need one more vertex since the camera is not a bounce
+        let max_depth = if from_camera {
+            self.max_depth + 1
+        } else {
+            self.max_depth
+        };
+        let mut pdf_fwd = pdf;
+        let mut bounces = 0;
+        while bounces < max_depth {
+            let hit_rec = match ctx.scene.hit(&ray) {
+                Some(hit_rec) => hit_rec,
+                None => {
+                    // Escaping camera paths end on the infinite lights
+                    if from_camera {
+                        let dir = Vec3::unit_vector(&ray.direction);
+                        path.push(Vertex::light(None, ray.pos(1.), -dir, beta, pdf_fwd));
+                    }
+                    break;
+                }
+            };
+
+            let wo = -Vec3::unit_vector(&ray.direction);
+            let mut vertex = Vertex::surface(hit_rec.clone(), wo, beta);
+            let last = &path[path.len() - 1];
+            vertex.pdf_fwd = last.convert_density(pdf_fwd, &vertex);
+            // Light absorbed on the way here, which scattering also takes out
+            // of the weight of the rest of the path
+            vertex.beta *= transmittance(last, &vertex);
+            bounces += 1;
+            if bounces >= max_depth {
+                path.push(vertex);
+                break;
+            }
+
+            let (scattered, attenuation) = match hit_rec.mat.scatter(&ray, &hit_rec, sampler) {
+                Some(scatter) => scatter,
+                None => {
+                    path.push(vertex);
+                    break;
+                }
+            };
+            let wi = Vec3::unit_vector(&scattered.direction);
+            let pdf_rev = if hit_rec.mat.is_specular() {
+                vertex.delta = true;
+                pdf_fwd = 0.;
+                0.
+            } else {
+                pdf_fwd = hit_rec.mat.pdf(&wo, &wi, &hit_rec);
+                hit_rec.mat.pdf(&wi, &wo, &hit_rec)
+            };
+            beta *= attenuation;
+            if from_camera {
+                beta *= hit_rec.mat.radiance_scale(&wo, &wi, &hit_rec);
+            }
+
+            let prev = path.len() - 1;
+            path[prev].pdf_rev = vertex.convert_density(pdf_rev, &path[prev]);
+            path.push(vertex);
+
+            if beta.is_zero() || (pdf_fwd == 0. && !hit_rec.mat.is_specular()) {
+                break;
+            }
+            ray = hit_rec.spawn_ray(wi).with_wavelength(scattered.wavelength);
+        }
+    }
+
+    fn generate_light_subpath(
+        &self,
+        ctx: &Context,
+        path: &mut Vec<Vertex>,
+        sampler: &mut dyn Sampler,
+    ) {
+        let light = match ctx.scene.choose_light(sampler.get_1d()) {
+            Some((light, _)) => light,
+            None => return,
+        };
+        let emission = match light.sample_le(sampler.get_2d(), sampler.get_2d(), ctx.scene.bounds())
+        {
+            Some(emission) => emission,
+            None => return,
+        };
+        if emission.pdf_pos == 0. || emission.pdf_dir == 0. || emission.radiance.is_zero() {
+            return;
+        }
+        stats::count(Counter::LightPaths, 1);
+
+        let light_pdf = ctx.light_pdf();
+        let dir = Vec3::unit_vector(&emission.ray.direction);
+        path.push(Vertex::light(
+            Some(light.clone()),
+            emission.ray.origin,
+            emission.normal,
+            emission.radiance / (light_pdf * emission.pdf_pos),
+            light_pdf * emission.pdf_pos,
+        ));
+        let beta = emission.radiance * f64::abs(Vec3::dot(&emission.normal, &dir))
+            / (light_pdf * emission.pdf_pos * emission.pdf_dir);
+        self.random_walk(
+            ctx,
+            Ray::at_time(emission.ray.origin, dir, ctx.time).with_wavelength(ctx.wavelength),
+            beta,
+            emission.pdf_dir,
+            path,
+            sampler,
+        );
+
+        // Infinite lights pick a direction first and then a position on a disk
+        // facing it, so their densities are swapped around
+        if path[0].is_infinite_light() {
+            if path.len() > 1 {
+                path[1].pdf_fwd = emission.pdf_pos;
+                if path[1].is_on_surface() {
+                    path[1].pdf_fwd *= f64::abs(Vec3::dot(&dir, &path[1].n));
+                }
+            }
+            path[0].pdf_fwd = ctx.infinite_light_density(&dir);
+        }
+    }
+
+    /// Contribution of the path made of the first `s` light vertices and the
+    /// first `t` camera vertices, along with where it lands on the film when it
+    /// was connected directly to the camera
+    fn connect(
+        &self,
+        ctx: &Context,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        s: usize,
+        t: usize,
+        sampler: &mut dyn Sampler,
+    ) -> (Vec3, Option<(f64, f64)>) {
+        let none = (Vec3::default(), None);
+        // Escaped camera paths can only be lit by the infinite lights they hit
+        if t > 1 && s != 0 && camera_path[t - 1].kind == VertexKind::Light {
+            return none;
+        }
+
+        let mut sampled = None;
+        let mut film_pos = None;
+        let l = if s == 0 {
+            let pt = &camera_path[t - 1];
+            pt.le(ctx, &camera_path[t - 2]) * pt.beta
+        } else if t == 1 {
+            let qs = &light_path[s - 1];
+            if !qs.is_connectible() {
+                return none;
+            }
+            let cs = match ctx.camera.sample_wi(&qs.p, sampler.get_2d()) {
+                Some(cs) if cs.pdf > 0. && cs.importance > 0. => cs,
+                _ => return none,
+            };
+            let weight = cs.importance / cs.pdf;
+            let vertex = Vertex::camera(cs.lens_point, Vec3::from((weight, weight, weight)));
+            let mut l = qs.beta
+                * qs.f(&vertex, Transport::Importance)
+                * vertex.beta
+                * transmittance(qs, &vertex);
+            if qs.is_on_surface() {
+                l *= f64::abs(Vec3::dot(&cs.wi, &qs.n));
+            }
+            if !l.is_zero() && !ctx.scene.unoccluded(&qs.p, &cs.lens_point, ctx.time) {
+                return none;
+            }
+            film_pos = Some(cs.film_pos);
+            sampled = Some(vertex);
+            l
+        } else if s == 1 {
+            let pt = &camera_path[t - 1];
+            if !pt.is_connectible() {
+                return none;
+            }
+            let light = match ctx.scene.choose_light(sampler.get_1d()) {
+                Some((light, _)) => light,
+                None => return none,
+            };
+            let ls = match light.sample_li(&pt.p, sampler.get_2d(), ctx.scene.bounds()) {
+                Some(ls) if ls.pdf > 0. && !ls.radiance.is_zero() => ls,
+                _ => return none,
+            };
+            let mut vertex = Vertex::light(
+                Some(light.clone()),
+                ls.p,
+                ls.normal,
+                ls.radiance / (ls.pdf * ctx.light_pdf()),
+                0.,
+            );
+            vertex.pdf_fwd = vertex.pdf_light_origin(ctx, pt);
+            let mut l = pt.beta
+                * pt.f(&vertex, Transport::Radiance)
+                * vertex.beta
+                * transmittance(&vertex, pt);
+            if pt.is_on_surface() {
+                l *= f64::abs(Vec3::dot(&ls.wi, &pt.n));
+            }
+            if !l.is_zero() && !ctx.scene.unoccluded(&pt.p, &ls.p, ctx.time) {
+                return none;
+            }
+            sampled = Some(vertex);
+            l
+        } else {
+            let qs = &light_path[s - 1];
+            let pt = &camera_path[t - 1];
+            if !qs.is_connectible() || !pt.is_connectible() {
+                return none;
+            }
+            let l =
+                qs.beta * qs.f(pt, Transport::Importance) * pt.f(qs, Transport::Radiance) * pt.beta;
+            if l.is_zero() {
+                return none;
+            }
+            l * geometry_term(ctx, qs, pt) * transmittance(qs, pt)
+        };
+
+        if l.is_zero() {
+            return none;
+        }
+        let weight = mis_weight(ctx, light_path, camera_path, sampled.as_ref(), s, t);
+        // The wavelength only colors paths that it bent
+        let mut vertices = light_path[..s].iter().chain(&camera_path[..t]);
+        let l = if vertices.any(Vertex::disperses) {
+            l * ctx.wavelength_rgb
+        } else {
+            l
+        };
+        (l * weight, film_pos)
+    }
+}
+
+impl Default for BidirectionalPathTracer {
+    fn default() -> Self {
+        BidirectionalPathTracer::new(10)
+    }
+}
+
+impl Integrator for BidirectionalPathTracer {
+    fn li(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        camera: &dyn Camera,
+        film: &Film,
+        sampler: &mut dyn Sampler,
+    ) -> Vec3 {
+        let (lambda, pdf) = spectrum::sample_wavelength(sampler.get_1d());
+        let ctx = Context {
+            scene,
+            camera,
+            time: ray.time,
+            wavelength: Some(Wavelengths::Single(lambda)),
+            wavelength_rgb: spectrum::wavelength_rgb(lambda, pdf),
+        };
+        let ray = Ray::at_time(ray.origin, Vec3::unit_vector(&ray.direction), ray.time)
+            .with_wavelength(ctx.wavelength);
+        let one = Vec3::from((1., 1., 1.));
+
+        let mut camera_path = Vec::with_capacity(self.max_depth + 2);
+        let mut camera_vertex = Vertex::camera(ray.origin, one);
+        // Light paths cannot be connected to cameras that have no lens to
+        // sample, which the weights treat like a delta distribution
+        camera_vertex.delta = !camera.can_connect();
+        camera_path.push(camera_vertex);
+        let (_, pdf_dir) = camera.pdf_we(&ray);
+        self.random_walk(&ctx, ray, one, pdf_dir, &mut camera_path, sampler);
+
+        let mut light_path = Vec::with_capacity(self.max_depth + 1);
+        self.generate_light_subpath(&ctx, &mut light_path, sampler);
+
+        let mut l = Vec3::default();
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len() {
+                let depth = (s + t) as isize - 2;
+                if (s == 1 && t == 1) || depth < 0 || depth > self.max_depth as isize {
+                    continue;
+                }
+
+                let (contribution, film_pos) =
+                    self.connect(&ctx, &light_path, &camera_path, s, t, sampler);
+                match film_pos {
+                    Some(film_pos) => film.add_splat(film_pos, contribution),
+                    None => l += contribution,
+                }
+            }
+        }
+
+        l
+    }
+}
+
+/// Geometric coupling of two vertices, zero if they can not see each other
+fn geometry_term(ctx: &Context, v0: &Vertex, v1: &Vertex) -> f64 {
+    let d = v0.p - v1.p;
+    let mut g = 1. / d.squared_len();
+    let d = d * g.sqrt();
+    if v0.is_on_surface() {
+        g *= f64::abs(Vec3::dot(&v0.n, &d));
+    }
+    if v1.is_on_surface() {
+        g *= f64::abs(Vec3::dot(&v1.n, &d));
+    }
+    if ctx.scene.unoccluded(&v0.p, &v1.p, ctx.time) {
+        g
+    } else {
+        0.
+    }
+}
+
+/// Fraction of the light that survives between two vertices, absorbed by the
+/// material behind whichever one the segment meets from behind
+fn transmittance(v0: &Vertex, v1: &Vertex) -> Vec3 {
+    v1.transmittance(v0)
+        .or_else(|| v0.transmittance(v1))
+        .unwrap_or(Vec3::from((1., 1., 1.)))
+}
+
+/// Balance heuristic weight of the (s, t) strategy, computed from the ratios
+/// of the densities of the strategies that could have produced the same path
+fn mis_weight(
+    ctx: &Context,
+    light_path: &[Vertex],
+    camera_path: &[Vertex],
+    sampled: Option<&Vertex>,
+    s: usize,
+    t: usize,
+) -> f64 {
+    if s + t == 2 {
+        return 1.;
+    }
+    // Delta densities are recorded as zero and must not affect the ratios
+    let remap0 = |f: f64| if f != 0. { f } else { 1. };
+
+    // (pdf_fwd, pdf_rev, delta) of every vertex, updated for this strategy
+    let mut camera: Vec<_> = camera_path[..t]
+        .iter()
+        .map(|v| (v.pdf_fwd, v.pdf_rev, v.delta))
+        .collect();
+    let mut light: Vec<_> = light_path[..s]
+        .iter()
+        .map(|v| (v.pdf_fwd, v.pdf_rev, v.delta))
+        .collect();
+
+    let qs = match s {
+        0 => None,
+        1 => sampled,
+        _ => Some(&light_path[s - 1]),
+    };
+    let pt = match t {
+        1 => sampled.expect("camera connections always sample a vertex"),
+        _ => &camera_path[t - 1],
+    };
+    let qs_minus = if s > 1 {
+        Some(&light_path[s - 2])
+    } else {
+        None
+    };
+    let pt_minus = if t > 1 {
+        Some(&camera_path[t - 2])
+    } else {
+        None
+    };
+
+    if t == 1 {
+        camera[0] = (pt.pdf_fwd, pt.pdf_rev, pt.delta);
+    }
+    if let Some(qs) = qs {
+        light[s - 1] = (qs.pdf_fwd, qs.pdf_rev, false);
+    }
+    camera[t - 1].2 = false;
+
+    camera[t - 1].1 = match qs {
+        Some(qs) => qs.pdf(ctx, qs_minus, pt),
+        None => pt.pdf_light_origin(ctx, pt_minus.expect("s = 0 implies t > 1")),
+    };
+    if let Some(pt_minus) = pt_minus {
+        camera[t - 2].1 = match qs {
+            Some(qs) => pt.pdf(ctx, Some(qs), pt_minus),
+            None => pt.pdf_light(ctx, pt_minus),
+        };
+    }
+    if let Some(qs) = qs {
+        light[s - 1].1 = pt.pdf(ctx, pt_minus, qs);
+        if let Some(qs_minus) = qs_minus {
+            light[s - 2].1 = qs.pdf(ctx, Some(pt), qs_minus);
+        }
+    }
+
+    // Camera paths never hit delta lights, so the light vertex can only be
+    // sampled from the light
+    let light_vertex = if s == 1 { sampled } else { light_path.first() };
+    let delta_light = light_vertex
+        .and_then(|v| v.light.as_ref())
+        .is_some_and(|light| light.is_delta());
+
+    let mut sum_ri = 0.;
+    let mut ri = 1.;
+    for i in (1..t).rev() {
+        ri *= remap0(camera[i].1) / remap0(camera[i].0);
+        if !camera[i].2 && !camera[i - 1].2 {
+            sum_ri += ri;
+        }
+    }
+
+    ri = 1.;
+    for i in (0..s).rev() {
+        ri *= remap0(light[i].1) / remap0(light[i].0);
+        let delta_prev = if i > 0 { light[i - 1].2 } else { delta_light };
+        if !light[i].2 && !delta_prev {
+            sum_ri += ri;
+        }
+    }
+
+    1. / (1. + sum_ri)
+}
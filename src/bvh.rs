@@ -0,0 +1,147 @@
+//! Bounding volume hierarchy, which finds the closest hit by testing a ray
+//! against only the objects whose boxes it passes through.
+
+use crate::hittable::{Aabb, HitRecord, Hittable, HittableList};
+use crate::stats::{self, Counter};
+use crate::types::Ray;
+
+// Objects a leaf holds at most
+const MAX_LEAF_OBJECTS: usize = 2;
+
+enum Node {
+    Leaf {
+        bbox: Aabb,
+        first: usize,
+        count: usize,
+    },
+    // The first child directly follows its parent
+    Interior {
+        bbox: Aabb,
+        second: usize,
+        axis: usize,
+    },
+}
+
+impl Node {
+    fn bbox(&self) -> &Aabb {
+        match self {
+            Node::Leaf { bbox, .. } | Node::Interior { bbox, .. } => bbox,
+        }
+    }
+}
+
+pub struct Bvh {
+    objects: Vec<Box<dyn Hittable + Sync>>,
+    nodes: Vec<Node>,
+}
+
+impl Bvh {
+    /// Builds the hierarchy by splitting the objects in half along the axis
+    /// their centres spread the most along, until few are left
+    pub fn new(list: HittableList) -> Bvh {
+        let mut objects: Vec<(Aabb, Box<dyn Hittable + Sync>)> = list
+            .into_objects()
+            .into_iter()
+            .map(|object| (object.bounding_box(), object))
+            .collect();
+        let mut nodes = Vec::new();
+        if !objects.is_empty() {
+            build(&mut objects, 0, &mut nodes);
+        }
+        Bvh {
+            objects: objects.into_iter().map(|(_, object)| object).collect(),
+            nodes,
+        }
+    }
+}
+
+// Adds the nodes for `objects`, which start at index `offset`, reordering
+// them so every leaf holds a contiguous range
+fn build(objects: &mut [(Aabb, Box<dyn Hittable + Sync>)], offset: usize, nodes: &mut Vec<Node>) {
+    let bbox = objects.iter().fold(Aabb::empty(), |bbox, (object_box, _)| {
+        bbox.union(object_box)
+    });
+    if objects.len() <= MAX_LEAF_OBJECTS {
+        nodes.push(Node::Leaf {
+            bbox,
+            first: offset,
+            count: objects.len(),
+        });
+        return;
+    }
+
+    let centroids = objects
+        .iter()
+        .fold(Aabb::empty(), |bounds, (object_box, _)| {
+            let c = object_box.centroid();
+            bounds.union(&Aabb::new(c, c))
+        });
+    let extent = centroids.max - centroids.min;
+    let axis = (0..3)
+        .max_by(|&a, &b| extent[a].total_cmp(&extent[b]))
+        .unwrap();
+    let mid = objects.len() / 2;
+    objects.select_nth_unstable_by(mid, |(a, _), (b, _)| {
+        a.centroid()[axis].total_cmp(&b.centroid()[axis])
+    });
+
+    let index = nodes.len();
+    nodes.push(Node::Interior {
+        bbox,
+        second: 0,
+        axis,
+    });
+    let (left, right) = objects.split_at_mut(mid);
+    build(left, offset, nodes);
+    let second_index = nodes.len();
+    build(right, offset + mid, nodes);
+    if let Node::Interior { second, .. } = &mut nodes[index] {
+        *second = second_index;
+    }
+}
+
+impl Hittable for Bvh {
+    fn hit(&self, t_range: (f64, f64), ray: &Ray) -> Option<HitRecord> {
+        let mut closest: Option<HitRecord> = None;
+        let mut closest_t = t_range.1;
+        let mut stack = Vec::with_capacity(64);
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.bbox().hit((t_range.0, closest_t), ray) {
+                continue;
+            }
+            match *node {
+                Node::Leaf { first, count, .. } => {
+                    stats::count(Counter::IntersectionTests, count as u64);
+                    for object in &self.objects[first..first + count] {
+                        if let Some(hit_rec) = object.hit((t_range.0, closest_t), ray) {
+                            closest_t = hit_rec.t;
+                            closest = Some(hit_rec);
+                        }
+                    }
+                }
+                Node::Interior { second, axis, .. } => {
+                    // Visit the child nearer along the ray first, so hits in
+                    // it can cull the other
+                    if ray.direction[axis] < 0. {
+                        stack.push(index + 1);
+                        stack.push(second);
+                    } else {
+                        stack.push(second);
+                        stack.push(index + 1);
+                    }
+                }
+            }
+        }
+        closest
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.nodes
+            .first()
+            .map_or(Aabb::empty(), |node| *node.bbox())
+    }
+}
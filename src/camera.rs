@@ -0,0 +1,881 @@
+//! Cameras turn positions on the film into rays leaving the camera. Film
+//! positions (s, t) are in [0, 1)^2, with t = 0 at the bottom of the image.
+
+use std::f64::consts::PI;
+
+use crate::aperture::Aperture;
+use crate::lens::{LensSystem, PupilBounds};
+use crate::sampler::Sampler;
+use crate::stats::{self, Counter};
+use crate::types::{Ray, Vec3};
+
+pub trait Camera: Sync {
+    /// Ray through film position (s, t), or None where the film sees nothing,
+    /// such as outside the image circle of a fisheye lens
+    fn generate_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Option<Ray>;
+
+    /// `generate_ray` and the factor the radiance arriving along the ray is
+    /// scaled by on the film, for cameras where light reaches the film
+    /// through a lens of their own. Rays the lens blocks come with a factor
+    /// of zero rather than as None, since the film still sees through the
+    /// rest of the lens.
+    fn generate_weighted_ray(
+        &self,
+        s: f64,
+        t: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, f64)> {
+        self.generate_ray(s, t, sampler).map(|ray| (ray, 1.))
+    }
+
+    /// `generate_weighted_ray`, counted in the render statistics
+    fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Option<(Ray, f64)> {
+        stats::count(Counter::CameraRays, 1);
+        self.generate_weighted_ray(s, t, sampler)
+    }
+
+    /// Whether light paths can be connected to the camera with `sample_wi`.
+    /// Cameras that cannot only produce images through `get_ray`, so
+    /// bidirectional integrators skip the strategies that need them.
+    fn can_connect(&self) -> bool {
+        false
+    }
+
+    /// Importance emitted along `ray` and the film position it corresponds to
+    fn we(&self, _ray: &Ray) -> Option<(f64, (f64, f64))> {
+        None
+    }
+
+    /// Position and direction densities with which `get_ray` produces `ray`
+    fn pdf_we(&self, _ray: &Ray) -> (f64, f64) {
+        (0., 0.)
+    }
+
+    /// Samples a point on the lens as seen from `p`
+    fn sample_wi(&self, _p: &Vec3, _u: (f64, f64)) -> Option<CameraSample> {
+        None
+    }
+
+    /// Factor the sensor scales the radiance arriving at it by
+    fn exposure(&self) -> f64 {
+        1.
+    }
+
+    /// Whether `exposure` is that of real camera settings, which take
+    /// radiance in cd/m²
+    fn photometric(&self) -> bool {
+        false
+    }
+}
+
+/// A point on the lens sampled towards a reference point, used to connect
+/// light paths to the camera
+pub struct CameraSample {
+    /// Unit direction from the reference point towards the lens
+    pub wi: Vec3,
+    pub lens_point: Vec3,
+    pub importance: f64,
+    /// Solid angle density of `wi`
+    pub pdf: f64,
+    /// Film position (s, t) the connection lands on
+    pub film_pos: (f64, f64),
+}
+
+/// Position and orientation of a camera looking from `origin` along -`w`,
+/// with `u` pointing right and `v` up
+#[derive(Clone, Copy)]
+struct Frame {
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Frame {
+    fn look_at(look_from: Vec3, look_at: Vec3, view_up: Vec3) -> Frame {
+        let w = Vec3::unit_vector(&(look_from - look_at));
+        let u = Vec3::unit_vector(&view_up.cross(&w));
+        let v = w.cross(&u);
+        Frame {
+            origin: look_from,
+            u,
+            v,
+            w,
+        }
+    }
+
+    /// World direction with coordinates (right, up, forward) in the frame
+    fn direction(&self, right: f64, up: f64, forward: f64) -> Vec3 {
+        right * self.u + up * self.v - forward * self.w
+    }
+}
+
+/// Thin lens camera with perspective projection, focused at `focus_dist`
+pub struct PerspectiveCamera {
+    origin: Vec3,
+    lower_left_corner: Vec3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    lens_radius: f64,
+    aperture: Aperture,
+    cats_eye: f64,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    focus_dist: f64,
+    half_width: f64,
+    half_height: f64,
+}
+
+impl PerspectiveCamera {
+    // vert_fov is top to bottom in degrees
+    pub fn new(
+        look_from: Vec3,
+        look_at: Vec3,
+        view_up: Vec3,
+        vert_fov: f64,
+        aspect: f64,
+        aperture: f64,
+        focus_dist: f64,
+    ) -> PerspectiveCamera {
+        let theta = vert_fov * PI / 180.;
+        let half_height = f64::tan(theta / 2.);
+        let half_width = aspect * half_height;
+
+        let Frame { u, v, w, .. } = Frame::look_at(look_from, look_at, view_up);
+
+        PerspectiveCamera {
+            origin: look_from,
+            lower_left_corner: look_from
+                - half_width * focus_dist * u
+                - half_height * focus_dist * v
+                - focus_dist * w,
+            horizontal: 2. * half_width * focus_dist * u,
+            vertical: 2. * half_height * focus_dist * v,
+            lens_radius: aperture / 2.,
+            aperture: Aperture::Circular,
+            cats_eye: 0.,
+            u,
+            v,
+            w,
+            focus_dist,
+            half_width,
+            half_height,
+        }
+    }
+
+    /// Gives the aperture a different shape, with `aperture` still the
+    /// diameter of the circle around it
+    pub fn with_aperture(mut self, aperture: Aperture) -> PerspectiveCamera {
+        self.aperture = aperture;
+        self
+    }
+
+    /// Cuts off the aperture towards the edges of the image, as the barrel of
+    /// a real lens does, so out of focus highlights there look like a cat's
+    /// eye. The aperture is clipped by a second one shifted by `strength`
+    /// times its radius in the image corners; 2 blocks the corners entirely.
+    pub fn with_cats_eye(mut self, strength: f64) -> PerspectiveCamera {
+        self.cats_eye = strength;
+        self
+    }
+
+    /// Moves the camera `offset` to the right to make it one eye of a stereo
+    /// pair, shifting the film so that the views of both eyes line up at
+    /// distance `convergence`. An infinite convergence keeps them parallel.
+    pub fn with_stereo_eye(mut self, offset: f64, convergence: f64) -> PerspectiveCamera {
+        self.origin += offset * self.u;
+        self.lower_left_corner += offset * (1. - self.focus_dist / convergence) * self.u;
+        self
+    }
+
+    // Area of the film when placed at distance 1 from the lens
+    fn film_area(&self) -> f64 {
+        4. * self.half_width * self.half_height
+    }
+
+    /// Density of `generate_ray` choosing `lens_point` per unit area
+    fn lens_pdf(&self, lens_point: &Vec3) -> f64 {
+        if self.lens_radius <= 0. {
+            return 1.;
+        }
+        let offset = *lens_point - self.origin;
+        let local = (
+            Vec3::dot(&offset, &self.u) / self.lens_radius,
+            Vec3::dot(&offset, &self.v) / self.lens_radius,
+        );
+        self.aperture.pdf(local) / (self.lens_radius * self.lens_radius)
+    }
+
+    /// Whether the lens barrel lets light through point (x, y) of the
+    /// aperture to film position (s, t)
+    fn unvignetted(&self, (x, y): (f64, f64), (s, t): (f64, f64)) -> bool {
+        if self.cats_eye == 0. {
+            return true;
+        }
+        let diagonal = f64::hypot(self.half_width, self.half_height);
+        let dx = x + self.cats_eye * (2. * s - 1.) * self.half_width / diagonal;
+        let dy = y + self.cats_eye * (2. * t - 1.) * self.half_height / diagonal;
+        dx * dx + dy * dy <= 1.
+    }
+
+    /// Film position (s, t) that a ray leaving the lens passes through, and the
+    /// cosine between the ray and the viewing direction
+    fn film_position(&self, ray: &Ray) -> Option<((f64, f64), f64)> {
+        let dir = Vec3::unit_vector(&ray.direction);
+        let cos_theta = -Vec3::dot(&dir, &self.w);
+        if cos_theta <= 0. {
+            return None;
+        }
+
+        let focus_point = ray.origin + (self.focus_dist / cos_theta) * dir;
+        let rel = focus_point - self.lower_left_corner;
+        let s = Vec3::dot(&rel, &self.horizontal) / self.horizontal.squared_len();
+        let t = Vec3::dot(&rel, &self.vertical) / self.vertical.squared_len();
+        if !(0. ..1.).contains(&s) || !(0. ..1.).contains(&t) {
+            return None;
+        }
+
+        if self.lens_radius > 0. {
+            let offset = ray.origin - self.origin;
+            let local = (
+                Vec3::dot(&offset, &self.u) / self.lens_radius,
+                Vec3::dot(&offset, &self.v) / self.lens_radius,
+            );
+            if !self.unvignetted(local, (s, t)) {
+                return None;
+            }
+        }
+
+        Some(((s, t), cos_theta))
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn generate_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Option<Ray> {
+        self.generate_weighted_ray(s, t, sampler)
+            .map(|(ray, _)| ray)
+    }
+
+    fn generate_weighted_ray(
+        &self,
+        s: f64,
+        t: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, f64)> {
+        let (x, y) = self.aperture.sample(sampler.get_2d());
+        let offset = self.lens_radius * (x * self.u + y * self.v);
+        let ray = Ray::from(
+            self.origin + offset,
+            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
+        );
+        let weight = if self.unvignetted((x, y), (s, t)) {
+            1.
+        } else {
+            0.
+        };
+        Some((ray, weight))
+    }
+
+    fn can_connect(&self) -> bool {
+        true
+    }
+
+    fn we(&self, ray: &Ray) -> Option<(f64, (f64, f64))> {
+        let (film_pos, cos_theta) = self.film_position(ray)?;
+        let importance = self.lens_pdf(&ray.origin) / (self.film_area() * cos_theta.powi(4));
+        Some((importance, film_pos))
+    }
+
+    fn pdf_we(&self, ray: &Ray) -> (f64, f64) {
+        match self.film_position(ray) {
+            Some((_, cos_theta)) => (
+                self.lens_pdf(&ray.origin),
+                1. / (self.film_area() * cos_theta.powi(3)),
+            ),
+            None => (0., 0.),
+        }
+    }
+
+    fn sample_wi(&self, p: &Vec3, u: (f64, f64)) -> Option<CameraSample> {
+        let (dx, dy) = self.aperture.sample(u);
+        let lens_point = self.origin + self.lens_radius * (dx * self.u + dy * self.v);
+        let to_lens = lens_point - *p;
+        let dist = to_lens.length();
+        let wi = to_lens / dist;
+
+        let (importance, film_pos) = self.we(&Ray::from(lens_point, -wi))?;
+        let pdf = dist * dist * self.lens_pdf(&lens_point) / f64::abs(Vec3::dot(&self.w, &wi));
+        Some(CameraSample {
+            wi,
+            lens_point,
+            importance,
+            pdf,
+            film_pos,
+        })
+    }
+}
+
+/// Thin lens camera set up like a real one. The focal length and sensor
+/// height give the field of view, the f-number the size of the aperture and
+/// so the depth of field, and the shutter speed how long the shutter is open
+/// and so the motion blur. Together with the ISO they set the exposure, for
+/// scenes in metres with radiance in cd/m².
+pub struct PhysicalCamera {
+    look_from: Vec3,
+    look_at: Vec3,
+    view_up: Vec3,
+    aspect: f64,
+    focus_dist: f64,
+    // In millimetres
+    focal_length: f64,
+    sensor_height: f64,
+    f_number: f64,
+    // In seconds, starting at time 0
+    shutter_speed: f64,
+    iso: f64,
+    aperture: Aperture,
+    cats_eye: f64,
+    // Offset and convergence distance of a stereo eye
+    stereo_eye: (f64, f64),
+    lens: PerspectiveCamera,
+}
+
+impl PhysicalCamera {
+    /// A 50 mm lens on a full frame sensor at f/8, 1/125 s and ISO 100
+    pub fn new(
+        look_from: Vec3,
+        look_at: Vec3,
+        view_up: Vec3,
+        aspect: f64,
+        focus_dist: f64,
+    ) -> PhysicalCamera {
+        let camera = PhysicalCamera {
+            look_from,
+            look_at,
+            view_up,
+            aspect,
+            focus_dist,
+            focal_length: 50.,
+            sensor_height: 24.,
+            f_number: 8.,
+            shutter_speed: 1. / 125.,
+            iso: 100.,
+            aperture: Aperture::Circular,
+            cats_eye: 0.,
+            stereo_eye: (0., f64::INFINITY),
+            lens: PerspectiveCamera::new(look_from, look_at, view_up, 1., aspect, 0., focus_dist),
+        };
+        camera.update_lens()
+    }
+
+    pub fn with_focal_length(mut self, millimetres: f64) -> PhysicalCamera {
+        self.focal_length = millimetres;
+        self.update_lens()
+    }
+
+    pub fn with_sensor_height(mut self, millimetres: f64) -> PhysicalCamera {
+        self.sensor_height = millimetres;
+        self.update_lens()
+    }
+
+    pub fn with_f_number(mut self, f_number: f64) -> PhysicalCamera {
+        self.f_number = f_number;
+        self.update_lens()
+    }
+
+    pub fn with_shutter_speed(mut self, seconds: f64) -> PhysicalCamera {
+        self.shutter_speed = seconds;
+        self
+    }
+
+    pub fn with_iso(mut self, iso: f64) -> PhysicalCamera {
+        self.iso = iso;
+        self
+    }
+
+    /// Sets the shutter speed that gives the exposure value at ISO 100 with
+    /// the f-number and ISO set so far, as a light meter would
+    pub fn with_exposure_value(mut self, ev100: f64) -> PhysicalCamera {
+        self.shutter_speed = self.f_number * self.f_number * 100. / (self.iso * f64::exp2(ev100));
+        self
+    }
+
+    /// See `PerspectiveCamera::with_aperture`
+    pub fn with_aperture(mut self, aperture: Aperture) -> PhysicalCamera {
+        self.aperture = aperture;
+        self.update_lens()
+    }
+
+    /// See `PerspectiveCamera::with_cats_eye`
+    pub fn with_cats_eye(mut self, strength: f64) -> PhysicalCamera {
+        self.cats_eye = strength;
+        self.update_lens()
+    }
+
+    /// See `PerspectiveCamera::with_stereo_eye`
+    pub fn with_stereo_eye(mut self, offset: f64, convergence: f64) -> PhysicalCamera {
+        self.stereo_eye = (offset, convergence);
+        self.update_lens()
+    }
+
+    /// Exposure value of the settings at ISO 100
+    pub fn ev100(&self) -> f64 {
+        f64::log2(self.f_number * self.f_number / self.shutter_speed * 100. / self.iso)
+    }
+
+    fn update_lens(mut self) -> PhysicalCamera {
+        let vert_fov = 2. * f64::atan(self.sensor_height / (2. * self.focal_length));
+        let aperture = self.focal_length / 1000. / self.f_number;
+        self.lens = PerspectiveCamera::new(
+            self.look_from,
+            self.look_at,
+            self.view_up,
+            vert_fov.to_degrees(),
+            self.aspect,
+            aperture,
+            self.focus_dist,
+        )
+        .with_aperture(self.aperture.clone())
+        .with_cats_eye(self.cats_eye)
+        .with_stereo_eye(self.stereo_eye.0, self.stereo_eye.1);
+        self
+    }
+}
+
+impl Camera for PhysicalCamera {
+    fn generate_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Option<Ray> {
+        self.generate_weighted_ray(s, t, sampler)
+            .map(|(ray, _)| ray)
+    }
+
+    fn generate_weighted_ray(
+        &self,
+        s: f64,
+        t: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, f64)> {
+        let (ray, weight) = self.lens.generate_weighted_ray(s, t, sampler)?;
+        let time = sampler.get_1d() * self.shutter_speed;
+        Some((Ray::at_time(ray.origin, ray.direction, time), weight))
+    }
+
+    fn can_connect(&self) -> bool {
+        true
+    }
+
+    fn we(&self, ray: &Ray) -> Option<(f64, (f64, f64))> {
+        self.lens.we(ray)
+    }
+
+    fn pdf_we(&self, ray: &Ray) -> (f64, f64) {
+        self.lens.pdf_we(ray)
+    }
+
+    fn sample_wi(&self, p: &Vec3, u: (f64, f64)) -> Option<CameraSample> {
+        self.lens.sample_wi(p, u)
+    }
+
+    // The usual calibration of digital cameras, where 1 / 1.2 of the
+    // exposure value's luminance saturates the sensor
+    fn exposure(&self) -> f64 {
+        1. / (1.2 * f64::exp2(self.ev100()))
+    }
+
+    fn photometric(&self) -> bool {
+        true
+    }
+}
+
+/// Camera that traces rays through a lens system, which gives the distortion,
+/// vignetting and focus breathing of a real lens. The lens is focused by
+/// moving it away from the film. Lengths of the lens and the film are in
+/// millimetres and the scene is taken to be in metres, with `look_from` the
+/// centre of the film. Rays are aimed at the exit pupil, and weighted as in
+/// pbrt by the irradiance they bring to the film.
+pub struct RealisticCamera {
+    frame: Frame,
+    lens: LensSystem,
+    film_distance: f64,
+    film_width: f64,
+    film_height: f64,
+    // Bounds of the exit pupil for rings of the film of equal width, out to
+    // the corners
+    exit_pupils: Vec<PupilBounds>,
+    exposure: f64,
+}
+
+// Rings of the film the bounds of the exit pupil are found for
+const EXIT_PUPIL_RINGS: usize = 16;
+
+impl RealisticCamera {
+    /// Film with the given diagonal, focused `focus_dist` in front of it, or
+    /// None if the lens cannot focus that close
+    pub fn new(
+        look_from: Vec3,
+        look_at: Vec3,
+        view_up: Vec3,
+        lens: LensSystem,
+        film_diagonal: f64,
+        aspect: f64,
+        focus_dist: f64,
+    ) -> Option<RealisticCamera> {
+        let film_distance = lens.film_distance(1000. * focus_dist)?;
+        let film_height = film_diagonal / f64::sqrt(1. + aspect * aspect);
+        let ring_width = film_diagonal / 2. / EXIT_PUPIL_RINGS as f64;
+        let exit_pupils = (0..EXIT_PUPIL_RINGS)
+            .map(|i| {
+                let r0 = i as f64 * ring_width;
+                lens.exit_pupil_bounds(film_distance, r0, r0 + ring_width)
+            })
+            .collect();
+        let mut camera = RealisticCamera {
+            frame: Frame::look_at(look_from, look_at, view_up),
+            lens,
+            film_distance,
+            film_width: aspect * film_height,
+            film_height,
+            exit_pupils,
+            exposure: 1.,
+        };
+        camera.exposure = 1. / camera.centre_irradiance();
+        Some(camera)
+    }
+
+    /// Distance from the film to the rear of the lens
+    pub fn film_distance(&self) -> f64 {
+        self.film_distance
+    }
+
+    // Irradiance that unit radiance in front of the lens brings to the centre
+    // of the film, the mean weight of rays through a grid on the exit pupil
+    fn centre_irradiance(&self) -> f64 {
+        let grid = 32;
+        let film_point = Vec3::new(0., 0., -self.film_distance);
+        let total: f64 = (0..grid * grid)
+            .filter_map(|i| {
+                let u = ((i % grid) as f64 + 0.5) / grid as f64;
+                let v = ((i / grid) as f64 + 0.5) / grid as f64;
+                let (x, y) = self.exit_pupils[0].sample((u, v));
+                let (_, weight) = self.trace(film_point, Vec3::new(x, y, 0.), 0)?;
+                Some(weight)
+            })
+            .sum();
+        total / (grid * grid) as f64
+    }
+
+    // Traces the ray from `film_point` through `rear_point`, sampled on the
+    // exit pupil of `ring`, out of the lens. Its weight is the irradiance the
+    // radiance along it brings to the film, for the density of the point.
+    fn trace(
+        &self,
+        film_point: Vec3,
+        rear_point: Vec3,
+        ring: usize,
+    ) -> Option<((Vec3, Vec3), f64)> {
+        let direction = rear_point - film_point;
+        let ray = self.lens.trace_from_film(film_point, direction)?;
+        let cos_theta = Vec3::unit_vector(&direction).z();
+        let area = self.exit_pupils[ring].area();
+        let weight = cos_theta.powi(4) * area / (self.film_distance * self.film_distance);
+        Some((ray, weight))
+    }
+}
+
+impl Camera for RealisticCamera {
+    fn generate_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Option<Ray> {
+        self.generate_weighted_ray(s, t, sampler)
+            .map(|(ray, _)| ray)
+    }
+
+    fn generate_weighted_ray(
+        &self,
+        s: f64,
+        t: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, f64)> {
+        // The lens turns the image upside down
+        let film_point = Vec3::new(
+            (0.5 - s) * self.film_width,
+            (0.5 - t) * self.film_height,
+            -self.film_distance,
+        );
+        // The bounds are found for points along +x, so are turned to the
+        // point's angle
+        let film_radius = f64::hypot(film_point.x(), film_point.y());
+        let ring_width =
+            f64::hypot(self.film_width, self.film_height) / 2. / EXIT_PUPIL_RINGS as f64;
+        let ring = usize::min((film_radius / ring_width) as usize, EXIT_PUPIL_RINGS - 1);
+        let (x, y) = self.exit_pupils[ring].sample(sampler.get_2d());
+        let (sin, cos) = if film_radius > 0. {
+            (film_point.y() / film_radius, film_point.x() / film_radius)
+        } else {
+            (0., 1.)
+        };
+        let rear_point = Vec3::new(cos * x - sin * y, sin * x + cos * y, 0.);
+        let ((origin, direction), weight) = self.trace(film_point, rear_point, ring)?;
+
+        let origin = (origin + Vec3::new(0., 0., self.film_distance)) / 1000.;
+        let ray = Ray::from(
+            self.frame.origin + self.frame.direction(origin.x(), origin.y(), origin.z()),
+            self.frame
+                .direction(direction.x(), direction.y(), direction.z()),
+        );
+        Some((ray, weight))
+    }
+
+    // Undoes the weight at the centre of the film, so that images are as
+    // bright there as through cameras without a lens
+    fn exposure(&self) -> f64 {
+        self.exposure
+    }
+}
+
+/// Parallel projection, which keeps sizes independent of the distance to the
+/// camera, as in architectural elevations. The film is `height` scene units
+/// tall, centred on the line from `look_from` to `look_at`.
+pub struct OrthographicCamera {
+    frame: Frame,
+    width: f64,
+    height: f64,
+}
+
+impl OrthographicCamera {
+    pub fn new(
+        look_from: Vec3,
+        look_at: Vec3,
+        view_up: Vec3,
+        height: f64,
+        aspect: f64,
+    ) -> OrthographicCamera {
+        OrthographicCamera {
+            frame: Frame::look_at(look_from, look_at, view_up),
+            width: aspect * height,
+            height,
+        }
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn generate_ray(&self, s: f64, t: f64, _sampler: &mut dyn Sampler) -> Option<Ray> {
+        let offset = self
+            .frame
+            .direction((s - 0.5) * self.width, (t - 0.5) * self.height, 0.);
+        Some(Ray::from(
+            self.frame.origin + offset,
+            self.frame.direction(0., 0., 1.),
+        ))
+    }
+}
+
+/// How a fisheye lens maps the angle from the viewing direction to the
+/// distance from the centre of the image circle
+#[derive(Clone, Copy, Debug)]
+pub enum FisheyeMapping {
+    /// Distance proportional to the angle
+    Equidistant,
+    /// Equal areas of the image circle see equal solid angles
+    Equisolid,
+}
+
+/// Fisheye lens whose image circle fills the height of the film and covers
+/// `fov` degrees. The rest of the film stays black.
+pub struct FisheyeCamera {
+    frame: Frame,
+    aspect: f64,
+    max_theta: f64,
+    mapping: FisheyeMapping,
+}
+
+impl FisheyeCamera {
+    pub fn new(
+        look_from: Vec3,
+        look_at: Vec3,
+        view_up: Vec3,
+        fov: f64,
+        aspect: f64,
+        mapping: FisheyeMapping,
+    ) -> FisheyeCamera {
+        FisheyeCamera {
+            frame: Frame::look_at(look_from, look_at, view_up),
+            aspect,
+            max_theta: f64::min(fov, 360.) * PI / 360.,
+            mapping,
+        }
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn generate_ray(&self, s: f64, t: f64, _sampler: &mut dyn Sampler) -> Option<Ray> {
+        let x = (2. * s - 1.) * self.aspect;
+        let y = 2. * t - 1.;
+        let r = f64::sqrt(x * x + y * y);
+        if r > 1. {
+            return None;
+        }
+
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => r * self.max_theta,
+            FisheyeMapping::Equisolid => 2. * f64::asin(r * f64::sin(self.max_theta / 2.)),
+        };
+        let phi = f64::atan2(y, x);
+        let dir = self.frame.direction(
+            f64::sin(theta) * f64::cos(phi),
+            f64::sin(theta) * f64::sin(phi),
+            f64::cos(theta),
+        );
+        Some(Ray::from(self.frame.origin, dir))
+    }
+}
+
+/// 360° panorama with longitude across and latitude up the film, looking
+/// towards `look_at` in the middle. Films should be twice as wide as high.
+pub struct EquirectangularCamera {
+    frame: Frame,
+    stereo_eye: (f64, f64),
+}
+
+impl EquirectangularCamera {
+    pub fn new(look_from: Vec3, look_at: Vec3, view_up: Vec3) -> EquirectangularCamera {
+        EquirectangularCamera {
+            frame: Frame::look_at(look_from, look_at, view_up),
+            stereo_eye: (0., f64::INFINITY),
+        }
+    }
+
+    /// Makes the camera one eye of an omnidirectional stereo pair. Every
+    /// column of the panorama is seen from `offset` to the right of the
+    /// centre when looking in its direction, so the eyes lie on a circle as
+    /// a head turning around would put them, and the views of both eyes line
+    /// up at distance `convergence`.
+    pub fn with_stereo_eye(mut self, offset: f64, convergence: f64) -> EquirectangularCamera {
+        self.stereo_eye = (offset, convergence);
+        self
+    }
+}
+
+impl Camera for EquirectangularCamera {
+    fn generate_ray(&self, s: f64, t: f64, _sampler: &mut dyn Sampler) -> Option<Ray> {
+        let longitude = (s - 0.5) * 2. * PI;
+        let latitude = (t - 0.5) * PI;
+        let dir = self.frame.direction(
+            f64::cos(latitude) * f64::sin(longitude),
+            f64::sin(latitude),
+            f64::cos(latitude) * f64::cos(longitude),
+        );
+        let (offset, convergence) = self.stereo_eye;
+        if offset == 0. {
+            return Some(Ray::from(self.frame.origin, dir));
+        }
+        let eye = offset
+            * self
+                .frame
+                .direction(f64::cos(longitude), 0., -f64::sin(longitude));
+        let dir = if convergence.is_finite() {
+            convergence * dir - eye
+        } else {
+            dir
+        };
+        Some(Ray::from(self.frame.origin + eye, dir))
+    }
+}
+
+/// The six 90° views along the axes of the camera, laid out on the film in
+/// two rows of three: left, front and right on top, then back, down and up.
+/// Films should be 3:2 for square faces.
+pub struct CubemapCamera {
+    frame: Frame,
+}
+
+impl CubemapCamera {
+    pub fn new(look_from: Vec3, look_at: Vec3, view_up: Vec3) -> CubemapCamera {
+        CubemapCamera {
+            frame: Frame::look_at(look_from, look_at, view_up),
+        }
+    }
+}
+
+impl Camera for CubemapCamera {
+    fn generate_ray(&self, s: f64, t: f64, _sampler: &mut dyn Sampler) -> Option<Ray> {
+        let column = usize::min((s * 3.) as usize, 2);
+        let row = usize::min(((1. - t) * 2.) as usize, 1);
+        // Position on the face in [-1, 1]^2
+        let a = (s * 3. - column as f64) * 2. - 1.;
+        let b = (t * 2. - (1 - row) as f64) * 2. - 1.;
+
+        // Each face as (right, up, forward) in the camera's frame
+        let (right, up, forward) = match (row, column) {
+            (0, 0) => (Vec3::new(0, 0, 1), Vec3::new(0, 1, 0), Vec3::new(-1, 0, 0)),
+            (0, 1) => (Vec3::new(1, 0, 0), Vec3::new(0, 1, 0), Vec3::new(0, 0, 1)),
+            (0, _) => (Vec3::new(0, 0, -1), Vec3::new(0, 1, 0), Vec3::new(1, 0, 0)),
+            (_, 0) => (Vec3::new(-1, 0, 0), Vec3::new(0, 1, 0), Vec3::new(0, 0, -1)),
+            (_, 1) => (Vec3::new(1, 0, 0), Vec3::new(0, 0, 1), Vec3::new(0, -1, 0)),
+            (_, _) => (Vec3::new(1, 0, 0), Vec3::new(0, 0, -1), Vec3::new(0, 1, 0)),
+        };
+        let local = a * right + b * up + forward;
+        let dir = self.frame.direction(local.x(), local.y(), local.z());
+        Some(Ray::from(self.frame.origin, dir))
+    }
+}
+
+/// How the views of the two eyes of a stereo pair share the film
+#[derive(Clone, Copy, Debug)]
+pub enum StereoLayout {
+    /// Left eye in the left half
+    SideBySide,
+    /// Left eye in the top half
+    TopBottom,
+}
+
+/// Renders the views of two eyes, such as cameras set up with
+/// `with_stereo_eye`, next to each other on one film. Light paths are not
+/// connected to it, since it has two lenses.
+pub struct StereoCamera {
+    left: Box<dyn Camera>,
+    right: Box<dyn Camera>,
+    layout: StereoLayout,
+}
+
+impl StereoCamera {
+    pub fn new(
+        left: Box<dyn Camera>,
+        right: Box<dyn Camera>,
+        layout: StereoLayout,
+    ) -> StereoCamera {
+        StereoCamera {
+            left,
+            right,
+            layout,
+        }
+    }
+}
+
+impl Camera for StereoCamera {
+    fn generate_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Option<Ray> {
+        self.generate_weighted_ray(s, t, sampler)
+            .map(|(ray, _)| ray)
+    }
+
+    fn generate_weighted_ray(
+        &self,
+        s: f64,
+        t: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, f64)> {
+        let (eye, s, t) = match self.layout {
+            StereoLayout::SideBySide if s < 0.5 => (&self.left, 2. * s, t),
+            StereoLayout::SideBySide => (&self.right, 2. * s - 1., t),
+            StereoLayout::TopBottom if t >= 0.5 => (&self.left, s, 2. * t - 1.),
+            StereoLayout::TopBottom => (&self.right, s, 2. * t),
+        };
+        eye.generate_weighted_ray(s, t, sampler)
+    }
+
+    fn exposure(&self) -> f64 {
+        self.left.exposure()
+    }
+
+    fn photometric(&self) -> bool {
+        self.left.photometric()
+    }
+}
@@ -0,0 +1,69 @@
+//! Checkpoints of progressive renders, so a render that gets stopped can be
+//! continued later. Samplers derive their random numbers from the seed, the
+//! pixel and the sample index, so besides the film only the number of
+//! samples taken needs saving to carry on with the same random numbers.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::film::Film;
+
+const MAGIC: &[u8; 8] = b"RTCKPT02";
+const MAX_SETTINGS_LEN: u64 = 1 << 16;
+
+/// Saves `film` after `samples_taken` samples per pixel to `path`. `settings`
+/// describes everything that affects the image, so that a checkpoint is only
+/// resumed by the same render.
+pub fn save(path: &Path, settings: &str, film: &Film, samples_taken: usize) -> io::Result<()> {
+    // Write a new file and swap it in, so being stopped halfway through
+    // leaves the previous checkpoint intact
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let mut output = BufWriter::new(File::create(&tmp_path)?);
+    output.write_all(MAGIC)?;
+    output.write_all(&(settings.len() as u64).to_le_bytes())?;
+    output.write_all(settings.as_bytes())?;
+    output.write_all(&(samples_taken as u64).to_le_bytes())?;
+    film.write_state(&mut output)?;
+    output.into_inner()?.sync_all()?;
+    fs::rename(&tmp_path, path)
+}
+
+/// Restores `film` from the checkpoint at `path` and returns how many samples
+/// per pixel it holds. Fails if the checkpoint was saved with other settings.
+pub fn load(path: &Path, settings: &str, film: &mut Film) -> io::Result<usize> {
+    let mut input = BufReader::new(File::open(path)?);
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("Not a checkpoint file".to_string()));
+    }
+
+    let settings_len = read_u64(&mut input)?;
+    if settings_len > MAX_SETTINGS_LEN {
+        return Err(invalid_data("Not a checkpoint file".to_string()));
+    }
+    let mut saved_settings = vec![0; settings_len as usize];
+    input.read_exact(&mut saved_settings)?;
+    if saved_settings != settings.as_bytes() {
+        return Err(invalid_data(format!(
+            "Checkpoint was saved with different settings: {}",
+            String::from_utf8_lossy(&saved_settings)
+        )));
+    }
+
+    let samples_taken = read_u64(&mut input)? as usize;
+    film.read_state(&mut input)?;
+    Ok(samples_taken)
+}
+
+fn read_u64(input: &mut dyn Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,313 @@
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+use crate::filter::Filter;
+use crate::types::Vec3;
+use crate::Ppm;
+
+/// Accumulates radiance estimates for every pixel. Camera samples are added
+/// to the pixels around where they were taken, weighted by the reconstruction
+/// filter, while contributions from light paths that were connected to the
+/// camera can land anywhere and are splatted from multiple threads at once.
+/// Splats are summed in fixed point so the result does not depend on the order
+/// threads add them in.
+pub struct Film {
+    width: usize,
+    height: usize,
+    filter: Filter,
+    sums: Vec<Vec3>,
+    weights: Vec<f64>,
+    // Camera samples taken in every pixel
+    counts: Vec<usize>,
+    splats: Vec<[SplatSum; 3]>,
+    splat_scale: Option<f64>,
+    // Scales radiance into the image written by `to_ppm`, or None to pick
+    // the exposure from the image
+    exposure: Option<f64>,
+}
+
+impl Film {
+    /// Film that averages the samples taken in every pixel
+    pub fn new(width: usize, height: usize) -> Film {
+        Film::with_filter(width, height, Filter::default())
+    }
+
+    pub fn with_filter(width: usize, height: usize, filter: Filter) -> Film {
+        let mut splats = Vec::with_capacity(width * height);
+        splats.resize_with(width * height, Default::default);
+        Film {
+            width,
+            height,
+            filter,
+            sums: vec![Vec3::default(); width * height],
+            weights: vec![0.; width * height],
+            counts: vec![0; width * height],
+            splats,
+            splat_scale: None,
+            exposure: Some(1.),
+        }
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    /// Adds `count` samples of pixel (x, y) whose radiance sums to `sum`,
+    /// bypassing the filter
+    pub fn add_samples(&mut self, x: usize, y: usize, sum: Vec3, count: usize) {
+        self.sums[y * self.width + x] += sum;
+        self.weights[y * self.width + x] += count as f64;
+        self.counts[y * self.width + x] += count;
+    }
+
+    /// Empty tile to add the samples taken in `rows` to, covering every
+    /// pixel the filter spreads them over. Tiles let threads add filtered
+    /// samples without sharing pixels.
+    pub fn tile(&self, rows: Range<usize>) -> FilmTile {
+        let radius = self.filter.radius();
+        let first = f64::max(f64::floor(rows.start as f64 - 0.5 - radius) + 1., 0.) as usize;
+        let last = f64::max(f64::floor(rows.end as f64 - 0.5 + radius) + 1., 0.) as usize;
+        let pixel_rows = first..usize::min(last, self.height);
+        let len = pixel_rows.len() * self.width;
+        FilmTile {
+            width: self.width,
+            pixel_rows,
+            filter: self.filter,
+            sums: vec![Vec3::default(); len],
+            weights: vec![0.; len],
+            counts: vec![0; len],
+        }
+    }
+
+    pub fn merge_tile(&mut self, tile: FilmTile) {
+        let offset = tile.pixel_rows.start * self.width;
+        for (i, sum) in tile.sums.into_iter().enumerate() {
+            self.sums[offset + i] += sum;
+            self.weights[offset + i] += tile.weights[i];
+            self.counts[offset + i] += tile.counts[i];
+        }
+    }
+
+    /// Adds radiance to the pixel under film position (s, t) in [0, 1)^2
+    pub fn add_splat(&self, film_pos: (f64, f64), radiance: Vec3) {
+        let x = usize::min((film_pos.0 * self.width as f64) as usize, self.width - 1);
+        let y = usize::min((film_pos.1 * self.height as f64) as usize, self.height - 1);
+        for (channel, splat) in self.splats[y * self.width + x].iter().enumerate() {
+            splat.add(radiance[channel]);
+        }
+    }
+
+    /// Overrides the factor splats are scaled by, for renderers whose splats
+    /// are not one light path per camera sample
+    pub fn set_splat_scale(&mut self, scale: f64) {
+        self.splat_scale = Some(scale);
+    }
+
+    /// Writes everything accumulated so far, so a render can be continued
+    /// with `read_state`
+    pub fn write_state(&self, output: &mut dyn Write) -> io::Result<()> {
+        write_u64(output, self.width as u64)?;
+        write_u64(output, self.height as u64)?;
+        for idx in 0..self.width * self.height {
+            for channel in 0..3 {
+                write_u64(output, self.sums[idx][channel].to_bits())?;
+            }
+            write_u64(output, self.weights[idx].to_bits())?;
+            write_u64(output, self.counts[idx] as u64)?;
+            for splat in self.splats[idx].iter() {
+                write_u64(output, splat.high.load(Ordering::Relaxed) as u64)?;
+                write_u64(output, splat.low.load(Ordering::Relaxed))?;
+            }
+        }
+        match self.splat_scale {
+            Some(scale) => write_u64(output, scale.to_bits()),
+            None => write_u64(output, f64::NAN.to_bits()),
+        }
+    }
+
+    /// Replaces what has been accumulated with what `write_state` wrote for
+    /// a film of the same size
+    pub fn read_state(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        if read_u64(input)? != self.width as u64 || read_u64(input)? != self.height as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Film size does not match",
+            ));
+        }
+        for idx in 0..self.width * self.height {
+            for channel in 0..3 {
+                self.sums[idx][channel] = f64::from_bits(read_u64(input)?);
+            }
+            self.weights[idx] = f64::from_bits(read_u64(input)?);
+            self.counts[idx] = read_u64(input)? as usize;
+            for splat in self.splats[idx].iter() {
+                splat.high.store(read_u64(input)? as i64, Ordering::Relaxed);
+                splat.low.store(read_u64(input)?, Ordering::Relaxed);
+            }
+        }
+        let scale = f64::from_bits(read_u64(input)?);
+        self.splat_scale = if scale.is_nan() { None } else { Some(scale) };
+        Ok(())
+    }
+
+    /// Sets the factor radiance is scaled by in the image, such as the
+    /// exposure of the camera
+    pub fn set_exposure(&mut self, exposure: f64) {
+        self.exposure = Some(exposure);
+    }
+
+    /// Scales radiance in the image by `auto_exposure` of what has been
+    /// rendered so far
+    pub fn set_auto_exposure(&mut self) {
+        self.exposure = None;
+    }
+
+    /// Exposure that brings the log-average luminance of the current
+    /// estimate to middle grey
+    pub fn auto_exposure(&self) -> f64 {
+        let scale = self.splat_scale();
+        let num_pixels = self.width * self.height;
+        let log_sum: f64 = (0..num_pixels)
+            .map(|idx| f64::ln(1e-4 + f64::max(self.estimate(idx, scale).luminance(), 0.)))
+            .sum();
+        MIDDLE_GREY / f64::exp(log_sum / num_pixels as f64)
+    }
+
+    /// Current estimate of the linear radiance of pixel (x, y)
+    pub fn pixel(&self, x: usize, y: usize) -> Vec3 {
+        self.estimate(y * self.width + x, self.splat_scale())
+    }
+
+    /// Gamma corrects the current estimate into an image
+    pub fn to_ppm(&self) -> Ppm {
+        let mut ppm = Ppm::from(self.width, self.height);
+        let scale = self.splat_scale();
+        let exposure = self.exposure.unwrap_or_else(|| self.auto_exposure());
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let radiance = self.estimate(y * self.width + x, scale);
+                ppm.set_pixel(x, y, to_display(exposure * radiance));
+            }
+        }
+
+        ppm
+    }
+
+    // Unless overridden, every camera sample traced one light path, and light
+    // paths are spread over the whole film
+    fn splat_scale(&self) -> f64 {
+        if let Some(scale) = self.splat_scale {
+            return scale;
+        }
+        let total: usize = self.counts.iter().sum();
+        (self.width * self.height) as f64 / usize::max(total, 1) as f64
+    }
+
+    fn estimate(&self, idx: usize, splat_scale: f64) -> Vec3 {
+        let mut color = Vec3::default();
+        if self.weights[idx] != 0. {
+            color = self.sums[idx] / self.weights[idx];
+        }
+
+        let splat = &self.splats[idx];
+        color + splat_scale * Vec3::new(splat[0].get(), splat[1].get(), splat[2].get())
+    }
+}
+
+/// Filtered samples for a band of pixel rows of a film
+pub struct FilmTile {
+    width: usize,
+    pixel_rows: Range<usize>,
+    filter: Filter,
+    sums: Vec<Vec3>,
+    weights: Vec<f64>,
+    counts: Vec<usize>,
+}
+
+impl FilmTile {
+    /// Adds a sample taken at `pos`, in pixels from the film's corner
+    pub fn add_sample(&mut self, pos: (f64, f64), radiance: Vec3) {
+        let radius = self.filter.radius();
+        // Pixels whose centre c is within pos - radius < c <= pos + radius
+        let range = |p: f64, end: usize| {
+            let first = f64::max(f64::floor(p - 0.5 - radius) + 1., 0.) as usize;
+            let last = f64::max(f64::floor(p - 0.5 + radius) + 1., 0.) as usize;
+            first..usize::min(last, end)
+        };
+        let ys = range(pos.1, self.pixel_rows.end);
+        for y in usize::max(ys.start, self.pixel_rows.start)..ys.end {
+            for x in range(pos.0, self.width) {
+                let weight = self
+                    .filter
+                    .eval(x as f64 + 0.5 - pos.0, y as f64 + 0.5 - pos.1);
+                let idx = (y - self.pixel_rows.start) * self.width + x;
+                self.sums[idx] += weight * radiance;
+                self.weights[idx] += weight;
+            }
+        }
+
+        let x = usize::min(pos.0 as usize, self.width - 1);
+        let y = pos.1 as usize;
+        if self.pixel_rows.contains(&y) {
+            self.counts[(y - self.pixel_rows.start) * self.width + x] += 1;
+        }
+    }
+}
+
+fn write_u64(output: &mut dyn Write, value: u64) -> io::Result<()> {
+    output.write_all(&value.to_le_bytes())
+}
+
+fn read_u64(input: &mut dyn Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Gamma 2 correction, scaled to 0-255 and clamped
+fn to_display(col: Vec3) -> Vec3 {
+    let channel = |c: f64| f64::min(f64::sqrt(f64::max(c, 0.)) * 255.99, 255.);
+    Vec3::from((channel(col.x()), channel(col.y()), channel(col.z())))
+}
+
+const MIDDLE_GREY: f64 = 0.18;
+
+// Splats keep 64 fractional bits, which leaves room for sums up to 2^63
+const FIXED_POINT_SCALE: f64 = 18446744073709551616.0;
+
+/// Sum of splats in 128 bit fixed point, split into a high word and a low
+/// word that carries into it. Integer addition gives the same sum in any
+/// order as long as the sum stays within ±2^63, beyond which it
+/// saturates instead of wrapping and the order matters again. The words are
+/// separate atomics, so `get` may miss a carry while splats are still being
+/// added, and is only exact once they all have been.
+#[derive(Default)]
+struct SplatSum {
+    high: AtomicI64,
+    low: AtomicU64,
+}
+
+impl SplatSum {
+    fn add(&self, value: f64) {
+        // Saturates for values too large to represent
+        let fixed = f64::round(value * FIXED_POINT_SCALE) as i128;
+        let (high, low) = ((fixed >> 64) as i64, fixed as u64);
+        let old = self.low.fetch_add(low, Ordering::Relaxed);
+        let carry = old.checked_add(low).is_none() as i64;
+        let _ = self
+            .high
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sum| {
+                Some(sum.saturating_add(high).saturating_add(carry))
+            });
+    }
+
+    fn get(&self) -> f64 {
+        self.high.load(Ordering::Relaxed) as f64
+            + self.low.load(Ordering::Relaxed) as f64 / FIXED_POINT_SCALE
+    }
+}
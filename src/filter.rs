@@ -0,0 +1,112 @@
+//! Pixel reconstruction filters. Every camera sample contributes to all pixels
+//! whose centre lies within the filter radius, weighted by the filter at the
+//! offset from the sample to the pixel centre.
+
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Filter {
+    /// Equal weight for every sample within the radius. With radius 0.5
+    /// every sample only counts towards the pixel it was taken in.
+    Box { radius: f64 },
+    /// Weight falling off linearly to zero at the radius
+    Tent { radius: f64 },
+    /// Gaussian with standard deviation `sigma`, shifted down to reach zero
+    /// at the radius
+    Gaussian { radius: f64, sigma: f64 },
+    /// Mitchell–Netravali cubic, with slightly negative lobes that sharpen
+    /// edges. B = C = 1/3 is the recommended choice.
+    Mitchell { radius: f64, b: f64, c: f64 },
+    /// Sinc windowed by a wider sinc with `tau` lobes
+    Lanczos { radius: f64, tau: f64 },
+}
+
+impl Filter {
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius, .. } => radius,
+        }
+    }
+
+    /// The same filter stretched or squeezed to `radius`
+    pub fn with_radius(self, radius: f64) -> Filter {
+        match self {
+            Filter::Box { .. } => Filter::Box { radius },
+            Filter::Tent { .. } => Filter::Tent { radius },
+            Filter::Gaussian { sigma, .. } => Filter::Gaussian { radius, sigma },
+            Filter::Mitchell { b, c, .. } => Filter::Mitchell { radius, b, c },
+            Filter::Lanczos { tau, .. } => Filter::Lanczos { radius, tau },
+        }
+    }
+
+    /// Weight of a sample at offset (dx, dy) in pixels from a pixel centre
+    pub fn eval(&self, dx: f64, dy: f64) -> f64 {
+        let radius = self.radius();
+        if dx.abs() > radius || dy.abs() > radius {
+            return 0.;
+        }
+
+        match *self {
+            Filter::Box { .. } => 1.,
+            Filter::Tent { radius } => (radius - dx.abs()) * (radius - dy.abs()),
+            Filter::Gaussian { radius, sigma } => {
+                let gaussian = |x: f64| {
+                    f64::max(
+                        0.,
+                        f64::exp(-x * x / (2. * sigma * sigma))
+                            - f64::exp(-radius * radius / (2. * sigma * sigma)),
+                    )
+                };
+                gaussian(dx) * gaussian(dy)
+            }
+            Filter::Mitchell { radius, b, c } => {
+                mitchell(2. * dx / radius, b, c) * mitchell(2. * dy / radius, b, c)
+            }
+            Filter::Lanczos { radius, tau } => {
+                windowed_sinc(dx, radius, tau) * windowed_sinc(dy, radius, tau)
+            }
+        }
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::Box { radius: 0.5 }
+    }
+}
+
+// Mitchell–Netravali cubic over [-2, 2]
+fn mitchell(x: f64, b: f64, c: f64) -> f64 {
+    let x = x.abs();
+    if x < 1. {
+        ((12. - 9. * b - 6. * c) * x * x * x + (-18. + 12. * b + 6. * c) * x * x + (6. - 2. * b))
+            / 6.
+    } else if x < 2. {
+        ((-b - 6. * c) * x * x * x
+            + (6. * b + 30. * c) * x * x
+            + (-12. * b - 48. * c) * x
+            + (8. * b + 24. * c))
+            / 6.
+    } else {
+        0.
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        1.
+    } else {
+        f64::sin(PI * x) / (PI * x)
+    }
+}
+
+fn windowed_sinc(x: f64, radius: f64, tau: f64) -> f64 {
+    if x.abs() > radius {
+        return 0.;
+    }
+    sinc(x) * sinc(x / tau)
+}
@@ -0,0 +1,257 @@
+//! Radiance RGBE (.hdr) images, which hold linear radiance with a shared
+//! exponent per pixel. Environment maps come in this format.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::types::Vec3;
+
+// Scanlines of these widths can be run length encoded
+const MIN_RLE_WIDTH: usize = 8;
+const MAX_RLE_WIDTH: usize = 0x7fff;
+// Larger images are taken to be corrupt rather than allocated
+const MAX_PIXELS: usize = 1 << 28;
+
+#[derive(Clone)]
+pub struct HdrImage {
+    width: usize,
+    height: usize,
+    // Row by row from the top
+    pixels: Vec<Vec3>,
+}
+
+impl HdrImage {
+    /// Black image
+    pub fn new(width: usize, height: usize) -> HdrImage {
+        HdrImage {
+            width,
+            height,
+            pixels: vec![Vec3::default(); width * height],
+        }
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    /// Pixel in column `x` and row `y`, counting rows from the bottom as
+    /// `Ppm` does
+    pub fn get_pixel(&self, x: usize, y: usize) -> Vec3 {
+        self.pixels[(self.height - 1 - y) * self.width + x]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, pixel: Vec3) {
+        self.pixels[(self.height - 1 - y) * self.width + x] = pixel;
+    }
+
+    pub fn load(path: &Path) -> io::Result<HdrImage> {
+        HdrImage::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Reads an image with flat or run length encoded scanlines
+    pub fn read(input: &mut dyn Read) -> io::Result<HdrImage> {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        let mut pos = 0;
+
+        let magic = next_line(&data, &mut pos)?;
+        if !magic.starts_with("#?") {
+            return Err(invalid_data("not a Radiance HDR image".to_string()));
+        }
+        loop {
+            let line = next_line(&data, &mut pos)?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(invalid_data(format!("unsupported pixel format {format}")));
+                }
+            }
+        }
+        let resolution = next_line(&data, &mut pos)?;
+        let (height, width, bottom_up) = match resolution.split_whitespace().collect::<Vec<_>>()[..]
+        {
+            [y, height, "+X", width] if y == "-Y" || y == "+Y" => {
+                (parse_dimension(height)?, parse_dimension(width)?, y == "+Y")
+            }
+            _ => {
+                return Err(invalid_data(format!(
+                    "unsupported image orientation {resolution}"
+                )))
+            }
+        };
+
+        // Run length encoded scanlines take at least two bytes per run of a
+        // component, flat ones four bytes per pixel
+        let min_scanline = if (MIN_RLE_WIDTH..=MAX_RLE_WIDTH).contains(&width) {
+            4 + 8 * width.div_ceil(127)
+        } else {
+            4 * width
+        };
+        let fits = width
+            .checked_mul(height)
+            .is_some_and(|pixels| pixels > 0 && pixels <= MAX_PIXELS)
+            && height * min_scanline <= data.len() - pos;
+        if !fits {
+            return Err(invalid_data(format!("invalid image size {width}x{height}")));
+        }
+
+        let mut image = HdrImage::new(width, height);
+        let mut scanline = vec![[0; 4]; width];
+        for row in 0..height {
+            read_scanline(&data, &mut pos, &mut scanline)?;
+            let row = if bottom_up { height - 1 - row } else { row };
+            for (x, rgbe) in scanline.iter().enumerate() {
+                image.pixels[row * width + x] = from_rgbe(*rgbe);
+            }
+        }
+        Ok(image)
+    }
+
+    /// Writes the image top to bottom, run length encoding the scanlines
+    /// when their width allows it
+    pub fn write(&self, output: &mut dyn Write) -> io::Result<()> {
+        let mut writer = BufWriter::new(output);
+        writer.write_all(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n")?;
+        writer.write_all(format!("-Y {} +X {}\n", self.height, self.width).as_bytes())?;
+
+        let rle = (MIN_RLE_WIDTH..=MAX_RLE_WIDTH).contains(&self.width);
+        for row in self.pixels.chunks(self.width.max(1)) {
+            let scanline: Vec<[u8; 4]> = row.iter().map(to_rgbe).collect();
+            if !rle {
+                for rgbe in &scanline {
+                    writer.write_all(rgbe)?;
+                }
+                continue;
+            }
+            writer.write_all(&[2, 2, (self.width >> 8) as u8, self.width as u8])?;
+            // Every component separately, in runs of literal values, which
+            // keeps the scanline unambiguous for any reader
+            for component in 0..4 {
+                let values: Vec<u8> = scanline.iter().map(|rgbe| rgbe[component]).collect();
+                for run in values.chunks(128) {
+                    writer.write_all(&[run.len() as u8])?;
+                    writer.write_all(run)?;
+                }
+            }
+        }
+        writer.flush()
+    }
+}
+
+fn read_scanline(data: &[u8], pos: &mut usize, scanline: &mut [[u8; 4]]) -> io::Result<()> {
+    let width = scanline.len();
+    let header = data.get(*pos..*pos + 4);
+    let rle = match header {
+        Some(&[2, 2, hi, lo]) => {
+            (MIN_RLE_WIDTH..=MAX_RLE_WIDTH).contains(&width)
+                && hi & 0x80 == 0
+                && (hi as usize) << 8 | lo as usize == width
+        }
+        _ => false,
+    };
+    if !rle {
+        for rgbe in scanline.iter_mut() {
+            *rgbe = next_bytes(data, pos, 4)?.try_into().unwrap();
+        }
+        return Ok(());
+    }
+
+    *pos += 4;
+    for component in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = next_bytes(data, pos, 1)?[0] as usize;
+            let (run, repeated) = if count > 128 {
+                (count - 128, true)
+            } else {
+                (count, false)
+            };
+            if run == 0 || x + run > width {
+                return Err(invalid_data("bad run length in scanline".to_string()));
+            }
+            if repeated {
+                let value = next_bytes(data, pos, 1)?[0];
+                for rgbe in &mut scanline[x..x + run] {
+                    rgbe[component] = value;
+                }
+            } else {
+                let values = next_bytes(data, pos, run)?;
+                for (rgbe, &value) in scanline[x..x + run].iter_mut().zip(values) {
+                    rgbe[component] = value;
+                }
+            }
+            x += run;
+        }
+    }
+    Ok(())
+}
+
+fn from_rgbe([r, g, b, e]: [u8; 4]) -> Vec3 {
+    if e == 0 {
+        return Vec3::default();
+    }
+    let scale = f64::powi(2., e as i32 - (128 + 8));
+    Vec3::from((r as f64 * scale, g as f64 * scale, b as f64 * scale))
+}
+
+fn to_rgbe(color: &Vec3) -> [u8; 4] {
+    let max = f64::max(color.r(), f64::max(color.g(), color.b()));
+    if max < 1e-32 {
+        return [0; 4];
+    }
+    // Write the largest component as a mantissa in [128, 256) and a power
+    // of two
+    let mut exponent = max.log2().floor() as i32 + 1;
+    if max / f64::powi(2., exponent) >= 1. {
+        exponent += 1;
+    } else if max / f64::powi(2., exponent) < 0.5 {
+        exponent -= 1;
+    }
+    let exponent = exponent.clamp(-128, 127);
+    let scale = 256. / f64::powi(2., exponent);
+    let component = |value: f64| (value.max(0.) * scale).min(255.) as u8;
+    [
+        component(color.r()),
+        component(color.g()),
+        component(color.b()),
+        (exponent + 128) as u8,
+    ]
+}
+
+fn next_bytes<'a>(data: &'a [u8], pos: &mut usize, count: usize) -> io::Result<&'a [u8]> {
+    let bytes = data
+        .get(*pos..*pos + count)
+        .ok_or_else(|| invalid_data("image data ends early".to_string()))?;
+    *pos += count;
+    Ok(bytes)
+}
+
+fn next_line(data: &[u8], pos: &mut usize) -> io::Result<String> {
+    let rest = data
+        .get(*pos..)
+        .filter(|rest| !rest.is_empty())
+        .ok_or_else(|| invalid_data("image header ends early".to_string()))?;
+    let len = rest
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .unwrap_or(rest.len());
+    *pos += usize::min(len + 1, rest.len());
+    Ok(String::from_utf8_lossy(&rest[..len]).trim().to_string())
+}
+
+fn parse_dimension(token: &str) -> io::Result<usize> {
+    token
+        .parse()
+        .map_err(|_| invalid_data(format!("expected a number, found {token}")))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
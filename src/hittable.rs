@@ -1,25 +1,126 @@
 use std::sync::Arc;
 
-use rand::random;
-
+use crate::light::Light;
 use crate::material::{Lambertian, Material};
+use crate::sampler::Sampler;
+use crate::sampling;
+use crate::spectrum::Wavelengths;
+use crate::stats::{self, Counter};
 use crate::types::{Ray, Vec3};
 
+#[derive(Clone)]
 pub struct HitRecord {
     pub t: f64,
     pub p: Vec3, // hit point
     pub normal: Vec3,
     pub mat: Arc<dyn Material>,
+    /// The light this surface belongs to, if it is an emitter known to the scene
+    pub light: Option<Arc<dyn Light>>,
+    /// Time of the ray that hit the surface
+    pub time: f64,
+    /// Wavelengths of the ray that hit the surface, if it carries any
+    pub wavelength: Option<Wavelengths>,
+}
+
+impl HitRecord {
+    /// Ray leaving the hit point in `direction` at the time of the hit, with
+    /// the wavelengths of the ray that hit
+    pub fn spawn_ray(&self, direction: Vec3) -> Ray {
+        Ray::at_time(self.p, direction, self.time).with_wavelength(self.wavelength)
+    }
+}
+
+/// Axis aligned bounding box
+#[derive(Clone, Copy)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// A box that contains nothing, so that its union with any box is that box
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vec3::from((f64::MAX, f64::MAX, f64::MAX)),
+            max: Vec3::from((f64::MIN, f64::MIN, f64::MIN)),
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vec3::from((
+                f64::min(self.min.x(), other.min.x()),
+                f64::min(self.min.y(), other.min.y()),
+                f64::min(self.min.z(), other.min.z()),
+            )),
+            max: Vec3::from((
+                f64::max(self.max.x(), other.max.x()),
+                f64::max(self.max.y(), other.max.y()),
+                f64::max(self.max.z(), other.max.z()),
+            )),
+        }
+    }
+
+    /// Whether `ray` passes through the box within `t_range`
+    pub fn hit(&self, t_range: (f64, f64), ray: &Ray) -> bool {
+        let (mut t_min, mut t_max) = t_range;
+        for axis in 0..3 {
+            let inv_d = 1. / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inv_d;
+            if inv_d < 0. {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = f64::max(t0, t_min);
+            t_max = f64::min(t1, t_max);
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        0.5 * (self.min + self.max)
+    }
+
+    /// Center and radius of a sphere enclosing the box
+    pub fn bounding_sphere(&self) -> (Vec3, f64) {
+        if self.min.x() > self.max.x() {
+            return (Vec3::default(), 0.0);
+        }
+        let center = 0.5 * (self.min + self.max);
+        (center, (self.max - center).length())
+    }
 }
 
-pub trait Hittable {
+pub trait Hittable: Send + Sync {
     fn hit(&self, t_range: (f64, f64), ray: &Ray) -> Option<HitRecord>;
+    fn bounding_box(&self) -> Aabb;
+}
+
+/// Lets geometry such as a BVH of static objects be shared between scenes
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit(&self, t_range: (f64, f64), ray: &Ray) -> Option<HitRecord> {
+        (**self).hit(t_range, ray)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        (**self).bounding_box()
+    }
 }
 
 pub struct Sphere {
     center: Vec3,
     radius: f64,
     material: Arc<dyn Material + Sync + Send>,
+    light: Option<Arc<dyn Light>>,
+    // Centre the sphere moves to and the time it gets there
+    motion: Option<(Vec3, f64)>,
 }
 
 impl Sphere {
@@ -28,16 +129,41 @@ impl Sphere {
             center,
             radius,
             material,
+            light: None,
+            motion: None,
         }
     }
 
-    pub fn random_in_unit_sphere() -> Vec3 {
-        let mut sample = Vec3::from((random::<f64>(), random::<f64>(), random::<f64>()));
-        while sample.length() > 1.0 {
-            sample = Vec3::from((random::<f64>(), random::<f64>(), random::<f64>()));
+    /// Marks the sphere as the geometry of `light`, so integrators that sample
+    /// lights can tell when a path hits it
+    pub fn with_light(mut self, light: Arc<dyn Light>) -> Sphere {
+        self.light = Some(light);
+        self
+    }
+
+    /// Moves the sphere in a straight line from its centre at time 0 to `end`
+    /// at time `duration`, staying there afterwards
+    pub fn with_motion(mut self, end: Vec3, duration: f64) -> Sphere {
+        self.motion = Some((end, duration));
+        self
+    }
+
+    fn center(&self, time: f64) -> Vec3 {
+        match self.motion {
+            Some((end, duration)) if duration > 0. => {
+                let f = f64::clamp(time / duration, 0., 1.);
+                self.center + f * (end - self.center)
+            }
+            Some((end, _)) if time > 0. => end,
+            _ => self.center,
         }
+    }
 
-        sample
+    /// Uniform point inside the unit sphere. Uses a fixed number of samples
+    /// rather than rejection sampling so samplers can mutate it smoothly.
+    pub fn random_in_unit_sphere(sampler: &mut dyn Sampler) -> Vec3 {
+        let dir = sampling::uniform_sphere(sampler.get_2d());
+        f64::cbrt(sampler.get_1d()) * dir
     }
 }
 
@@ -47,6 +173,8 @@ impl Default for Sphere {
             center: Vec3::default(),
             radius: 1.0,
             material: Arc::new(Lambertian::new(Vec3::from((0.5, 0.5, 0.5)))),
+            light: None,
+            motion: None,
         }
     }
 }
@@ -54,7 +182,8 @@ impl Default for Sphere {
 impl Hittable for Sphere {
     fn hit(&self, t_range: (f64, f64), ray: &Ray) -> Option<HitRecord> {
         // You can remove the 2s and 4s cuz they cancel out
-        let oc = ray.origin - self.center;
+        let center = self.center(ray.time);
+        let oc = ray.origin - center;
         let a = Vec3::dot(&ray.direction, &ray.direction);
         let b = 2.0 * Vec3::dot(&oc, &ray.direction);
         let c = Vec3::dot(&oc, &oc) - self.radius * self.radius;
@@ -66,8 +195,11 @@ impl Hittable for Sphere {
                 return Some(HitRecord {
                     t: temp,
                     p: ray.pos(temp),
-                    normal: (ray.pos(temp) - self.center) / self.radius,
+                    normal: (ray.pos(temp) - center) / self.radius,
                     mat: self.material.clone(),
+                    light: self.light.clone(),
+                    time: ray.time,
+                    wavelength: ray.wavelength,
                 });
             }
 
@@ -76,13 +208,25 @@ impl Hittable for Sphere {
                 return Some(HitRecord {
                     t: temp,
                     p: ray.pos(temp),
-                    normal: ((ray.pos(temp) - self.center) / self.radius),
+                    normal: ((ray.pos(temp) - center) / self.radius),
                     mat: self.material.clone(),
+                    light: self.light.clone(),
+                    time: ray.time,
+                    wavelength: ray.wavelength,
                 });
             }
         }
 
-        return None;
+        None
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let r = Vec3::from((self.radius, self.radius, self.radius));
+        let start = Aabb::new(self.center - r, self.center + r);
+        match self.motion {
+            Some((end, _)) => start.union(&Aabb::new(end - r, end + r)),
+            None => start,
+        }
     }
 }
 
@@ -98,13 +242,32 @@ impl HittableList {
     pub fn add(&mut self, obj: Box<dyn Hittable + Sync>) {
         self.list.push(obj);
     }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn into_objects(self) -> Vec<Box<dyn Hittable + Sync>> {
+        self.list
+    }
+}
+
+impl Default for HittableList {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Hittable for HittableList {
     fn hit(&self, t_range: (f64, f64), ray: &Ray) -> Option<HitRecord> {
+        stats::count(Counter::IntersectionTests, self.list.len() as u64);
         let mut ret = None;
 
-        let mut closest_hit = f64::MAX;
+        let mut closest_hit = t_range.1;
         for obj in &self.list {
             if let Some(hit_rec) = obj.hit((t_range.0, closest_hit), ray) {
                 closest_hit = hit_rec.t;
@@ -114,4 +277,10 @@ impl Hittable for HittableList {
 
         ret
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.list
+            .iter()
+            .fold(Aabb::empty(), |bbox, obj| bbox.union(&obj.bounding_box()))
+    }
 }
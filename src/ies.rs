@@ -0,0 +1,188 @@
+//! Photometric profiles of luminaires in the IES LM-63 format, which give the
+//! intensity a light sends in every direction.
+//!
+//! Angles are in degrees, in the type C system most profiles use: vertical
+//! angles run from 0 straight down to 180 straight up, and horizontal angles
+//! counterclockwise seen from above, from 0 along the length of the
+//! luminaire.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Clone, Debug)]
+pub struct IesProfile {
+    vertical_angles: Vec<f64>,
+    horizontal_angles: Vec<f64>,
+    // Candela for every horizontal angle, each for all vertical angles
+    candela: Vec<Vec<f64>>,
+    max_candela: f64,
+}
+
+impl IesProfile {
+    /// Parses the text of a profile. Tilt data is skipped, so the profile is
+    /// taken to hold for the luminaire in any orientation.
+    pub fn parse(text: &str) -> io::Result<IesProfile> {
+        let mut lines = text.lines();
+        loop {
+            let line = lines
+                .next()
+                .ok_or_else(|| invalid_data("missing TILT line".to_string()))?
+                .trim();
+            if let Some(tilt) = line.strip_prefix("TILT=") {
+                let mut values = lines.flat_map(str::split_whitespace);
+                if tilt.trim() == "INCLUDE" {
+                    // Lamp geometry, then angles and multipliers for every tilt
+                    values.next();
+                    let count = next_number(&mut values)? as usize;
+                    for _ in 0..2 * count {
+                        next_number(&mut values)?;
+                    }
+                }
+                return IesProfile::parse_data(&mut values);
+            }
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<IesProfile> {
+        IesProfile::parse(&fs::read_to_string(path)?)
+    }
+
+    fn parse_data<'a>(values: &mut impl Iterator<Item = &'a str>) -> io::Result<IesProfile> {
+        // Lamps, lumens, candela multiplier, numbers of angles, photometric
+        // type, units and size of the luminaire, then ballast factors and watts
+        let mut header = [0.; 13];
+        for value in header.iter_mut() {
+            *value = next_number(values)?;
+        }
+        let photometric_type = header[5];
+        if photometric_type != 1. {
+            return Err(invalid_data(format!(
+                "unsupported photometric type {photometric_type}, only type C is"
+            )));
+        }
+        let (vertical, horizontal) = (header[3] as usize, header[4] as usize);
+        if vertical == 0 || horizontal == 0 {
+            return Err(invalid_data("profile has no angles".to_string()));
+        }
+
+        let mut read = |count: usize| -> io::Result<Vec<f64>> {
+            (0..count).map(|_| next_number(values)).collect()
+        };
+        let vertical_angles = read(vertical)?;
+        let mut horizontal_angles = read(horizontal)?;
+        let scale = header[2] * header[10] * header[11];
+        let mut candela = (0..horizontal)
+            .map(|_| Ok(read(vertical)?.iter().map(|c| c * scale).collect()))
+            .collect::<io::Result<Vec<Vec<f64>>>>()?;
+        let increasing = |angles: &[f64]| angles.windows(2).all(|pair| pair[0] < pair[1]);
+        if !increasing(&vertical_angles) || !increasing(&horizontal_angles) {
+            return Err(invalid_data("angles must increase".to_string()));
+        }
+        // Close the circle of profiles that go most of the way round, so
+        // the last angles interpolate towards the first
+        let last = horizontal_angles[horizontal - 1];
+        if horizontal_angles[0] == 0. && last > 180. && last < 360. {
+            horizontal_angles.push(360.);
+            candela.push(candela[0].clone());
+        }
+
+        let max_candela = candela.iter().flatten().fold(0., |max: f64, &c| max.max(c));
+        Ok(IesProfile {
+            vertical_angles,
+            horizontal_angles,
+            candela,
+            max_candela,
+        })
+    }
+
+    pub fn max_candela(&self) -> f64 {
+        self.max_candela
+    }
+
+    /// Intensity in candela, interpolated bilinearly between the measured
+    /// angles and zero beyond the vertical ones. Profiles that only cover
+    /// part of the horizontal circle are mirrored as their symmetry implies.
+    pub fn candela(&self, vertical: f64, horizontal: f64) -> f64 {
+        let (first, last) = (
+            self.vertical_angles[0],
+            self.vertical_angles[self.vertical_angles.len() - 1],
+        );
+        if vertical < first || vertical > last {
+            return 0.;
+        }
+        let (h0, h1, s) = match lerp_position(&self.horizontal_angles, self.fold(horizontal)) {
+            Some(position) => position,
+            None => return 0.,
+        };
+        let (v0, v1, t) = lerp_position(&self.vertical_angles, vertical).unwrap_or((0, 0, 0.));
+        let at = |h: usize| (1. - t) * self.candela[h][v0] + t * self.candela[h][v1];
+        (1. - s) * at(h0) + s * at(h1)
+    }
+
+    /// Intensity relative to the brightest direction
+    pub fn relative_intensity(&self, vertical: f64, horizontal: f64) -> f64 {
+        if self.max_candela > 0. {
+            self.candela(vertical, horizontal) / self.max_candela
+        } else {
+            0.
+        }
+    }
+
+    // Brings a horizontal angle into the range the profile covers
+    fn fold(&self, horizontal: f64) -> f64 {
+        let angles = &self.horizontal_angles;
+        let (first, last) = (angles[0], angles[angles.len() - 1]);
+        let h = horizontal.rem_euclid(360.);
+        if angles.len() == 1 {
+            // Symmetric about the vertical axis
+            first
+        } else if (first, last) == (0., 90.) {
+            // Symmetric in each quadrant
+            let h = if h > 180. { 360. - h } else { h };
+            if h > 90. {
+                180. - h
+            } else {
+                h
+            }
+        } else if (first, last) == (0., 180.) && h > 180. {
+            // Symmetric about the plane through 0 and 180 degrees
+            360. - h
+        } else if (first, last) == (90., 270.) && !(90. ..=270.).contains(&h) {
+            // Symmetric about the plane through 90 and 270 degrees
+            (540. - h).rem_euclid(360.)
+        } else {
+            h
+        }
+    }
+}
+
+// Indices of the angles around `x` and how far it lies between them, or None
+// outside the angles
+fn lerp_position(angles: &[f64], x: f64) -> Option<(usize, usize, f64)> {
+    if angles.len() == 1 {
+        return Some((0, 0, 0.));
+    }
+    if x < angles[0] || x > angles[angles.len() - 1] {
+        return None;
+    }
+    let i = usize::min(
+        angles.partition_point(|&a| a <= x).saturating_sub(1),
+        angles.len() - 2,
+    );
+    let t = (x - angles[i]) / (angles[i + 1] - angles[i]);
+    Some((i, i + 1, t))
+}
+
+fn next_number<'a>(values: &mut impl Iterator<Item = &'a str>) -> io::Result<f64> {
+    let value = values
+        .next()
+        .ok_or_else(|| invalid_data("profile ends early".to_string()))?;
+    value
+        .parse()
+        .map_err(|_| invalid_data(format!("expected a number, found {value}")))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
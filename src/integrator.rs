@@ -0,0 +1,207 @@
+use crate::camera::Camera;
+use crate::film::Film;
+use crate::hittable::HitRecord;
+use crate::light::Light;
+use crate::sampler::Sampler;
+use crate::sampling::power_heuristic;
+use crate::scene::Scene;
+use crate::spectrum::{self, Wavelengths};
+use crate::types::{Ray, Vec3};
+
+/// Estimates the radiance arriving at the camera along a ray. Integrators that
+/// also trace paths from the lights add those contributions to `film` directly.
+pub trait Integrator: Sync {
+    fn li(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        camera: &dyn Camera,
+        film: &Film,
+        sampler: &mut dyn Sampler,
+    ) -> Vec3;
+}
+
+/// Unidirectional path tracer that follows scattered rays until they escape
+/// the scene or hit an emitter. At every non-specular hit it also samples a
+/// light, weighting that against finding the same light by scattering with
+/// multiple importance sampling.
+pub struct PathTracer {
+    max_depth: usize,
+    spectral: bool,
+}
+
+impl PathTracer {
+    pub fn new(max_depth: usize) -> PathTracer {
+        PathTracer {
+            max_depth,
+            spectral: false,
+        }
+    }
+
+    /// Traces every path at three wavelengths and converts the result to RGB,
+    /// rather than tracing the three channels of RGB
+    pub fn with_spectral(mut self, spectral: bool) -> PathTracer {
+        self.spectral = spectral;
+        self
+    }
+
+    fn color(&self, ray: &Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Vec3 {
+        let mut l = Vec3::default();
+        let mut beta = Vec3::from((1., 1., 1.));
+        let mut ray = *ray;
+        // Point the ray was scattered from and the density of its direction,
+        // unless it left the camera or a surface where no light was sampled
+        let mut scattered_from = None;
+        for depth in 0..=self.max_depth {
+            let hit_rec = match scene.hit(&ray) {
+                Some(hit_rec) => hit_rec,
+                None => {
+                    l += beta * escaped(scene, &ray, scattered_from);
+                    break;
+                }
+            };
+            // Light absorbed on the way here if that was through the inside of
+            // the material, which scattering also takes out of `beta`
+            let arrived = if Vec3::dot(&ray.direction, &hit_rec.normal) > 0. {
+                let distance = hit_rec.t * ray.direction.length();
+                beta * hit_rec.mat.transmittance(distance, &hit_rec)
+            } else {
+                beta
+            };
+            let emitted = hit_rec.mat.emitted(&ray, &hit_rec);
+            if !emitted.is_zero() {
+                let weight = match &hit_rec.light {
+                    Some(light) => light_weight(scene, light.as_ref(), &ray, scattered_from),
+                    None => 1.,
+                };
+                l += weight * arrived * emitted;
+            }
+            if depth == self.max_depth {
+                break;
+            }
+
+            let wo = -Vec3::unit_vector(&ray.direction);
+            let samples_lights = samples_lights(&hit_rec);
+            if samples_lights {
+                l += arrived * estimate_direct(scene, &hit_rec, &wo, true, sampler);
+            }
+            let (scattered, attenuation) = match hit_rec.mat.scatter(&ray, &hit_rec, sampler) {
+                Some(scattering) => scattering,
+                None => break,
+            };
+            scattered_from = samples_lights.then(|| {
+                let wi = Vec3::unit_vector(&scattered.direction);
+                (hit_rec.p, hit_rec.mat.pdf(&wo, &wi, &hit_rec))
+            });
+            beta *= attenuation;
+            ray = scattered;
+        }
+        l
+    }
+}
+
+impl Default for PathTracer {
+    fn default() -> Self {
+        PathTracer::new(50)
+    }
+}
+
+impl Integrator for PathTracer {
+    fn li(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        _camera: &dyn Camera,
+        _film: &Film,
+        sampler: &mut dyn Sampler,
+    ) -> Vec3 {
+        if !self.spectral {
+            return self.color(ray, scene, sampler);
+        }
+        let wavelengths = Wavelengths::sample_hero(sampler.get_1d());
+        let ray = ray.with_wavelength(Some(wavelengths));
+        wavelengths.to_rgb(&self.color(&ray, scene, sampler))
+    }
+}
+
+// Whether the path tracer samples the lights at `hit_rec`. Dispersive
+// materials pick the wavelength of the light they scatter, so `eval` and
+// `pdf` only match `scatter` once the ray carries a single wavelength.
+fn samples_lights(hit_rec: &HitRecord) -> bool {
+    if hit_rec.mat.is_specular() {
+        return false;
+    }
+    if !hit_rec.mat.disperses() {
+        return true;
+    }
+    match hit_rec.wavelength {
+        Some(Wavelengths::Single(_)) => true,
+        Some(Wavelengths::Hero { secondary, .. }) => !secondary,
+        None => false,
+    }
+}
+
+// Weight of light from `light` reached by `ray`, which `estimate_direct` may
+// also have found by sampling the light from where the ray was scattered
+fn light_weight(
+    scene: &Scene,
+    light: &dyn Light,
+    ray: &Ray,
+    scattered_from: Option<(Vec3, f64)>,
+) -> f64 {
+    match scattered_from {
+        Some((p, pdf)) => {
+            let light_pdf =
+                scene.light_pdf() * light.pdf_li(&p, &Vec3::unit_vector(&ray.direction));
+            power_heuristic(pdf, light_pdf)
+        }
+        None => 1.,
+    }
+}
+
+// Radiance from the infinite lights carried by a ray that leaves the scene,
+// weighted against sampling them
+fn escaped(scene: &Scene, ray: &Ray, scattered_from: Option<(Vec3, f64)>) -> Vec3 {
+    scene
+        .lights()
+        .iter()
+        .filter(|light| light.is_infinite())
+        .map(|light| {
+            light_weight(scene, light.as_ref(), ray, scattered_from)
+                * spectrum::spectral(&light.le(ray), ray.wavelength)
+        })
+        .sum()
+}
+
+/// Estimates the light arriving at a non-specular hit straight from the lights
+/// and scattered towards `wo`, by sampling a point on one light. With `mis` the
+/// sample is weighted by the power heuristic against the material scattering
+/// towards it, for integrators that also count lights their scattered rays hit.
+pub fn estimate_direct(
+    scene: &Scene,
+    hit_rec: &HitRecord,
+    wo: &Vec3,
+    mis: bool,
+    sampler: &mut dyn Sampler,
+) -> Vec3 {
+    let (light, light_pdf) = match scene.choose_light(sampler.get_1d()) {
+        Some(choice) => choice,
+        None => return Vec3::default(),
+    };
+    let ls = match light.sample_li(&hit_rec.p, sampler.get_2d(), scene.bounds()) {
+        Some(ls) if ls.pdf > 0. && !ls.radiance.is_zero() => ls,
+        _ => return Vec3::default(),
+    };
+
+    let f = hit_rec.mat.eval(wo, &ls.wi, hit_rec);
+    if f.is_zero() || !scene.unoccluded(&hit_rec.p, &ls.p, hit_rec.time) {
+        return Vec3::default();
+    }
+    let weight = if mis && !light.is_delta() {
+        power_heuristic(ls.pdf * light_pdf, hit_rec.mat.pdf(wo, &ls.wi, hit_rec))
+    } else {
+        1.
+    };
+    let radiance = spectrum::spectral(&ls.radiance, hit_rec.wavelength);
+    weight * f * radiance * f64::abs(Vec3::dot(&ls.wi, &hit_rec.normal)) / (ls.pdf * light_pdf)
+}
@@ -0,0 +1,344 @@
+//! Lens systems made of spherical elements, described by a prescription like
+//! those in lens design books and patents. Lengths are in millimetres.
+//!
+//! Lens space has the optical axis along +z, pointing out of the lens towards
+//! the scene, with the vertex of the rearmost surface at z = 0 and the film
+//! behind it.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::types::Vec3;
+
+// Points on the film and rays from each, along both axes of the rear plane,
+// traced to find the bounds of the exit pupil
+const PUPIL_FILM_POINTS: usize = 4;
+const PUPIL_GRID: usize = 24;
+
+/// One surface of a lens system and the medium behind it
+#[derive(Clone, Copy, Debug)]
+pub struct LensElement {
+    /// Radius of the spherical surface, positive if its centre lies behind
+    /// it, towards the film. 0 marks the aperture stop, which is flat.
+    pub curvature_radius: f64,
+    /// Distance along the axis to the next surface
+    pub thickness: f64,
+    /// Index of refraction of the medium between this surface and the next
+    pub ior: f64,
+    pub aperture_radius: f64,
+}
+
+impl LensElement {
+    fn is_stop(&self) -> bool {
+        self.curvature_radius == 0.
+    }
+}
+
+/// Rectangle on the plane of the rear vertex that holds the exit pupil, the
+/// points through which light from the film makes it out of the lens
+#[derive(Clone, Copy, Debug)]
+pub struct PupilBounds {
+    pub min: (f64, f64),
+    pub max: (f64, f64),
+}
+
+impl PupilBounds {
+    pub fn area(&self) -> f64 {
+        (self.max.0 - self.min.0) * (self.max.1 - self.min.1)
+    }
+
+    /// Uniformly distributed point inside the bounds
+    pub fn sample(&self, u: (f64, f64)) -> (f64, f64) {
+        (
+            self.min.0 + u.0 * (self.max.0 - self.min.0),
+            self.min.1 + u.1 * (self.max.1 - self.min.1),
+        )
+    }
+}
+
+/// Where a lens system's principal planes and rear focal point lie on the
+/// axis, from a thick lens approximation
+#[derive(Clone, Copy, Debug)]
+struct CardinalPoints {
+    front_principal: f64,
+    rear_focus: f64,
+    rear_principal: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct LensSystem {
+    /// From the front of the lens, facing the scene, to the back
+    elements: Vec<LensElement>,
+    // Position of every surface's vertex on the axis
+    vertices: Vec<f64>,
+}
+
+impl LensSystem {
+    pub fn new(elements: Vec<LensElement>) -> LensSystem {
+        // The thickness of the last element is the distance to the film,
+        // which focusing decides
+        let mut vertices = vec![0.; elements.len()];
+        for i in (0..elements.len().saturating_sub(1)).rev() {
+            vertices[i] = vertices[i + 1] + elements[i].thickness;
+        }
+        LensSystem { elements, vertices }
+    }
+
+    /// Parses a prescription with one surface per line, front to back, as
+    /// its curvature radius, thickness, index of refraction and aperture
+    /// diameter. An index of 0 stands for air. Text after `#` is ignored.
+    pub fn parse(text: &str) -> io::Result<LensSystem> {
+        let mut elements = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "line {}: expected radius, thickness, IOR and aperture",
+                        number + 1
+                    ),
+                )
+            };
+            let values = line
+                .split_whitespace()
+                .map(|value| value.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|_| invalid())?;
+            let [curvature_radius, thickness, ior, aperture] = values[..] else {
+                return Err(invalid());
+            };
+            elements.push(LensElement {
+                curvature_radius,
+                thickness,
+                ior: if ior == 0. { 1. } else { ior },
+                aperture_radius: aperture / 2.,
+            });
+        }
+        if elements.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "lens prescription has no elements",
+            ));
+        }
+        Ok(LensSystem::new(elements))
+    }
+
+    pub fn load(path: &Path) -> io::Result<LensSystem> {
+        LensSystem::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn elements(&self) -> &[LensElement] {
+        &self.elements
+    }
+
+    pub fn rear_aperture_radius(&self) -> f64 {
+        self.elements
+            .last()
+            .map_or(0., |element| element.aperture_radius)
+    }
+
+    /// Focal length of the thick lens approximation, or None if the system
+    /// does not focus parallel light
+    pub fn focal_length(&self) -> Option<f64> {
+        let points = self.cardinal_points()?;
+        Some(points.rear_principal - points.rear_focus)
+    }
+
+    /// Distance from the rear vertex to the film that brings objects
+    /// `distance` in front of the film into focus, or None if the lens cannot
+    /// focus that close
+    pub fn film_distance(&self, distance: f64) -> Option<f64> {
+        let points = self.cardinal_points()?;
+        let f = points.rear_principal - points.rear_focus;
+        // Moving the film to z = -b puts the object at z = distance - b; with
+        // a = b + rear principal as the image distance, the lens equation
+        // becomes a^2 - k a + f k = 0
+        let k = distance - points.front_principal + points.rear_principal;
+        let discriminant = k * k - 4. * f * k;
+        if f <= 0. || discriminant < 0. {
+            return None;
+        }
+        let b = (k - f64::sqrt(discriminant)) / 2. - points.rear_principal;
+        if b > 0. {
+            Some(b)
+        } else {
+            None
+        }
+    }
+
+    /// Bounds of the exit pupil seen from the points on the film, placed
+    /// `film_distance` behind the rear vertex, between `r0` and `r1` from the
+    /// axis along +x. Found by tracing a grid of rays from a few of the points
+    /// to the plane of the rear vertex, and grown by a cell of the grid to
+    /// cover what falls between the rays. The whole grid is returned if no
+    /// ray gets through.
+    pub fn exit_pupil_bounds(&self, film_distance: f64, r0: f64, r1: f64) -> PupilBounds {
+        // The rear surface is curved, so rays through it can cross the plane
+        // of its vertex beyond its aperture
+        let extent = 1.5 * self.rear_aperture_radius();
+        let cell = 2. * extent / PUPIL_GRID as f64;
+        let mut bounds: Option<PupilBounds> = None;
+        for i in 0..PUPIL_FILM_POINTS {
+            let r = r0 + (i as f64 + 0.5) / PUPIL_FILM_POINTS as f64 * (r1 - r0);
+            let film_point = Vec3::new(r, 0., -film_distance);
+            for j in 0..PUPIL_GRID * PUPIL_GRID {
+                let x = -extent + ((j % PUPIL_GRID) as f64 + 0.5) * cell;
+                let y = -extent + ((j / PUPIL_GRID) as f64 + 0.5) * cell;
+                let rear_point = Vec3::new(x, y, 0.);
+                if self
+                    .trace_from_film(film_point, rear_point - film_point)
+                    .is_none()
+                {
+                    continue;
+                }
+                bounds = Some(match bounds {
+                    Some(b) => PupilBounds {
+                        min: (b.min.0.min(x), b.min.1.min(y)),
+                        max: (b.max.0.max(x), b.max.1.max(y)),
+                    },
+                    None => PupilBounds {
+                        min: (x, y),
+                        max: (x, y),
+                    },
+                });
+            }
+        }
+        match bounds {
+            Some(b) => PupilBounds {
+                min: (b.min.0 - cell, b.min.1 - cell),
+                max: (b.max.0 + cell, b.max.1 + cell),
+            },
+            None => PupilBounds {
+                min: (-extent, -extent),
+                max: (extent, extent),
+            },
+        }
+    }
+
+    /// Traces a ray from behind the lens out into the scene, returning it as
+    /// it leaves the front element, or None if the housing or aperture
+    /// stop blocks it
+    pub fn trace_from_film(&self, origin: Vec3, direction: Vec3) -> Option<(Vec3, Vec3)> {
+        let mut ray = (origin, Vec3::unit_vector(&direction));
+        for i in (0..self.elements.len()).rev() {
+            let outside = if i == 0 { 1. } else { self.elements[i - 1].ior };
+            ray = self.refract_at(i, ray, self.elements[i].ior, outside)?;
+        }
+        Some(ray)
+    }
+
+    /// Traces a ray from the scene through the lens, returning it as it
+    /// leaves the rear element
+    pub fn trace_from_scene(&self, origin: Vec3, direction: Vec3) -> Option<(Vec3, Vec3)> {
+        let mut ray = (origin, Vec3::unit_vector(&direction));
+        for i in 0..self.elements.len() {
+            let outside = if i == 0 { 1. } else { self.elements[i - 1].ior };
+            ray = self.refract_at(i, ray, outside, self.elements[i].ior)?;
+        }
+        Some(ray)
+    }
+
+    // Intersects surface i and refracts from index `from` into index `to`
+    fn refract_at(
+        &self,
+        i: usize,
+        (origin, direction): (Vec3, Vec3),
+        from: f64,
+        to: f64,
+    ) -> Option<(Vec3, Vec3)> {
+        let element = &self.elements[i];
+        let vertex = self.vertices[i];
+        let (point, normal) = if element.is_stop() {
+            let t = (vertex - origin.z()) / direction.z();
+            if t.is_nan() || t <= 0. {
+                return None;
+            }
+            (origin + t * direction, Vec3::new(0, 0, 1))
+        } else {
+            let radius = element.curvature_radius;
+            let centre = Vec3::new(0., 0., vertex - radius);
+            let t = intersect_cap(origin, direction, centre, radius)?;
+            let point = origin + t * direction;
+            (point, (point - centre) / radius.abs())
+        };
+        if point.x() * point.x() + point.y() * point.y()
+            > element.aperture_radius * element.aperture_radius
+        {
+            return None;
+        }
+        if element.is_stop() || from == to {
+            return Some((point, direction));
+        }
+        let normal = if Vec3::dot(&normal, &direction) > 0. {
+            -normal
+        } else {
+            normal
+        };
+        Some((point, refract(&direction, &normal, from / to)?))
+    }
+
+    fn cardinal_points(&self) -> Option<CardinalPoints> {
+        // Paraxial rays parallel to the axis, a small fraction of the way out
+        let front = &self.elements[0];
+        let height = 0.001 * front.aperture_radius;
+
+        let start = Vec3::new(height, 0., self.vertices[0] + 1.);
+        let (origin, direction) = self.trace_from_scene(start, Vec3::new(0, 0, -1))?;
+        let (rear_focus, rear_principal) = axis_crossings(origin, direction, height)?;
+
+        let start = Vec3::new(height, 0., -1.);
+        let (origin, direction) = self.trace_from_film(start, Vec3::new(0, 0, 1))?;
+        let (_, front_principal) = axis_crossings(origin, direction, height)?;
+
+        Some(CardinalPoints {
+            front_principal,
+            rear_focus,
+            rear_principal,
+        })
+    }
+}
+
+// Where a ray that entered the lens parallel to the axis at `height` crosses
+// the axis, and where it would have had to bend to get there in one go
+fn axis_crossings(origin: Vec3, direction: Vec3, height: f64) -> Option<(f64, f64)> {
+    if direction.x() == 0. {
+        return None;
+    }
+    let focus = origin.z() - origin.x() / direction.x() * direction.z();
+    let principal = origin.z() + (height - origin.x()) / direction.x() * direction.z();
+    Some((focus, principal))
+}
+
+// Distance to the intersection with the half of a sphere around `centre` that
+// holds the vertex, at z = centre + radius
+fn intersect_cap(origin: Vec3, direction: Vec3, centre: Vec3, radius: f64) -> Option<f64> {
+    let oc = origin - centre;
+    let b = Vec3::dot(&oc, &direction);
+    let c = oc.squared_len() - radius * radius;
+    let discriminant = b * b - c;
+    if discriminant < 0. {
+        return None;
+    }
+    let root = f64::sqrt(discriminant);
+    [-b - root, -b + root].into_iter().find(|&t| {
+        let z = origin.z() + t * direction.z() - centre.z();
+        t > 1e-9 && z * radius > 0.
+    })
+}
+
+// Refracts unit direction `d` through a surface with unit normal `n` facing
+// it, or None on total internal reflection
+fn refract(d: &Vec3, n: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = -Vec3::dot(n, d);
+    let sin2_t = eta * eta * f64::max(0., 1. - cos_i * cos_i);
+    if sin2_t >= 1. {
+        return None;
+    }
+    let cos_t = f64::sqrt(1. - sin2_t);
+    Some(eta * *d + (eta * cos_i - cos_t) * *n)
+}
@@ -1,14 +1,33 @@
+pub mod animation;
+pub mod aperture;
+pub mod bdpt;
+pub mod bvh;
+pub mod camera;
+pub mod checkpoint;
+pub mod film;
+pub mod filter;
+pub mod hdr;
 pub mod hittable;
+pub mod ies;
+pub mod integrator;
+pub mod lens;
+pub mod light;
 pub mod material;
+pub mod microfacet;
+pub mod mlt;
+pub mod photon;
+pub mod render;
+pub mod sampler;
+pub mod sampling;
+pub mod scene;
+pub mod sky;
+pub mod spectrum;
+pub mod stats;
 pub mod types;
 
-use std::{
-    f64::consts::PI,
-    io::{BufWriter, Write},
-};
+use std::io::{self, BufWriter, Read, Write};
 
-use rand::{Rng};
-use types::{Ray, Vec3};
+use types::Vec3;
 
 const PPM_HEADER: &str = "P3\n";
 
@@ -18,6 +37,12 @@ pub struct Ppm {
     pixels: Vec<Vec<Vec3>>,
 }
 
+impl Default for Ppm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Ppm {
     pub fn new() -> Ppm {
         Ppm {
@@ -71,6 +96,10 @@ impl Ppm {
         self.pixels[self.height - 1 - y][x] = pixel;
     }
 
+    pub fn get_pixel(&self, x: usize, y: usize) -> Vec3 {
+        self.pixels[self.height - 1 - y][x]
+    }
+
     pub fn get_height(&self) -> usize {
         self.height
     }
@@ -79,6 +108,72 @@ impl Ppm {
         self.width
     }
 
+    /// Reads a plain or binary PPM or PGM image, with values scaled to
+    /// [0, 255] as `write` expects them
+    pub fn read(input: &mut dyn Read) -> io::Result<Ppm> {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        let mut pos = 0;
+
+        let magic = next_token(&data, &mut pos)?;
+        let (channels, binary) = match magic.as_str() {
+            "P2" => (1, false),
+            "P3" => (3, false),
+            "P5" => (1, true),
+            "P6" => (3, true),
+            _ => return Err(invalid_data(format!("unsupported image format {magic}"))),
+        };
+        let width = parse_token(&data, &mut pos)?;
+        let height = parse_token(&data, &mut pos)?;
+        let max_value = parse_token(&data, &mut pos)?;
+        if max_value == 0 || max_value > 65535 {
+            return Err(invalid_data(format!("invalid maximum value {max_value}")));
+        }
+        // A single whitespace character separates the header from binary data
+        pos += 1;
+
+        // Every value takes at least a byte, so a size the data can not hold
+        // is rejected before anything is allocated for it
+        let bytes_per_value = if max_value > 255 { 2 } else { 1 };
+        let count = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(channels))
+            .filter(|&count| count > 0)
+            .filter(|&count| {
+                count
+                    .checked_mul(bytes_per_value)
+                    .is_some_and(|bytes| bytes <= data.len().saturating_sub(pos))
+            })
+            .ok_or_else(|| invalid_data(format!("invalid image size {width}x{height}")))?;
+
+        let mut values = Vec::with_capacity(count);
+        for _ in 0..count {
+            let value = if binary {
+                let bytes = data
+                    .get(pos..pos + bytes_per_value)
+                    .ok_or_else(|| invalid_data("image data ends early".to_string()))?;
+                pos += bytes_per_value;
+                bytes
+                    .iter()
+                    .fold(0, |value, &byte| value << 8 | byte as usize)
+            } else {
+                parse_token(&data, &mut pos)?
+            };
+            values.push(value as f64 * 255. / max_value as f64);
+        }
+
+        let mut ppm = Ppm::from(width, height);
+        for (idx, pixel) in values.chunks(channels).enumerate() {
+            let color = match *pixel {
+                [grey] => Vec3::from((grey, grey, grey)),
+                [r, g, b] => Vec3::from((r, g, b)),
+                _ => unreachable!(),
+            };
+            ppm.pixels[idx / width][idx % width] = color;
+        }
+        Ok(ppm)
+    }
+
     pub fn write(&self, output: &mut dyn Write) {
         let mut writer = BufWriter::new(output);
 
@@ -104,74 +199,43 @@ impl Ppm {
                         )
                         .as_bytes(),
                     )
-                    .expect(&format!("Could not write pixel {x} {y}"));
+                    .unwrap_or_else(|_| panic!("Could not write pixel {x} {y}"));
             }
         }
     }
 }
 
-fn random_in_unit_disk() -> Vec3 {
-    let mut rng = rand::thread_rng();
-    loop {
-        let p = 2. * Vec3::from((rng.gen(), rng.gen(), 0.)) - Vec3::from((1., 1., 0.));
-        if p.squared_len() < 1.0 {
-            return p;
-        }
-    }
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
 }
 
-#[allow(dead_code)]
-pub struct Camera {
-    origin: Vec3,
-    lower_left_corner: Vec3,
-    horizontal: Vec3,
-    vertical: Vec3,
-    lens_radius: f64,
-    u: Vec3,
-    v: Vec3,
-    w: Vec3,
-}
-
-impl Camera {
-    // vert_fov is top to bottom in degrees
-    pub fn new(
-        look_from: Vec3,
-        look_at: Vec3,
-        view_up: Vec3,
-        vert_fov: f64,
-        aspect: f64,
-        aperture: f64,
-        focus_dist: f64,
-    ) -> Camera {
-        let theta = vert_fov * PI / 180.;
-        let half_height = f64::tan(theta / 2.);
-        let half_width = aspect * half_height;
-
-        let w = Vec3::unit_vector(&(look_from - look_at));
-        let u = Vec3::unit_vector(&view_up.cross(&w));
-        let v = w.cross(&u);
-
-        Camera {
-            origin: look_from,
-            lower_left_corner: look_from
-                - half_width * focus_dist * u
-                - half_height * focus_dist * v
-                - focus_dist * w,
-            horizontal: 2. * half_width * focus_dist * u,
-            vertical: 2. * half_height * focus_dist * v,
-            lens_radius: aperture / 2.,
-            u,
-            v,
-            w,
+// Next whitespace separated token of a PPM header, skipping comments
+fn next_token(data: &[u8], pos: &mut usize) -> io::Result<String> {
+    loop {
+        match data.get(*pos) {
+            Some(b'#') => {
+                while data.get(*pos).is_some_and(|&byte| byte != b'\n') {
+                    *pos += 1;
+                }
+            }
+            Some(byte) if byte.is_ascii_whitespace() => *pos += 1,
+            Some(_) => break,
+            None => return Err(invalid_data("image data ends early".to_string())),
         }
     }
-
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let ray_dir = self.lens_radius * random_in_unit_disk();
-        let offset = self.u * ray_dir.x() + self.v * ray_dir.y();
-        Ray::from(
-            self.origin + offset,
-            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
-        )
+    let start = *pos;
+    while data
+        .get(*pos)
+        .is_some_and(|byte| !byte.is_ascii_whitespace())
+    {
+        *pos += 1;
     }
+    Ok(String::from_utf8_lossy(&data[start..*pos]).into_owned())
+}
+
+fn parse_token(data: &[u8], pos: &mut usize) -> io::Result<usize> {
+    let token = next_token(data, pos)?;
+    token
+        .parse()
+        .map_err(|_| invalid_data(format!("expected a number, found {token}")))
 }
@@ -0,0 +1,672 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::camera::Camera;
+use crate::hdr::HdrImage;
+use crate::hittable::Sphere;
+use crate::ies::IesProfile;
+use crate::material::DiffuseLight;
+use crate::sampling::{self, Distribution2D};
+use crate::sky::GRADIENT_SKY_EV100;
+use crate::types::{Ray, Vec3};
+
+/// Illumination arriving at a reference point from a sampled point on a light
+pub struct LightSample {
+    /// Unit direction from the reference point towards the light
+    pub wi: Vec3,
+    pub p: Vec3,
+    /// Surface normal at `p`, zero if the light has no surface
+    pub normal: Vec3,
+    pub radiance: Vec3,
+    /// Solid angle density of `wi`
+    pub pdf: f64,
+}
+
+/// A ray leaving a light, used to start light subpaths
+pub struct EmissionSample {
+    pub ray: Ray,
+    pub normal: Vec3,
+    pub radiance: Vec3,
+    pub pdf_pos: f64,
+    pub pdf_dir: f64,
+}
+
+/// Lights take their random numbers as explicit `u` arguments so callers
+/// control how they are generated. `world` is the center and radius of a
+/// sphere enclosing the scene, which infinite lights need to place points.
+pub trait Light: Send + Sync {
+    fn sample_li(&self, p: &Vec3, u: (f64, f64), world: (Vec3, f64)) -> Option<LightSample>;
+
+    /// Solid angle density with which `sample_li` picks `wi` from `p`
+    fn pdf_li(&self, p: &Vec3, wi: &Vec3) -> f64;
+
+    fn sample_le(
+        &self,
+        u1: (f64, f64),
+        u2: (f64, f64),
+        world: (Vec3, f64),
+    ) -> Option<EmissionSample>;
+
+    /// Position and direction densities with which `sample_le` produces `ray`
+    /// leaving a point with the given normal
+    fn pdf_le(&self, ray: &Ray, normal: &Vec3, world: (Vec3, f64)) -> (f64, f64);
+
+    /// Radiance carried by a ray that escapes the scene
+    fn le(&self, _ray: &Ray) -> Vec3 {
+        Vec3::default()
+    }
+
+    /// Infinite lights surround the scene and are reached by escaping rays
+    fn is_infinite(&self) -> bool {
+        false
+    }
+
+    /// Delta lights shine from a single point or in a single direction, so
+    /// rays never hit them and only sampling them finds their light. Their
+    /// sampled densities are 1 and the densities they report for given
+    /// positions or directions are 0.
+    fn is_delta(&self) -> bool {
+        false
+    }
+}
+
+/// Spherical area light that emits uniformly from its outside
+pub struct SphereLight {
+    center: Vec3,
+    radius: f64,
+    emit: Vec3,
+}
+
+impl SphereLight {
+    pub fn new(center: Vec3, radius: f64, emit: Vec3) -> SphereLight {
+        SphereLight {
+            center,
+            radius,
+            emit,
+        }
+    }
+
+    /// Geometry for the light, to be added to the world
+    pub fn sphere(self: &Arc<Self>) -> Sphere {
+        Sphere::new(
+            self.center,
+            self.radius,
+            Arc::new(DiffuseLight::new(self.emit)),
+        )
+        .with_light(self.clone())
+    }
+
+    fn area(&self) -> f64 {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    fn cos_theta_max(&self, p: &Vec3) -> Option<f64> {
+        let dist_squared = (self.center - *p).squared_len();
+        if dist_squared <= self.radius * self.radius {
+            return None;
+        }
+        let sin_squared = self.radius * self.radius / dist_squared;
+        Some(f64::sqrt(f64::max(0.0, 1.0 - sin_squared)))
+    }
+}
+
+impl Light for SphereLight {
+    // Samples the cone of directions subtended by the sphere
+    fn sample_li(&self, p: &Vec3, u: (f64, f64), _world: (Vec3, f64)) -> Option<LightSample> {
+        let cos_theta_max = self.cos_theta_max(p)?;
+        let to_center = Vec3::unit_vector(&(self.center - *p));
+        let wi = sampling::to_world(&sampling::uniform_cone(u, cos_theta_max), &to_center);
+
+        let oc = *p - self.center;
+        let b = Vec3::dot(&oc, &wi);
+        let c = Vec3::dot(&oc, &oc) - self.radius * self.radius;
+        let t = -b - f64::sqrt(f64::max(0.0, b * b - c));
+        let light_p = *p + t * wi;
+
+        Some(LightSample {
+            wi,
+            p: light_p,
+            normal: Vec3::unit_vector(&(light_p - self.center)),
+            radiance: self.emit,
+            pdf: sampling::uniform_cone_pdf(cos_theta_max),
+        })
+    }
+
+    fn pdf_li(&self, p: &Vec3, wi: &Vec3) -> f64 {
+        match self.cos_theta_max(p) {
+            Some(cos_theta_max) => {
+                let to_center = Vec3::unit_vector(&(self.center - *p));
+                if Vec3::dot(&to_center, wi) >= cos_theta_max * wi.length() {
+                    sampling::uniform_cone_pdf(cos_theta_max)
+                } else {
+                    0.0
+                }
+            }
+            None => 0.0,
+        }
+    }
+
+    fn sample_le(
+        &self,
+        u1: (f64, f64),
+        u2: (f64, f64),
+        _world: (Vec3, f64),
+    ) -> Option<EmissionSample> {
+        let normal = sampling::uniform_sphere(u1);
+        let local = sampling::cosine_hemisphere(u2);
+        Some(EmissionSample {
+            ray: Ray::from(
+                self.center + self.radius * normal,
+                sampling::to_world(&local, &normal),
+            ),
+            normal,
+            radiance: self.emit,
+            pdf_pos: 1.0 / self.area(),
+            pdf_dir: local.z() / PI,
+        })
+    }
+
+    fn pdf_le(&self, ray: &Ray, normal: &Vec3, _world: (Vec3, f64)) -> (f64, f64) {
+        let cosine = Vec3::dot(normal, &Vec3::unit_vector(&ray.direction));
+        (1.0 / self.area(), f64::max(0.0, cosine) / PI)
+    }
+}
+
+/// Light shining equally in all directions from a point, falling off with the
+/// square of the distance
+pub struct PointLight {
+    position: Vec3,
+    intensity: Vec3,
+    profile: Option<Arc<IesProfile>>,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, intensity: Vec3) -> PointLight {
+        PointLight {
+            position,
+            intensity,
+            profile: None,
+        }
+    }
+
+    /// Shapes the light by a photometric profile hanging straight down, with
+    /// horizontal angles starting along +x. `intensity` becomes that of the
+    /// brightest direction.
+    pub fn with_profile(mut self, profile: Arc<IesProfile>) -> PointLight {
+        self.profile = Some(profile);
+        self
+    }
+
+    // Intensity sent in unit direction `w`
+    fn intensity(&self, w: &Vec3) -> Vec3 {
+        let (nadir, zero) = (Vec3::from((0.0, -1.0, 0.0)), Vec3::from((1.0, 0.0, 0.0)));
+        profile_scale(&self.profile, w, &nadir, &zero) * self.intensity
+    }
+}
+
+impl Light for PointLight {
+    fn sample_li(&self, p: &Vec3, _u: (f64, f64), _world: (Vec3, f64)) -> Option<LightSample> {
+        let to_light = self.position - *p;
+        let dist_squared = to_light.squared_len();
+        if dist_squared == 0.0 {
+            return None;
+        }
+        let wi = to_light / dist_squared.sqrt();
+        Some(LightSample {
+            wi,
+            p: self.position,
+            normal: Vec3::default(),
+            radiance: self.intensity(&-wi) / dist_squared,
+            pdf: 1.0,
+        })
+    }
+
+    fn pdf_li(&self, _p: &Vec3, _wi: &Vec3) -> f64 {
+        0.0
+    }
+
+    // The emission normal is the direction, so the cosine with it is 1
+    fn sample_le(
+        &self,
+        u1: (f64, f64),
+        _u2: (f64, f64),
+        _world: (Vec3, f64),
+    ) -> Option<EmissionSample> {
+        let dir = sampling::uniform_sphere(u1);
+        Some(EmissionSample {
+            ray: Ray::from(self.position, dir),
+            normal: dir,
+            radiance: self.intensity(&dir),
+            pdf_pos: 1.0,
+            pdf_dir: sampling::uniform_sphere_pdf(),
+        })
+    }
+
+    fn pdf_le(&self, _ray: &Ray, _normal: &Vec3, _world: (Vec3, f64)) -> (f64, f64) {
+        (0.0, sampling::uniform_sphere_pdf())
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+/// Point light that only shines into a cone, fading out towards its edge
+pub struct SpotLight {
+    position: Vec3,
+    axis: Vec3,
+    intensity: Vec3,
+    cos_cone: f64,
+    cos_falloff_start: f64,
+    profile: Option<Arc<IesProfile>>,
+}
+
+impl SpotLight {
+    /// Cone pointing at `target` with a half angle of `cone_angle` degrees
+    /// and a hard edge
+    pub fn new(position: Vec3, target: Vec3, intensity: Vec3, cone_angle: f64) -> SpotLight {
+        let cos_cone = f64::cos(cone_angle.to_radians());
+        SpotLight {
+            position,
+            axis: Vec3::unit_vector(&(target - position)),
+            intensity,
+            cos_cone,
+            cos_falloff_start: cos_cone,
+            profile: None,
+        }
+    }
+
+    /// Fades the light out smoothly over the outermost `degrees` of the cone
+    pub fn with_falloff(mut self, degrees: f64) -> SpotLight {
+        let cone_angle = f64::acos(self.cos_cone);
+        self.cos_falloff_start = f64::cos(f64::max(cone_angle - degrees.to_radians(), 0.0));
+        self
+    }
+
+    /// Shapes the light by a photometric profile whose nadir points along
+    /// the axis of the cone. `intensity` becomes that of the brightest
+    /// direction.
+    pub fn with_profile(mut self, profile: Arc<IesProfile>) -> SpotLight {
+        self.profile = Some(profile);
+        self
+    }
+
+    // Fraction of the intensity sent in unit direction `w`
+    fn falloff(&self, w: &Vec3) -> f64 {
+        let cos_theta = Vec3::dot(w, &self.axis);
+        let cone = if cos_theta < self.cos_cone {
+            0.0
+        } else if cos_theta >= self.cos_falloff_start {
+            1.0
+        } else {
+            let x = (cos_theta - self.cos_cone) / (self.cos_falloff_start - self.cos_cone);
+            x * x * (3.0 - 2.0 * x)
+        };
+        if cone == 0.0 {
+            return 0.0;
+        }
+        let (zero, _) = self.axis.orthonormal_basis();
+        cone * profile_scale(&self.profile, w, &self.axis, &zero)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample_li(&self, p: &Vec3, _u: (f64, f64), _world: (Vec3, f64)) -> Option<LightSample> {
+        let to_light = self.position - *p;
+        let dist_squared = to_light.squared_len();
+        if dist_squared == 0.0 {
+            return None;
+        }
+        let wi = to_light / dist_squared.sqrt();
+        Some(LightSample {
+            wi,
+            p: self.position,
+            normal: Vec3::default(),
+            radiance: self.falloff(&-wi) * self.intensity / dist_squared,
+            pdf: 1.0,
+        })
+    }
+
+    fn pdf_li(&self, _p: &Vec3, _wi: &Vec3) -> f64 {
+        0.0
+    }
+
+    fn sample_le(
+        &self,
+        u1: (f64, f64),
+        _u2: (f64, f64),
+        _world: (Vec3, f64),
+    ) -> Option<EmissionSample> {
+        let local = sampling::uniform_cone(u1, self.cos_cone);
+        let dir = sampling::to_world(&local, &self.axis);
+        Some(EmissionSample {
+            ray: Ray::from(self.position, dir),
+            normal: dir,
+            radiance: self.falloff(&dir) * self.intensity,
+            pdf_pos: 1.0,
+            pdf_dir: sampling::uniform_cone_pdf(self.cos_cone),
+        })
+    }
+
+    fn pdf_le(&self, ray: &Ray, _normal: &Vec3, _world: (Vec3, f64)) -> (f64, f64) {
+        let dir = Vec3::unit_vector(&ray.direction);
+        if Vec3::dot(&dir, &self.axis) >= self.cos_cone {
+            (0.0, sampling::uniform_cone_pdf(self.cos_cone))
+        } else {
+            (0.0, 0.0)
+        }
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+// Fraction of the intensity of a light shaped by `profile` that it sends in
+// unit direction `w`, with the profile's nadir along `nadir` and its
+// horizontal angles starting at `zero`
+fn profile_scale(profile: &Option<Arc<IesProfile>>, w: &Vec3, nadir: &Vec3, zero: &Vec3) -> f64 {
+    let Some(profile) = profile else {
+        return 1.0;
+    };
+    let vertical = f64::acos(Vec3::dot(w, nadir).clamp(-1.0, 1.0));
+    let quarter = zero.cross(nadir);
+    let horizontal = f64::atan2(Vec3::dot(w, &quarter), Vec3::dot(w, zero));
+    profile.relative_intensity(vertical.to_degrees(), horizontal.to_degrees())
+}
+
+/// Parallel light arriving from one direction everywhere, like a distant sun
+pub struct DirectionalLight {
+    direction: Vec3,
+    irradiance: Vec3,
+}
+
+impl DirectionalLight {
+    /// `direction` points towards the light. `irradiance` is what falls on a
+    /// surface facing it.
+    pub fn new(direction: Vec3, irradiance: Vec3) -> DirectionalLight {
+        DirectionalLight {
+            direction: Vec3::unit_vector(&direction),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample_li(&self, p: &Vec3, _u: (f64, f64), world: (Vec3, f64)) -> Option<LightSample> {
+        Some(LightSample {
+            wi: self.direction,
+            p: *p + 2.0 * world.1 * self.direction,
+            normal: Vec3::default(),
+            radiance: self.irradiance,
+            pdf: 1.0,
+        })
+    }
+
+    fn pdf_li(&self, _p: &Vec3, _wi: &Vec3) -> f64 {
+        0.0
+    }
+
+    fn sample_le(
+        &self,
+        u1: (f64, f64),
+        _u2: (f64, f64),
+        world: (Vec3, f64),
+    ) -> Option<EmissionSample> {
+        Some(infinite_emission(
+            self.direction,
+            u1,
+            world,
+            self.irradiance,
+            1.0,
+        ))
+    }
+
+    fn pdf_le(&self, _ray: &Ray, _normal: &Vec3, world: (Vec3, f64)) -> (f64, f64) {
+        (1.0 / (PI * world.1 * world.1), 0.0)
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+/// Sky that blends between two colors from the horizon up
+pub struct GradientSky {
+    bottom: Vec3,
+    top: Vec3,
+}
+
+impl GradientSky {
+    pub fn new(bottom: Vec3, top: Vec3) -> GradientSky {
+        GradientSky { bottom, top }
+    }
+
+    /// Exposure for the sky through `camera`, in the units of
+    /// [`GRADIENT_SKY_EV100`] for physical cameras
+    pub fn exposure_for(camera: &dyn Camera) -> f64 {
+        if camera.photometric() {
+            1.2 * f64::exp2(GRADIENT_SKY_EV100) * camera.exposure()
+        } else {
+            camera.exposure()
+        }
+    }
+}
+
+impl Default for GradientSky {
+    fn default() -> Self {
+        GradientSky::new(Vec3::from((1.0, 1.0, 1.0)), Vec3::from((0.5, 0.7, 1.0)))
+    }
+}
+
+impl Light for GradientSky {
+    fn sample_li(&self, p: &Vec3, u: (f64, f64), world: (Vec3, f64)) -> Option<LightSample> {
+        let wi = sampling::uniform_sphere(u);
+        Some(LightSample {
+            wi,
+            p: *p + 2.0 * world.1 * wi,
+            normal: Vec3::default(),
+            radiance: self.le(&Ray::from(*p, wi)),
+            pdf: sampling::uniform_sphere_pdf(),
+        })
+    }
+
+    fn pdf_li(&self, _p: &Vec3, _wi: &Vec3) -> f64 {
+        sampling::uniform_sphere_pdf()
+    }
+
+    fn sample_le(
+        &self,
+        u1: (f64, f64),
+        u2: (f64, f64),
+        world: (Vec3, f64),
+    ) -> Option<EmissionSample> {
+        let wi = sampling::uniform_sphere(u1);
+        Some(infinite_emission(
+            wi,
+            u2,
+            world,
+            self.le(&Ray::from(world.0, wi)),
+            sampling::uniform_sphere_pdf(),
+        ))
+    }
+
+    fn pdf_le(&self, _ray: &Ray, _normal: &Vec3, world: (Vec3, f64)) -> (f64, f64) {
+        (
+            1.0 / (PI * world.1 * world.1),
+            sampling::uniform_sphere_pdf(),
+        )
+    }
+
+    fn le(&self, ray: &Ray) -> Vec3 {
+        let unit_dir = Vec3::unit_vector(&ray.direction);
+        let t = 0.5 * (unit_dir.y() + 1.0);
+        (1.0 - t) * self.bottom + t * self.top
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+}
+
+// Ray entering the scene from direction `wi` of an infinite light, from a
+// point on a disk facing it just outside the scene
+pub(crate) fn infinite_emission(
+    wi: Vec3,
+    u: (f64, f64),
+    world: (Vec3, f64),
+    radiance: Vec3,
+    pdf_dir: f64,
+) -> EmissionSample {
+    let (center, radius) = world;
+    let dir = -wi;
+    let (v1, v2) = dir.orthonormal_basis();
+    let (dx, dy) = sampling::concentric_disk(u);
+    let disk_p = center + radius * (dx * v1 + dy * v2);
+
+    EmissionSample {
+        ray: Ray::from(disk_p + radius * wi, dir),
+        normal: dir,
+        radiance,
+        pdf_pos: 1.0 / (PI * radius * radius),
+        pdf_dir,
+    }
+}
+
+/// Light from all around the scene, looked up in an equirectangular image
+/// that wraps around the y axis with its centre towards -z, as the
+/// equirectangular camera sees it. Directions are sampled proportionally to
+/// the brightness of the image.
+pub struct EnvironmentLight {
+    image: HdrImage,
+    distribution: Distribution2D,
+    rotation: f64,
+    intensity: f64,
+}
+
+impl EnvironmentLight {
+    pub fn new(image: HdrImage) -> EnvironmentLight {
+        let (width, height) = (image.get_width(), image.get_height());
+        // Rows near the poles cover less of the sphere
+        let mut brightness = Vec::with_capacity(width * height);
+        for row in 0..height {
+            let sin_theta = f64::sin(PI * (row as f64 + 0.5) / height as f64);
+            for x in 0..width {
+                brightness.push(image.get_pixel(x, height - 1 - row).luminance() * sin_theta);
+            }
+        }
+        EnvironmentLight {
+            distribution: Distribution2D::new(&brightness, width, height),
+            image,
+            rotation: 0.0,
+            intensity: 1.0,
+        }
+    }
+
+    /// Turns the environment counterclockwise around the y axis, seen from
+    /// above
+    pub fn with_rotation(mut self, degrees: f64) -> EnvironmentLight {
+        self.rotation = degrees.to_radians();
+        self
+    }
+
+    /// Scales the radiance of the image
+    pub fn with_intensity(mut self, intensity: f64) -> EnvironmentLight {
+        self.intensity = intensity;
+        self
+    }
+
+    // Position in the image, with v from the top, and the sine of the polar
+    // angle, which maps image area to solid angle
+    fn image_position(&self, dir: &Vec3) -> ((f64, f64), f64) {
+        let dir = Vec3::unit_vector(dir);
+        let (sin, cos) = f64::sin_cos(-self.rotation);
+        let (x, z) = (
+            cos * dir.x() + sin * dir.z(),
+            -sin * dir.x() + cos * dir.z(),
+        );
+        let u = 0.5 + f64::atan2(x, -z) / (2.0 * PI);
+        let theta = f64::acos(dir.y().clamp(-1.0, 1.0));
+        ((u.rem_euclid(1.0), theta / PI), f64::sin(theta))
+    }
+
+    fn direction(&self, (u, v): (f64, f64)) -> Vec3 {
+        let longitude = (u - 0.5) * 2.0 * PI - self.rotation;
+        let theta = v * PI;
+        Vec3::from((
+            f64::sin(theta) * f64::sin(longitude),
+            f64::cos(theta),
+            -f64::sin(theta) * f64::cos(longitude),
+        ))
+    }
+
+    fn lookup(&self, (u, v): (f64, f64)) -> Vec3 {
+        let (width, height) = (self.image.get_width(), self.image.get_height());
+        let x = usize::min((u * width as f64) as usize, width - 1);
+        let row = usize::min((v * height as f64) as usize, height - 1);
+        self.intensity * self.image.get_pixel(x, height - 1 - row)
+    }
+
+    // Solid angle density of the direction at image position `uv`
+    fn pdf_dir(&self, uv: (f64, f64), sin_theta: f64) -> f64 {
+        if sin_theta == 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(uv) / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn sample_direction(&self, u: (f64, f64)) -> Option<(Vec3, (f64, f64), f64)> {
+        let (uv, pdf) = self.distribution.sample(u);
+        let sin_theta = f64::sin(uv.1 * PI);
+        if pdf == 0.0 || sin_theta == 0.0 {
+            return None;
+        }
+        Some((self.direction(uv), uv, pdf / (2.0 * PI * PI * sin_theta)))
+    }
+}
+
+impl Light for EnvironmentLight {
+    fn sample_li(&self, p: &Vec3, u: (f64, f64), world: (Vec3, f64)) -> Option<LightSample> {
+        let (wi, uv, pdf) = self.sample_direction(u)?;
+        Some(LightSample {
+            wi,
+            p: *p + 2.0 * world.1 * wi,
+            normal: Vec3::default(),
+            radiance: self.lookup(uv),
+            pdf,
+        })
+    }
+
+    fn pdf_li(&self, _p: &Vec3, wi: &Vec3) -> f64 {
+        let (uv, sin_theta) = self.image_position(wi);
+        self.pdf_dir(uv, sin_theta)
+    }
+
+    fn sample_le(
+        &self,
+        u1: (f64, f64),
+        u2: (f64, f64),
+        world: (Vec3, f64),
+    ) -> Option<EmissionSample> {
+        let (wi, uv, pdf) = self.sample_direction(u1)?;
+        Some(infinite_emission(wi, u2, world, self.lookup(uv), pdf))
+    }
+
+    fn pdf_le(&self, ray: &Ray, _normal: &Vec3, world: (Vec3, f64)) -> (f64, f64) {
+        (
+            1.0 / (PI * world.1 * world.1),
+            self.pdf_li(&world.0, &-ray.direction),
+        )
+    }
+
+    fn le(&self, ray: &Ray) -> Vec3 {
+        self.lookup(self.image_position(&ray.direction).0)
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+}
@@ -1,106 +1,1081 @@
-use rand::random;
-use rayon::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use std::{
-    fs::File,
+    env,
+    fs::{self, File},
+    path::PathBuf,
+    process,
     sync::Arc,
     thread,
-    time::{Duration, SystemTime},
+    time::{Duration, Instant},
 };
 
 use raytrace::{
+    animation::{
+        frame_time, AnimatedObject, Animation, CameraAnimation, CameraPose, Interpolation,
+        Transform,
+    },
+    aperture::{Aperture, ApertureMask},
+    bdpt::BidirectionalPathTracer,
+    camera::{
+        Camera, CubemapCamera, EquirectangularCamera, FisheyeCamera, FisheyeMapping,
+        OrthographicCamera, PerspectiveCamera, PhysicalCamera, RealisticCamera, StereoCamera,
+        StereoLayout,
+    },
+    checkpoint,
+    film::Film,
+    filter::Filter,
+    hdr::HdrImage,
     hittable::{Hittable, HittableList, Sphere},
-    material::{Dielectric, Lambertian, Metal},
-    types::{Ray, Vec3},
-    Camera, Ppm,
+    ies::IesProfile,
+    integrator::{Integrator, PathTracer},
+    lens::LensSystem,
+    light::{DirectionalLight, EnvironmentLight, GradientSky, Light, PointLight, SpotLight},
+    material::{Dielectric, Dispersion, Lambertian, Material, Metal},
+    mlt::MetropolisLightTransport,
+    photon::{PhotonMapper, ProgressivePhotonMapper},
+    render::{render, render_adaptive, render_progressive, AdaptiveSampling, ProgressiveRendering},
+    sampler::{
+        BlueNoiseSampler, HaltonSampler, IndependentSampler, Sampler, SobolSampler,
+        StratifiedSampler,
+    },
+    sky::{sun_direction, PhysicalSky, DAYLIGHT_EV100, GRADIENT_SKY_EV100},
+    stats::{ProgressReporter, RayCounts, RenderStats},
+    types::Vec3,
+    Ppm,
 };
 
-fn color(ray: Ray, world: &dyn Hittable, depth: usize) -> Vec3 {
-    if let Some(hit_rec) = world.hit((0.001, f64::MAX), &ray) {
-        if depth < 50 {
-            if let Some((scattered, attenuation)) = hit_rec.mat.scatter(&ray, &hit_rec) {
-                return attenuation * color(scattered, world, depth + 1);
+#[derive(Debug)]
+enum Method {
+    Path,
+    Bidirectional,
+    Photon,
+    ProgressivePhoton,
+    Metropolis,
+}
+
+#[derive(Debug)]
+enum Projection {
+    Perspective,
+    Physical,
+    Realistic,
+    Orthographic,
+    Fisheye(FisheyeMapping),
+    Equirectangular,
+    Cubemap,
+}
+
+#[derive(Debug)]
+enum SkyKind {
+    Gradient,
+    Physical,
+}
+
+// Lights added to the scene for look development, with positions and
+// directions as (x, y, z), white intensities and optional IES profiles
+#[derive(Debug)]
+enum LightSpec {
+    Point {
+        position: (f64, f64, f64),
+        intensity: f64,
+        profile: Option<PathBuf>,
+    },
+    Spot {
+        position: (f64, f64, f64),
+        target: (f64, f64, f64),
+        cone_angle: f64,
+        falloff: f64,
+        intensity: f64,
+        profile: Option<PathBuf>,
+    },
+    Directional {
+        direction: (f64, f64, f64),
+        irradiance: f64,
+    },
+}
+
+impl LightSpec {
+    fn light(&self) -> Arc<dyn Light> {
+        let white = |value: f64| Vec3::from((value, value, value));
+        let load = |path: &PathBuf| {
+            Arc::new(IesProfile::load(path).unwrap_or_else(|err| {
+                eprintln!("Could not read IES profile {}: {err}", path.display());
+                process::exit(1);
+            }))
+        };
+        match self {
+            LightSpec::Point {
+                position,
+                intensity,
+                profile,
+            } => {
+                let light = PointLight::new((*position).into(), white(*intensity));
+                match profile {
+                    Some(path) => Arc::new(light.with_profile(load(path))),
+                    None => Arc::new(light),
+                }
+            }
+            LightSpec::Spot {
+                position,
+                target,
+                cone_angle,
+                falloff,
+                intensity,
+                profile,
+            } => {
+                let light = SpotLight::new(
+                    (*position).into(),
+                    (*target).into(),
+                    white(*intensity),
+                    *cone_angle,
+                )
+                .with_falloff(*falloff);
+                match profile {
+                    Some(path) => Arc::new(light.with_profile(load(path))),
+                    None => Arc::new(light),
+                }
             }
+            LightSpec::Directional {
+                direction,
+                irradiance,
+            } => Arc::new(DirectionalLight::new(
+                (*direction).into(),
+                white(*irradiance),
+            )),
+        }
+    }
+}
+
+// Where the camera is at one moment of the animation, with positions as
+// (x, y, z) and the field of view in degrees
+#[derive(Debug)]
+struct CameraKey {
+    time: f64,
+    look_from: (f64, f64, f64),
+    look_at: (f64, f64, f64),
+    vert_fov: f64,
+}
+
+impl CameraKey {
+    fn pose(&self) -> CameraPose {
+        CameraPose {
+            look_from: self.look_from.into(),
+            look_at: self.look_at.into(),
+            vert_fov: self.vert_fov,
+        }
+    }
+}
+
+// Where one of the large spheres of the random scene is at one moment of the
+// animation, turned in degrees around its vertical axis and scaled around
+// its centre
+#[derive(Debug)]
+struct ObjectKey {
+    object: String,
+    time: f64,
+    position: (f64, f64, f64),
+    rotation: f64,
+    scale: f64,
+}
+
+impl ObjectKey {
+    fn transform(&self) -> Transform {
+        Transform {
+            translation: self.position.into(),
+            rotation: self.rotation,
+            scale: self.scale,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+struct Options {
+    method: Method,
+    projection: Projection,
+    sampler: SamplerKind,
+    // Photons per pass for progressive photon mapping
+    photons: usize,
+    radius: f64,
+    // Seeds the scene and every sampler, so equal seeds give identical images
+    seed: u64,
+    threads: Option<usize>,
+    // Standard error at which adaptive sampling stops sampling a pixel
+    adaptive: Option<f64>,
+    // Samples per pixel per pass when rendering progressively
+    progressive: Option<usize>,
+    // Progressive renders save a checkpoint here every `checkpoint_interval`
+    checkpoint: Option<PathBuf>,
+    checkpoint_interval: Duration,
+    resume: bool,
+    filter: Filter,
+    filter_radius: Option<f64>,
+    // Settings of the physical camera, which is metered for the sky unless
+    // given a shutter speed
+    f_stop: f64,
+    shutter: Option<f64>,
+    iso: f64,
+    auto_exposure: bool,
+    // Shape of the aperture of the perspective and physical cameras, a
+    // circle unless there are blades or a mask
+    blades: usize,
+    blade_rotation: f64,
+    aperture_mask: Option<PathBuf>,
+    cats_eye: f64,
+    // Lens prescription and film size of the realistic camera
+    lens: PathBuf,
+    film_diagonal: f64,
+    // Stereo pairs, with eyes `interocular` apart whose views line up at
+    // `convergence`, the focus distance unless given
+    stereo: Option<StereoLayout>,
+    interocular: f64,
+    convergence: Option<f64>,
+    // Renders this many frames of the animation, spread over `time_range`
+    frames: Option<usize>,
+    time_range: (f64, f64),
+    // The camera circles the scene unless given keys, and the large spheres
+    // stay put unless given keys of their own
+    camera_keys: Vec<CameraKey>,
+    object_keys: Vec<ObjectKey>,
+    interpolation: Interpolation,
+    // Lights the scene from an equirectangular HDR image instead of the sky
+    environment: Option<PathBuf>,
+    environment_rotation: f64,
+    environment_intensity: f64,
+    // Daylight from the sun at the given position, in degrees, and the sky
+    sky: SkyKind,
+    sun_elevation: f64,
+    sun_azimuth: f64,
+    turbidity: f64,
+    ground_albedo: f64,
+    sky_intensity: f64,
+    lights: Vec<LightSpec>,
+    // Frosts the glass spheres of the random scene, from 0 for polished glass,
+    // tints them by absorbing this much of each channel per unit distance and
+    // splits colors by a wavelength dependent index of refraction
+    glass_roughness: f64,
+    glass_absorption: (f64, f64, f64),
+    glass_dispersion: Option<Dispersion>,
+    // Traces wavelengths instead of the channels of RGB
+    spectral: bool,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        method: Method::Path,
+        projection: Projection::Perspective,
+        sampler: SamplerKind::Independent,
+        photons: 1_000_000,
+        radius: 0.05,
+        seed: 0,
+        threads: None,
+        adaptive: None,
+        progressive: None,
+        checkpoint: None,
+        checkpoint_interval: Duration::from_secs(60),
+        resume: false,
+        filter: Filter::default(),
+        filter_radius: None,
+        f_stop: 2.8,
+        shutter: None,
+        iso: 100.,
+        auto_exposure: false,
+        blades: 0,
+        blade_rotation: 0.,
+        aperture_mask: None,
+        cats_eye: 0.,
+        lens: PathBuf::from("lenses/dgauss-50mm.txt"),
+        film_diagonal: 35.,
+        stereo: None,
+        interocular: 0.065,
+        convergence: None,
+        frames: None,
+        time_range: (0., 1.),
+        camera_keys: Vec::new(),
+        object_keys: Vec::new(),
+        interpolation: Interpolation::Linear,
+        environment: None,
+        environment_rotation: 0.,
+        environment_intensity: 1.,
+        sky: SkyKind::Gradient,
+        sun_elevation: 30.,
+        sun_azimuth: 0.,
+        turbidity: 3.,
+        ground_albedo: 0.3,
+        sky_intensity: 1.,
+        lights: Vec::new(),
+        glass_roughness: 0.,
+        glass_absorption: (0., 0., 0.),
+        glass_dispersion: None,
+        spectral: false,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("Missing value for {arg}"));
+        match arg.as_str() {
+            "--integrator" => {
+                options.method = match value()?.as_str() {
+                    "path" => Method::Path,
+                    "bdpt" => Method::Bidirectional,
+                    "photon" => Method::Photon,
+                    "sppm" => Method::ProgressivePhoton,
+                    "mlt" => Method::Metropolis,
+                    other => return Err(format!("Unknown integrator {other}")),
+                }
+            }
+            "--camera" => {
+                options.projection = match value()?.as_str() {
+                    "perspective" => Projection::Perspective,
+                    "physical" => Projection::Physical,
+                    "realistic" => Projection::Realistic,
+                    "orthographic" => Projection::Orthographic,
+                    "fisheye" => Projection::Fisheye(FisheyeMapping::Equidistant),
+                    "equisolid" => Projection::Fisheye(FisheyeMapping::Equisolid),
+                    "equirectangular" => Projection::Equirectangular,
+                    "cubemap" => Projection::Cubemap,
+                    other => return Err(format!("Unknown camera {other}")),
+                }
+            }
+            "--sampler" => {
+                options.sampler = match value()?.as_str() {
+                    "independent" => SamplerKind::Independent,
+                    "stratified" => SamplerKind::Stratified,
+                    "halton" => SamplerKind::Halton,
+                    "sobol" => SamplerKind::Sobol,
+                    "bluenoise" => SamplerKind::BlueNoise,
+                    other => return Err(format!("Unknown sampler {other}")),
+                }
+            }
+            "--photons" => options.photons = parse(&value()?)?,
+            "--radius" => options.radius = parse(&value()?)?,
+            "--seed" => options.seed = parse(&value()?)?,
+            "--threads" => options.threads = Some(parse(&value()?)?),
+            "--adaptive" => options.adaptive = Some(parse(&value()?)?),
+            "--progressive" => options.progressive = Some(parse(&value()?)?),
+            "--checkpoint" => options.checkpoint = Some(PathBuf::from(value()?)),
+            "--checkpoint-interval" => {
+                options.checkpoint_interval = Duration::from_secs(parse(&value()?)?)
+            }
+            "--resume" => options.resume = true,
+            "--filter" => {
+                options.filter = match value()?.as_str() {
+                    "box" => Filter::Box { radius: 0.5 },
+                    "tent" => Filter::Tent { radius: 1. },
+                    "gaussian" => Filter::Gaussian {
+                        radius: 1.5,
+                        sigma: 0.5,
+                    },
+                    "mitchell" => Filter::Mitchell {
+                        radius: 2.,
+                        b: 1. / 3.,
+                        c: 1. / 3.,
+                    },
+                    "lanczos" => Filter::Lanczos {
+                        radius: 3.,
+                        tau: 3.,
+                    },
+                    other => return Err(format!("Unknown filter {other}")),
+                }
+            }
+            "--filter-radius" => options.filter_radius = Some(parse(&value()?)?),
+            "--f-stop" => options.f_stop = parse(&value()?)?,
+            "--shutter" => options.shutter = Some(parse(&value()?)?),
+            "--iso" => options.iso = parse(&value()?)?,
+            "--auto-exposure" => options.auto_exposure = true,
+            "--blades" => options.blades = parse(&value()?)?,
+            "--blade-rotation" => options.blade_rotation = parse(&value()?)?,
+            "--aperture-mask" => options.aperture_mask = Some(PathBuf::from(value()?)),
+            "--cats-eye" => options.cats_eye = parse(&value()?)?,
+            "--lens" => options.lens = PathBuf::from(value()?),
+            "--film-diagonal" => options.film_diagonal = parse(&value()?)?,
+            "--stereo" => {
+                options.stereo = match value()?.as_str() {
+                    "side-by-side" => Some(StereoLayout::SideBySide),
+                    "top-bottom" => Some(StereoLayout::TopBottom),
+                    other => return Err(format!("Unknown stereo layout {other}")),
+                }
+            }
+            "--interocular" => options.interocular = parse(&value()?)?,
+            "--convergence" => options.convergence = Some(parse(&value()?)?),
+            "--environment" => options.environment = Some(PathBuf::from(value()?)),
+            "--environment-rotation" => options.environment_rotation = parse(&value()?)?,
+            "--environment-intensity" => options.environment_intensity = parse(&value()?)?,
+            "--sky" => {
+                options.sky = match value()?.as_str() {
+                    "gradient" => SkyKind::Gradient,
+                    "physical" => SkyKind::Physical,
+                    other => return Err(format!("Unknown sky {other}")),
+                }
+            }
+            "--sun-elevation" => options.sun_elevation = parse(&value()?)?,
+            "--sun-azimuth" => options.sun_azimuth = parse(&value()?)?,
+            "--turbidity" => options.turbidity = parse(&value()?)?,
+            "--ground-albedo" => options.ground_albedo = parse(&value()?)?,
+            "--sky-intensity" => options.sky_intensity = parse(&value()?)?,
+            "--light" => options.lights.push(parse_light(&value()?)?),
+            "--glass-roughness" => options.glass_roughness = parse(&value()?)?,
+            "--glass-absorption" => {
+                let value = value()?;
+                options.glass_absorption = match value.split(',').collect::<Vec<_>>()[..] {
+                    [r, g, b] => (parse(r)?, parse(g)?, parse(b)?),
+                    _ => return Err(format!("Invalid absorption {value}")),
+                };
+            }
+            "--glass-dispersion" => options.glass_dispersion = Some(parse_dispersion(&value()?)?),
+            "--spectral" => options.spectral = true,
+            "--frames" => options.frames = Some(parse(&value()?)?),
+            "--time-range" => {
+                let value = value()?;
+                let (start, end) = value
+                    .split_once(':')
+                    .ok_or(format!("Invalid time range {value}"))?;
+                options.time_range = (parse(start)?, parse(end)?);
+            }
+            "--camera-key" => options.camera_keys.push(parse_camera_key(&value()?)?),
+            "--object-key" => options.object_keys.push(parse_object_key(&value()?)?),
+            "--interpolation" => {
+                options.interpolation = match value()?.as_str() {
+                    "linear" => Interpolation::Linear,
+                    "spline" => Interpolation::Spline,
+                    other => return Err(format!("Unknown interpolation {other}")),
+                }
+            }
+            _ => return Err(format!("Unknown argument {arg}")),
+        }
+    }
+
+    if options.resume && options.checkpoint.is_none() {
+        return Err("--resume needs a --checkpoint file".to_string());
+    }
+    // Checkpoints are saved between passes
+    if options.checkpoint.is_some() && options.progressive.is_none() {
+        options.progressive = Some(16);
+    }
+    if options.progressive.is_some() {
+        if options.adaptive.is_some() {
+            return Err("--progressive cannot be combined with --adaptive".to_string());
+        }
+        if let Method::ProgressivePhoton | Method::Metropolis = options.method {
+            return Err("--progressive needs an integrator that traces camera samples".to_string());
+        }
+    }
+    if options.environment.is_some() && matches!(options.sky, SkyKind::Physical) {
+        return Err("--environment cannot be combined with --sky physical".to_string());
+    }
+    if options.spectral && !matches!(options.method, Method::Path | Method::Metropolis) {
+        return Err("--spectral needs the path or mlt integrator".to_string());
+    }
+    if options.frames.is_some() && options.progressive.is_some() {
+        return Err("--frames cannot be combined with --progressive or --checkpoint".to_string());
+    }
+    if options.stereo.is_some() {
+        if let Projection::Realistic
+        | Projection::Orthographic
+        | Projection::Fisheye(_)
+        | Projection::Cubemap = options.projection
+        {
+            return Err(format!(
+                "--stereo does not support {:?} cameras",
+                options.projection
+            ));
+        }
+    }
+    if let Some(radius) = options.filter_radius {
+        options.filter = options.filter.with_radius(radius);
+    }
+    Ok(options)
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid value {value}"))
+}
+
+// Parses point:X,Y,Z:I[:PROFILE], spot:X,Y,Z:TX,TY,TZ:ANGLE[,FALLOFF]:I[:PROFILE] or
+// directional:DX,DY,DZ:E
+fn parse_light(spec: &str) -> Result<LightSpec, String> {
+    let invalid = || format!("Invalid light {spec}");
+    let numbers = |part: &str| -> Result<Vec<f64>, String> { part.split(',').map(parse).collect() };
+    let vector = |part: &str| -> Result<(f64, f64, f64), String> {
+        match numbers(part)?[..] {
+            [x, y, z] => Ok((x, y, z)),
+            _ => Err(invalid()),
+        }
+    };
+    let mut parts: Vec<&str> = spec.split(':').collect();
+    let profile = match parts[..] {
+        ["point", _, _, profile] | ["spot", _, _, _, _, profile] => {
+            parts.pop();
+            Some(PathBuf::from(profile))
+        }
+        _ => None,
+    };
+    match parts[..] {
+        ["point", position, intensity] => Ok(LightSpec::Point {
+            position: vector(position)?,
+            intensity: parse(intensity)?,
+            profile,
+        }),
+        ["spot", position, target, cone, intensity] => {
+            let (cone_angle, falloff) = match numbers(cone)?[..] {
+                [cone_angle] => (cone_angle, 0.),
+                [cone_angle, falloff] => (cone_angle, falloff),
+                _ => return Err(invalid()),
+            };
+            Ok(LightSpec::Spot {
+                position: vector(position)?,
+                target: vector(target)?,
+                cone_angle,
+                falloff,
+                intensity: parse(intensity)?,
+                profile,
+            })
+        }
+        ["directional", direction, irradiance] => Ok(LightSpec::Directional {
+            direction: vector(direction)?,
+            irradiance: parse(irradiance)?,
+        }),
+        _ => Err(invalid()),
+    }
+}
+
+// Parses TIME:X,Y,Z:TX,TY,TZ[:FOV]
+fn parse_camera_key(spec: &str) -> Result<CameraKey, String> {
+    let invalid = || format!("Invalid camera key {spec}");
+    let (time, look_from, look_at, vert_fov) = match spec.split(':').collect::<Vec<_>>()[..] {
+        [time, look_from, look_at] => (time, look_from, look_at, "20"),
+        [time, look_from, look_at, vert_fov] => (time, look_from, look_at, vert_fov),
+        _ => return Err(invalid()),
+    };
+    Ok(CameraKey {
+        time: parse(time)?,
+        look_from: parse_vector(look_from).ok_or_else(invalid)?,
+        look_at: parse_vector(look_at).ok_or_else(invalid)?,
+        vert_fov: parse(vert_fov)?,
+    })
+}
+
+// Parses NAME:TIME:X,Y,Z[:DEGREES[:SCALE]] for one of the large spheres
+fn parse_object_key(spec: &str) -> Result<ObjectKey, String> {
+    let invalid = || format!("Invalid object key {spec}");
+    let parts: Vec<&str> = spec.split(':').collect();
+    let (object, time, position, rest) = match parts[..] {
+        [object, time, position, ref rest @ ..] if rest.len() <= 2 => {
+            (object, time, position, rest)
         }
+        _ => return Err(invalid()),
+    };
+    if !LARGE_SPHERES.contains(&object) {
+        return Err(format!("Unknown object {object}"));
+    }
+    Ok(ObjectKey {
+        object: object.to_string(),
+        time: parse(time)?,
+        position: parse_vector(position).ok_or_else(invalid)?,
+        rotation: rest.first().map_or(Ok(0.), |rotation| parse(rotation))?,
+        scale: rest.get(1).map_or(Ok(1.), |scale| parse(scale))?,
+    })
+}
+
+fn parse_vector(part: &str) -> Option<(f64, f64, f64)> {
+    match part
+        .split(',')
+        .map(parse)
+        .collect::<Result<Vec<f64>, _>>()
+        .ok()?[..]
+    {
+        [x, y, z] => Some((x, y, z)),
+        _ => None,
+    }
+}
 
-        return Vec3::default();
-    } else {
-        let unit_dir = Vec3::unit_vector(&ray.direction);
-        let t = 0.5 * (unit_dir.y() + 1.0);
-        return (1.0 - t) * Vec3::from((1.0, 1.0, 1.0)) + t * Vec3::from((0.5, 0.7, 1.0));
+// Parses a glass of known dispersion or cauchy:A,B
+fn parse_dispersion(spec: &str) -> Result<Dispersion, String> {
+    match spec {
+        "bk7" => Ok(Dispersion::bk7()),
+        "sf11" => Ok(Dispersion::sf11()),
+        "diamond" => Ok(Dispersion::diamond()),
+        _ => match spec.strip_prefix("cauchy:").map(|c| c.split_once(',')) {
+            Some(Some((a, b))) => Ok(Dispersion::Cauchy {
+                a: parse(a)?,
+                b: parse(b)?,
+            }),
+            _ => Err(format!("Unknown dispersion {spec}")),
+        },
     }
 }
 
 fn main() {
-    let aspect_ratio = 3. / 2.;
+    let options = parse_args().unwrap_or_else(|err| {
+        eprintln!("{err}");
+        eprintln!(
+            "Usage: raytrace [--integrator path|bdpt|photon|sppm|mlt] \
+             [--camera perspective|physical|realistic|orthographic|fisheye|equisolid|equirectangular|cubemap] \
+             [--lens FILE] [--film-diagonal MM] \
+             [--stereo side-by-side|top-bottom] [--interocular D] [--convergence D] \
+             [--frames N] [--time-range START:END] \
+             [--camera-key TIME:X,Y,Z:TX,TY,TZ[:FOV]]... \
+             [--object-key glass|diffuse|metal:TIME:X,Y,Z[:DEGREES[:SCALE]]]... \
+             [--interpolation linear|spline] \
+             [--environment IMAGE.hdr] [--environment-rotation DEGREES] [--environment-intensity S] \
+             [--sky gradient|physical] [--sun-elevation DEGREES] [--sun-azimuth DEGREES] \
+             [--turbidity T] [--ground-albedo A] [--sky-intensity S] \
+             [--light point:X,Y,Z:I[:PROFILE.ies]|spot:X,Y,Z:TX,TY,TZ:ANGLE[,FALLOFF]:I[:PROFILE.ies]|directional:DX,DY,DZ:E]... \
+             [--glass-roughness R] [--glass-absorption R,G,B] \
+             [--glass-dispersion bk7|sf11|diamond|cauchy:A,B] [--spectral] \
+             [--f-stop N] [--shutter SECONDS] [--iso N] [--auto-exposure] \
+             [--blades N] [--blade-rotation DEGREES] [--aperture-mask IMAGE] [--cats-eye S] \
+             [--sampler independent|stratified|halton|sobol|bluenoise] [--photons N] [--radius R] \
+             [--seed N] [--threads N] [--adaptive THRESHOLD] [--progressive N] \
+             [--checkpoint FILE] [--checkpoint-interval SECONDS] [--resume] \
+             [--filter box|tent|gaussian|mitchell|lanczos] [--filter-radius R]"
+        );
+        process::exit(1);
+    });
+
+    // Panoramas cover twice as much horizontally as vertically
+    let aspect_ratio = match options.projection {
+        Projection::Equirectangular => 2.,
+        _ => 3. / 2.,
+    };
+    // Stereo pairs put the view of each eye in half the image
+    let film_aspect_ratio = match options.stereo {
+        Some(StereoLayout::SideBySide) => 2. * aspect_ratio,
+        Some(StereoLayout::TopBottom) => aspect_ratio / 2.,
+        None => aspect_ratio,
+    };
     let width = 1200;
-    let height = f64::round(width as f64 / aspect_ratio) as usize;
+    let height = f64::round(width as f64 / film_aspect_ratio) as usize;
     let num_samples = 500;
-    let mut ppm = Ppm::from(width, height);
+    let seed = options.seed;
+    let sampler: Box<dyn Sampler> = match options.sampler {
+        SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+        SamplerKind::Stratified => Box::new(StratifiedSampler::new(num_samples, seed)),
+        SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+        SamplerKind::Sobol => Box::new(SobolSampler::new(num_samples, seed)),
+        SamplerKind::BlueNoise => Box::new(BlueNoiseSampler::new(num_samples, seed)),
+    };
+
+    let mut lights: Vec<Arc<dyn Light>> = match (&options.environment, &options.sky) {
+        (Some(path), _) => {
+            let image = HdrImage::load(path).unwrap_or_else(|err| {
+                eprintln!("Could not read environment {}: {err}", path.display());
+                process::exit(1);
+            });
+            vec![Arc::new(
+                EnvironmentLight::new(image)
+                    .with_rotation(options.environment_rotation)
+                    .with_intensity(options.environment_intensity),
+            )]
+        }
+        (None, SkyKind::Physical) => {
+            let sky = PhysicalSky::new(
+                sun_direction(options.sun_elevation, options.sun_azimuth),
+                options.turbidity,
+                options.ground_albedo,
+            )
+            .with_intensity(options.sky_intensity);
+            vec![Arc::new(sky.sun()), Arc::new(sky)]
+        }
+        (None, SkyKind::Gradient) => vec![Arc::new(GradientSky::default())],
+    };
+    lights.extend(options.lights.iter().map(LightSpec::light));
+    let mut rng = StdRng::seed_from_u64(seed);
+    let glass = || {
+        let glass = Dielectric::new(1.5)
+            .with_roughness(options.glass_roughness)
+            .with_absorption(options.glass_absorption.into());
+        match options.glass_dispersion {
+            Some(dispersion) => glass.with_dispersion(dispersion),
+            None => glass,
+        }
+    };
+    // Large spheres with keys are placed around their centres, and all the
+    // others go into the BVH shared by every frame
+    let mut world = random_scene(&mut rng, &glass);
+    let mut objects = Vec::new();
+    for (name, center, material) in large_spheres(&glass) {
+        let mut keys = options.object_keys.iter().filter(|key| key.object == name);
+        let Some(first) = keys.next() else {
+            world.add(Box::new(Sphere::new(center, 1., material)));
+            continue;
+        };
+        let sphere: Arc<dyn Hittable> = Arc::new(Sphere::new(Vec3::default(), 1., material));
+        let object = keys.fold(
+            AnimatedObject::starting_at(sphere, first.time, first.transform()),
+            |object, key| object.key(key.time, key.transform()),
+        );
+        objects.push(object.with_interpolation(options.interpolation));
+    }
+    let animation = objects
+        .into_iter()
+        .fold(Animation::new(world, lights), Animation::with_object);
+    // Animations circle the camera around the scene once over the time range,
+    // unless the camera has keys
+    let camera_animation = match options.camera_keys.split_first() {
+        Some((first, rest)) => rest
+            .iter()
+            .fold(
+                CameraAnimation::still(first.time, first.pose()),
+                |animation, key| animation.key(key.time, key.pose()),
+            )
+            .with_interpolation(options.interpolation),
+        None => CameraAnimation::turntable(
+            Vec3::from((13., 2., 3.)),
+            Vec3::from((0., 0., 0.)),
+            20.,
+            options.time_range,
+        ),
+    };
 
-    let world = random_scene();
+    // Physical cameras are metered for the sky, and environment maps are
+    // exposed like the gradient sky
+    let (sky_ev100, sky_exposure): (f64, fn(&dyn Camera) -> f64) = match options.sky {
+        SkyKind::Gradient => (GRADIENT_SKY_EV100, GradientSky::exposure_for),
+        SkyKind::Physical => (DAYLIGHT_EV100, PhysicalSky::exposure_for),
+    };
 
-    let look_from = Vec3::from((13., 2., 3.));
-    let look_at = Vec3::from((0., 0., 0.));
     let dist_to_focus = 10.;
     let aperture = 0.1;
-    let camera = Camera::new(
-        look_from,
-        look_at,
-        Vec3::from((0., 1., 0.)),
-        20.,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
-    );
-
-    let start = SystemTime::now();
+    let aperture_shape = match &options.aperture_mask {
+        Some(path) => {
+            let image = File::open(path).and_then(|mut file| Ppm::read(&mut file));
+            match image.map(|image| ApertureMask::from_image(&image)) {
+                Ok(Some(mask)) => Aperture::Mask(Arc::new(mask)),
+                Ok(None) => {
+                    eprintln!("Aperture mask {} lets no light through", path.display());
+                    process::exit(1);
+                }
+                Err(err) => {
+                    eprintln!("Could not read aperture mask {}: {err}", path.display());
+                    process::exit(1);
+                }
+            }
+        }
+        None => Aperture::polygonal(options.blades, options.blade_rotation),
+    };
+    let view_up = Vec3::from((0., 1., 0.));
+    // The camera of one eye, `offset` to the right of the centre
+    let camera_for_eye = |pose: &CameraPose, offset: f64, convergence: f64| -> Box<dyn Camera> {
+        let (look_from, look_at) = (pose.look_from, pose.look_at);
+        match options.projection {
+            Projection::Perspective => Box::new(
+                PerspectiveCamera::new(
+                    look_from,
+                    look_at,
+                    view_up,
+                    pose.vert_fov,
+                    aspect_ratio,
+                    aperture,
+                    dist_to_focus,
+                )
+                .with_aperture(aperture_shape.clone())
+                .with_cats_eye(options.cats_eye)
+                .with_stereo_eye(offset, convergence),
+            ),
+            // The focal length that gives the field of view on a 24 mm sensor
+            Projection::Physical => {
+                let camera =
+                    PhysicalCamera::new(look_from, look_at, view_up, aspect_ratio, dist_to_focus)
+                        .with_focal_length(12. / f64::tan(pose.vert_fov.to_radians() / 2.))
+                        .with_f_number(options.f_stop)
+                        .with_iso(options.iso);
+                let camera = match options.shutter {
+                    Some(seconds) => camera.with_shutter_speed(seconds),
+                    None => camera.with_exposure_value(sky_ev100),
+                };
+                Box::new(
+                    camera
+                        .with_aperture(aperture_shape.clone())
+                        .with_cats_eye(options.cats_eye)
+                        .with_stereo_eye(offset, convergence),
+                )
+            }
+            Projection::Realistic => {
+                let lens = LensSystem::load(&options.lens).unwrap_or_else(|err| {
+                    eprintln!("Could not read lens {}: {err}", options.lens.display());
+                    process::exit(1);
+                });
+                let camera = RealisticCamera::new(
+                    look_from,
+                    look_at,
+                    view_up,
+                    lens,
+                    options.film_diagonal,
+                    aspect_ratio,
+                    dist_to_focus,
+                );
+                Box::new(camera.unwrap_or_else(|| {
+                    eprintln!("The lens cannot focus at {dist_to_focus}");
+                    process::exit(1);
+                }))
+            }
+            // As tall as the perspective view at the focus distance
+            Projection::Orthographic => Box::new(OrthographicCamera::new(
+                look_from,
+                look_at,
+                view_up,
+                2. * dist_to_focus * f64::tan(pose.vert_fov.to_radians() / 2.),
+                aspect_ratio,
+            )),
+            Projection::Fisheye(mapping) => Box::new(FisheyeCamera::new(
+                look_from,
+                look_at,
+                view_up,
+                180.,
+                aspect_ratio,
+                mapping,
+            )),
+            Projection::Equirectangular => Box::new(
+                EquirectangularCamera::new(look_from, look_at, view_up)
+                    .with_stereo_eye(offset, convergence),
+            ),
+            Projection::Cubemap => Box::new(CubemapCamera::new(look_from, look_at, view_up)),
+        }
+    };
+    let camera_at = |pose: &CameraPose| -> Box<dyn Camera> {
+        match options.stereo {
+            Some(layout) => {
+                let convergence = options.convergence.unwrap_or(dist_to_focus);
+                let half = options.interocular / 2.;
+                Box::new(StereoCamera::new(
+                    camera_for_eye(pose, -half, convergence),
+                    camera_for_eye(pose, half, convergence),
+                    layout,
+                ))
+            }
+            None => camera_for_eye(pose, 0., f64::INFINITY),
+        }
+    };
 
-    let num_threads: usize = thread::available_parallelism().unwrap().into();
-    let samples_per_thread = f64::round(num_samples as f64 / num_threads as f64) as usize;
-    let actual_total_samples = num_threads * samples_per_thread;
-    println!("Using {num_threads} threads to calculate {samples_per_thread} samples per thread");
-    println!("Total samples: {actual_total_samples}");
-    // let num_threads = 16;
+    let num_threads = options
+        .threads
+        .unwrap_or_else(|| thread::available_parallelism().unwrap().into());
+    println!("Using {num_threads} threads to calculate {num_samples} samples per pixel");
     let pool = rayon::ThreadPoolBuilder::new()
         .num_threads(num_threads)
         .build()
         .unwrap();
 
-    for y in (0..height).rev() {
-        for x in 0..width {
-            let mut col = pool.install(|| {
-                let mut local_col = Vec3::default();
-                for _ in 0..samples_per_thread {
-                    local_col += pool
-                        .broadcast(|_ctx| {
-                            let u = (x as f64 + random::<f64>()) / (width as f64);
-                            let v = (y as f64 + random::<f64>()) / (height as f64);
-
-                            let ray = camera.get_ray(u, v);
-                            color(ray, &world, 0)
-                        })
-                        .par_iter()
-                        .sum();
+    // Everything that affects the image, which a checkpoint must match
+    let settings = format!(
+        "{:?} {:?} f/{} shutter={:?} blades={}@{} mask={:?} cats_eye={} lens={:?}@{} stereo={:?}@{}/{:?} time={} keys={:?}/{:?}/{:?} environment={:?}@{}x{} sky={:?}@{},{}/{}/{}x{} lights={:?} glass={}/{:?}/{:?} spectral={} {:?} {:?} photons={} radius={} seed={} samples={} pass={:?} size={width}x{height}",
+        options.method,
+        options.projection,
+        options.f_stop,
+        options.shutter,
+        options.blades,
+        options.blade_rotation,
+        options.aperture_mask,
+        options.cats_eye,
+        options.lens,
+        options.film_diagonal,
+        options.stereo,
+        options.interocular,
+        options.convergence,
+        options.time_range.0,
+        options.camera_keys,
+        options.object_keys,
+        options.interpolation,
+        options.environment,
+        options.environment_rotation,
+        options.environment_intensity,
+        options.sky,
+        options.sun_elevation,
+        options.sun_azimuth,
+        options.turbidity,
+        options.ground_albedo,
+        options.sky_intensity,
+        options.lights,
+        options.glass_roughness,
+        options.glass_absorption,
+        options.glass_dispersion,
+        options.spectral,
+        options.sampler,
+        options.filter,
+        options.photons,
+        options.radius,
+        seed,
+        num_samples,
+        options.progressive,
+    );
+
+    // Single images show the scene at the start of the time range
+    let frames: Vec<(f64, String)> = match options.frames {
+        Some(frames) => (0..frames)
+            .map(|frame| {
+                let time = frame_time(options.time_range, frame, frames);
+                (time, format!("output/frame_{frame:04}.ppm"))
+            })
+            .collect(),
+        None => vec![(options.time_range.0, "output/random_scene.ppm".to_string())],
+    };
+
+    let mut stats = RenderStats::new();
+    for (time, output) in &frames {
+        let scene = animation.scene_at(*time);
+        let camera = camera_at(&camera_animation.pose(*time));
+        let mut film = Film::with_filter(width, height, options.filter);
+        if options.auto_exposure {
+            film.set_auto_exposure();
+        } else {
+            film.set_exposure(sky_exposure(camera.as_ref()));
+        }
+
+        // Integrators that trace camera samples take the same number of samples
+        // in every pixel, unless sampling adaptively. Progressive renders write
+        // the image after every pass, and save checkpoints if asked to.
+        let sampling = (options.adaptive, options.progressive);
+        let pixel_samples = (width * height * num_samples) as u64;
+        let render_samples = |integrator: &dyn Integrator, film: &mut Film| match sampling {
+            (None, Some(samples_per_pass)) => {
+                let mut progressive = ProgressiveRendering::new(num_samples, samples_per_pass);
+                let mut remaining = num_samples;
+                if let (true, Some(path)) = (options.resume, &options.checkpoint) {
+                    let taken = checkpoint::load(path, &settings, film).unwrap_or_else(|err| {
+                        eprintln!("Could not resume from {}: {err}", path.display());
+                        process::exit(1);
+                    });
+                    println!("Resuming with {taken} samples per pixel taken");
+                    progressive = progressive.resume_from(taken);
+                    remaining -= usize::min(taken, num_samples);
                 }
+                let _progress = ProgressReporter::start((width * height * remaining) as u64);
+                let mut last_checkpoint = Instant::now();
+                render_progressive(
+                    &scene,
+                    camera.as_ref(),
+                    integrator,
+                    sampler.as_ref(),
+                    film,
+                    &progressive,
+                    |film, taken| {
+                        write_ppm("output/random_scene.ppm", &film.to_ppm());
+                        println!("Wrote image with {taken} samples per pixel");
+                        if let Some(path) = &options.checkpoint {
+                            if last_checkpoint.elapsed() >= options.checkpoint_interval {
+                                checkpoint::save(path, &settings, film, taken)
+                                    .expect("Could not save checkpoint");
+                                last_checkpoint = Instant::now();
+                            }
+                        }
+                    },
+                )
+            }
+            (Some(threshold), _) => {
+                let settings = AdaptiveSampling::new(16, num_samples, threshold);
+                // Judged by the most samples it can take, so it may finish early
+                let progress = ProgressReporter::start(pixel_samples);
+                let (maps, rays) = render_adaptive(
+                    &scene,
+                    camera.as_ref(),
+                    integrator,
+                    sampler.as_ref(),
+                    film,
+                    &settings,
+                );
+                drop(progress);
+                println!(
+                    "Took {:.1} samples per pixel on average",
+                    maps.total_samples() as f64 / (width * height) as f64
+                );
+                write_ppm(
+                    "output/random_scene_samples.ppm",
+                    &maps.sample_count_image(),
+                );
+                write_ppm("output/random_scene_variance.ppm", &maps.variance_image());
+                rays
+            }
+            (None, None) => {
+                let _progress = ProgressReporter::start(pixel_samples);
+                render(
+                    &scene,
+                    camera.as_ref(),
+                    integrator,
+                    sampler.as_ref(),
+                    film,
+                    num_samples,
+                )
+            }
+        };
 
-                local_col
-            });
-            col /= actual_total_samples as f64;
-            col = Vec3::from((f64::sqrt(col.x()), f64::sqrt(col.y()), f64::sqrt(col.z())));
+        pool.install(|| match options.method {
+            Method::Path => stats.phase("render", || {
+                render_samples(
+                    &PathTracer::default().with_spectral(options.spectral),
+                    &mut film,
+                )
+            }),
+            Method::Bidirectional => stats.phase("render", || {
+                render_samples(&BidirectionalPathTracer::default(), &mut film)
+            }),
+            Method::Photon => {
+                let mut photon_mapper = None;
+                stats.phase("photon map", || {
+                    photon_mapper
+                        .insert(PhotonMapper::new(
+                            &scene,
+                            options.photons,
+                            options.radius,
+                            50,
+                            seed,
+                        ))
+                        .rays()
+                });
+                let photon_mapper = photon_mapper.expect("the photon map phase builds the map");
+                println!("Stored {} photons", photon_mapper.stored_photons());
+                stats.phase("render", || render_samples(&photon_mapper, &mut film))
+            }
+            Method::ProgressivePhoton => stats.phase("render", || {
+                let _progress = ProgressReporter::start(pixel_samples);
+                ProgressivePhotonMapper::new(options.photons, options.radius, 50, seed).render(
+                    &scene,
+                    camera.as_ref(),
+                    &mut film,
+                    num_samples,
+                )
+            }),
+            Method::Metropolis => stats.phase("render", || {
+                let (bootstrap_samples, chains) = (100_000, 1000);
+                // Every chain starts by replaying a bootstrap path
+                let _progress =
+                    ProgressReporter::start(pixel_samples + (bootstrap_samples + chains) as u64);
+                MetropolisLightTransport::new(50, bootstrap_samples, chains, num_samples, seed)
+                    .with_spectral(options.spectral)
+                    .render(&scene, camera.as_ref(), &mut film)
+            }),
+        });
 
-            ppm.set_pixel(x, y, col * 255.99);
+        stats.phase("output", || {
+            write_ppm(output, &film.to_ppm());
+            RayCounts::default()
+        });
+        if options.frames.is_some() {
+            println!("Wrote {output}");
         }
     }
 
-    let mut file = File::create("output/random_scene.ppm").expect("Could not create ppm file");
-    ppm.write(&mut file);
+    let time = Duration::from_secs(stats.time().as_secs());
+    println!("Rendering took {}", humantime::format_duration(time));
+    print!("{stats}");
+}
 
-    let end = SystemTime::now();
-    let delta = Duration::new(end.duration_since(start).unwrap().as_secs(), 0);
-    println!("Rendering took {}", humantime::format_duration(delta));
+// Writes to a temporary file first, so the image is never seen half written
+// while rendering progressively
+fn write_ppm(path: &str, ppm: &Ppm) {
+    let tmp_path = format!("{path}.tmp");
+    let mut file = File::create(&tmp_path).expect("Could not create ppm file");
+    ppm.write(&mut file);
+    fs::rename(&tmp_path, path).expect("Could not write ppm file");
 }
 
-fn random_scene() -> HittableList {
+fn random_scene(rng: &mut impl Rng, glass: &dyn Fn() -> Dielectric) -> HittableList {
     let mut list = HittableList::new();
     list.add(Box::new(Sphere::new(
         Vec3::from((0., -1000., 0.)),
@@ -110,11 +1085,11 @@ fn random_scene() -> HittableList {
 
     for a in -11..11 {
         for b in -11..11 {
-            let choose_mat: f64 = random();
+            let choose_mat: f64 = rng.gen();
             let center = Vec3::from((
-                a as f64 + 0.9 * random::<f64>(),
+                a as f64 + 0.9 * rng.gen::<f64>(),
                 0.2,
-                b as f64 + 0.9 * random::<f64>(),
+                b as f64 + 0.9 * rng.gen::<f64>(),
             ));
             if (center - Vec3::from((4., 0.2, 0.))).length() > 0.9 {
                 if choose_mat < 0.8 {
@@ -123,9 +1098,9 @@ fn random_scene() -> HittableList {
                         center,
                         0.2,
                         Arc::new(Lambertian::new(Vec3::new(
-                            random::<f64>() * random::<f64>(),
-                            random::<f64>() * random::<f64>(),
-                            random::<f64>() * random::<f64>(),
+                            rng.gen::<f64>() * rng.gen::<f64>(),
+                            rng.gen::<f64>() * rng.gen::<f64>(),
+                            rng.gen::<f64>() * rng.gen::<f64>(),
                         ))),
                     )))
                 } else if choose_mat < 0.95 {
@@ -135,40 +1110,42 @@ fn random_scene() -> HittableList {
                         0.2,
                         Arc::new(Metal::new(
                             Vec3::new(
-                                0.5 * (1. + random::<f64>()),
-                                0.5 * (1. + random::<f64>()),
-                                0.5 * (1. + random::<f64>()),
+                                0.5 * (1. + rng.gen::<f64>()),
+                                0.5 * (1. + rng.gen::<f64>()),
+                                0.5 * (1. + rng.gen::<f64>()),
                             ),
-                            0.5 * random::<f64>(),
+                            0.5 * rng.gen::<f64>(),
                         )),
                     )));
                 } else {
                     // glass
-                    list.add(Box::new(Sphere::new(
-                        center,
-                        0.2,
-                        Arc::new(Dielectric::new(1.5)),
-                    )));
+                    list.add(Box::new(Sphere::new(center, 0.2, Arc::new(glass()))));
                 }
             }
         }
     }
 
-    list.add(Box::new(Sphere::new(
-        Vec3::new(0, 1, 0),
-        1.,
-        Arc::new(Dielectric::new(1.5)),
-    )));
-    list.add(Box::new(Sphere::new(
-        Vec3::new(-4, 1, 0),
-        1.,
-        Arc::new(Lambertian::new(Vec3::new(0.4, 0.2, 0.1))),
-    )));
-    list.add(Box::new(Sphere::new(
-        Vec3::new(4, 1, 0),
-        1.,
-        Arc::new(Metal::new(Vec3::new(0.7, 0.6, 0.5), 0.)),
-    )));
-
     list
 }
+
+// Names of the large spheres in the middle of the random scene, which keys
+// can move
+const LARGE_SPHERES: [&str; 3] = ["glass", "diffuse", "metal"];
+
+// The large spheres with their names, centres and materials
+fn large_spheres(glass: &dyn Fn() -> Dielectric) -> [(&'static str, Vec3, Arc<dyn Material>); 3] {
+    let [glass_name, diffuse_name, metal_name] = LARGE_SPHERES;
+    [
+        (glass_name, Vec3::new(0, 1, 0), Arc::new(glass())),
+        (
+            diffuse_name,
+            Vec3::new(-4, 1, 0),
+            Arc::new(Lambertian::new(Vec3::new(0.4, 0.2, 0.1))),
+        ),
+        (
+            metal_name,
+            Vec3::new(4, 1, 0),
+            Arc::new(Metal::new(Vec3::new(0.7, 0.6, 0.5), 0.)),
+        ),
+    ]
+}
@@ -1,12 +1,65 @@
-use rand::random;
+use std::f64::consts::PI;
 
 use crate::hittable::HitRecord;
-use crate::hittable::Sphere;
+use crate::microfacet::TrowbridgeReitz;
+use crate::sampler::Sampler;
+use crate::sampling;
+use crate::spectrum::{self, Wavelengths};
 use crate::types::Ray;
 use crate::types::Vec3;
 
-pub trait Material {
-    fn scatter(&self, r_in: &Ray, hit_rec: &HitRecord) -> Option<(Ray, Vec3)>;
+/// `scatter` returns the sampled ray together with its weight, which is the
+/// BSDF times the cosine term divided by the density of the sampled direction.
+/// Integrators that connect path vertices directly use `eval` and `pdf`
+/// instead, where `wo` and `wi` are unit vectors pointing away from the surface.
+pub trait Material: Send + Sync {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        hit_rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Vec3)>;
+
+    /// Radiance leaving the hit point back along `r_in`
+    fn emitted(&self, _r_in: &Ray, _hit_rec: &HitRecord) -> Vec3 {
+        Vec3::default()
+    }
+
+    fn eval(&self, _wo: &Vec3, _wi: &Vec3, _hit_rec: &HitRecord) -> Vec3 {
+        Vec3::default()
+    }
+
+    /// Solid angle density with which `scatter` picks `wi` for light leaving along `wo`
+    fn pdf(&self, _wo: &Vec3, _wi: &Vec3, _hit_rec: &HitRecord) -> f64 {
+        0.0
+    }
+
+    /// Fraction of the light that survives travelling `distance` through the
+    /// inside of the material to the hit
+    fn transmittance(&self, _distance: f64, _hit_rec: &HitRecord) -> Vec3 {
+        Vec3::from((1.0, 1.0, 1.0))
+    }
+
+    /// Factor that turns the light scattered by `eval` and `scatter` from
+    /// `wi` into `wo` into radiance. They give the scattering of importance,
+    /// which is what light paths carry, while radiance refracted into a denser
+    /// medium is squeezed into a narrower cone. The factors cancel on paths
+    /// that leave the material again.
+    fn radiance_scale(&self, _wo: &Vec3, _wi: &Vec3, _hit_rec: &HitRecord) -> f64 {
+        1.0
+    }
+
+    /// Specular materials scatter into a delta distribution, so `eval` and
+    /// `pdf` are meaningless for them and paths can not be connected through them
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    /// Whether where light scatters depends on its wavelength, so that paths
+    /// through the material carry the color of a single wavelength
+    fn disperses(&self) -> bool {
+        false
+    }
 }
 
 pub struct Lambertian {
@@ -20,24 +73,174 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _r_in: &Ray, hit_rec: &HitRecord) -> Option<(Ray, Vec3)> {
-        let target = hit_rec.p + hit_rec.normal + Sphere::random_in_unit_sphere();
-        let scattered = Ray::from(hit_rec.p, target - hit_rec.p);
-        let attenuation = self.albedo;
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        hit_rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Vec3)> {
+        // Scatter to the side of the surface the ray came from
+        let mut normal = hit_rec.normal;
+        if Vec3::dot(&r_in.direction, &normal) > 0.0 {
+            normal *= -1.0;
+        }
+        let local = sampling::cosine_hemisphere(sampler.get_2d());
+        let scattered = hit_rec.spawn_ray(sampling::to_world(&local, &normal));
+        let attenuation = spectrum::spectral(&self.albedo, hit_rec.wavelength);
 
         Some((scattered, attenuation))
     }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, hit_rec: &HitRecord) -> Vec3 {
+        if Vec3::dot(wo, &hit_rec.normal) * Vec3::dot(wi, &hit_rec.normal) > 0.0 {
+            spectrum::spectral(&self.albedo, hit_rec.wavelength) / PI
+        } else {
+            Vec3::default()
+        }
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, hit_rec: &HitRecord) -> f64 {
+        if Vec3::dot(wo, &hit_rec.normal) * Vec3::dot(wi, &hit_rec.normal) > 0.0 {
+            f64::abs(Vec3::dot(wi, &hit_rec.normal)) / PI
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Emits `emit` from the outside of the surface and absorbs all light.
+/// Emitters should be built with `SphereLight::sphere` so that integrators
+/// that sample lights know about them.
+pub struct DiffuseLight {
+    emit: Vec3,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Vec3) -> DiffuseLight {
+        DiffuseLight { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        _hit_rec: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Vec3)> {
+        None
+    }
+
+    fn emitted(&self, r_in: &Ray, hit_rec: &HitRecord) -> Vec3 {
+        if Vec3::dot(&r_in.direction, &hit_rec.normal) < 0.0 {
+            spectrum::spectral(&self.emit, hit_rec.wavelength)
+        } else {
+            Vec3::default()
+        }
+    }
 }
 
+/// Conductor whose surface is a Trowbridge-Reitz distribution of mirror
+/// microfacets, each reflecting by the Fresnel equations for the complex index
+/// of refraction `eta + i k`, given per color channel
 pub struct Metal {
-    albedo: Vec3,
-    fuzziness: f64,
+    eta: Vec3,
+    k: Vec3,
+    distribution: TrowbridgeReitz,
 }
 
 impl Metal {
-    pub fn new(albedo: Vec3, fuzziness: f64) -> Metal {
-        let fuzziness = f64::min(fuzziness, 1.0);
-        Metal { albedo, fuzziness }
+    /// Conductor that reflects `albedo` at normal incidence, with `roughness`
+    /// from 0 for a mirror to 1
+    pub fn new(albedo: Vec3, roughness: f64) -> Metal {
+        // With eta = 1 the reflectance at normal incidence is k² / (4 + k²)
+        let mut k = Vec3::default();
+        for i in 0..3 {
+            let r = albedo[i].clamp(0.0, 0.999);
+            k[i] = 2.0 * f64::sqrt(r / (1.0 - r));
+        }
+        Metal::conductor(Vec3::from((1.0, 1.0, 1.0)), k, roughness)
+    }
+
+    pub fn conductor(eta: Vec3, k: Vec3, roughness: f64) -> Metal {
+        Metal {
+            eta,
+            k,
+            distribution: TrowbridgeReitz::from_roughness(roughness),
+        }
+    }
+
+    // Measured indices at 650, 550 and 450 nm
+    pub fn gold(roughness: f64) -> Metal {
+        Metal::conductor(
+            Vec3::from((0.143, 0.374, 1.442)),
+            Vec3::from((3.983, 2.385, 1.603)),
+            roughness,
+        )
+    }
+
+    pub fn copper(roughness: f64) -> Metal {
+        Metal::conductor(
+            Vec3::from((0.200, 0.924, 1.102)),
+            Vec3::from((3.912, 2.452, 2.142)),
+            roughness,
+        )
+    }
+
+    pub fn aluminum(roughness: f64) -> Metal {
+        Metal::conductor(
+            Vec3::from((1.657, 0.880, 0.521)),
+            Vec3::from((9.224, 6.270, 4.837)),
+            roughness,
+        )
+    }
+
+    /// Fraction of light reflected at a mirror facet seen at angle `cos_theta`
+    pub fn fresnel(&self, cos_theta: f64) -> Vec3 {
+        let mut reflectance = Vec3::default();
+        for i in 0..3 {
+            reflectance[i] = fresnel_conductor(cos_theta, self.eta[i], self.k[i]);
+        }
+        reflectance
+    }
+
+    // `fresnel` for light of `wavelength`, with the indices interpolated
+    // between the channels in spectral rendering
+    fn reflectance(&self, cos_theta: f64, wavelength: Option<Wavelengths>) -> Vec3 {
+        let Some(Wavelengths::Hero { lambda, .. }) = wavelength else {
+            return self.fresnel(cos_theta);
+        };
+        let mut reflectance = Vec3::default();
+        for i in 0..3 {
+            let eta = spectrum::interpolate(&self.eta, lambda[i]);
+            let k = spectrum::interpolate(&self.k, lambda[i]);
+            reflectance[i] = fresnel_conductor(cos_theta, eta, k);
+        }
+        reflectance
+    }
+
+    // `wo`, `wi` and the microfacet normal between them in the frame around
+    // the normal on the side of `wo`, or None if the BRDF is zero for them
+    fn local_half_vector(
+        &self,
+        wo: &Vec3,
+        wi: &Vec3,
+        hit_rec: &HitRecord,
+    ) -> Option<(Vec3, Vec3, Vec3)> {
+        if self.distribution.is_smooth() {
+            return None;
+        }
+        let normal = facing_normal(wo, hit_rec);
+        let wo = sampling::to_local(wo, &normal);
+        let wi = sampling::to_local(wi, &normal);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return None;
+        }
+        let h = wo + wi;
+        if h.is_zero() {
+            return None;
+        }
+        Some((wo, wi, Vec3::unit_vector(&h)))
     }
 }
 
@@ -45,70 +248,381 @@ fn reflect(vec: &Vec3, normal: &Vec3) -> Vec3 {
     vec - 2.0 * Vec3::dot(vec, normal) * normal
 }
 
+// Unpolarized reflectance of a conductor with index `eta + i k` relative to
+// the medium above it
+fn fresnel_conductor(cos_theta: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = f64::sqrt(t0 * t0 + 4.0 * eta * eta * k * k);
+    let a = f64::sqrt(0.5 * (a2_plus_b2 + t0));
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * cos2.sqrt() * a;
+    let rs = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rs + rp)
+}
+
+// Normal of an opaque surface turned to the side `w` lies on
+fn facing_normal(w: &Vec3, hit_rec: &HitRecord) -> Vec3 {
+    if Vec3::dot(w, &hit_rec.normal) < 0.0 {
+        -hit_rec.normal
+    } else {
+        hit_rec.normal
+    }
+}
+
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, hit_rec: &HitRecord) -> Option<(Ray, Vec3)> {
-        let reflected = reflect(&r_in.direction, &hit_rec.normal)
-            + self.fuzziness * Sphere::random_in_unit_sphere();
-        if Vec3::dot(&reflected, &hit_rec.normal) > 0.0 {
-            Some((Ray::from(hit_rec.p, reflected), self.albedo))
-        } else {
-            None
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        hit_rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Vec3)> {
+        let wo = -Vec3::unit_vector(&r_in.direction);
+        let normal = facing_normal(&wo, hit_rec);
+        if self.distribution.is_smooth() {
+            let reflected = reflect(&-wo, &normal);
+            let weight = self.reflectance(Vec3::dot(&wo, &normal), hit_rec.wavelength);
+            return Some((hit_rec.spawn_ray(reflected), weight));
+        }
+
+        let wo = sampling::to_local(&wo, &normal);
+        if wo.z() == 0.0 {
+            return None;
+        }
+        let h = self.distribution.sample_visible(&wo, sampler.get_2d());
+        let wi = reflect(&-wo, &h);
+        if wi.z() <= 0.0 {
+            return None;
         }
+        // The microfacet density cancels against that of the sample
+        let weight = self.reflectance(Vec3::dot(&wo, &h), hit_rec.wavelength)
+            * self.distribution.g(&wo, &wi)
+            / self.distribution.g1(&wo);
+        Some((hit_rec.spawn_ray(sampling::to_world(&wi, &normal)), weight))
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, hit_rec: &HitRecord) -> Vec3 {
+        let Some((wo, wi, h)) = self.local_half_vector(wo, wi, hit_rec) else {
+            return Vec3::default();
+        };
+        self.reflectance(Vec3::dot(&wo, &h), hit_rec.wavelength)
+            * self.distribution.d(&h)
+            * self.distribution.g(&wo, &wi)
+            / (4.0 * wo.z() * wi.z())
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, hit_rec: &HitRecord) -> f64 {
+        let Some((wo, _, h)) = self.local_half_vector(wo, wi, hit_rec) else {
+            return 0.0;
+        };
+        self.distribution.d_visible(&wo, &h) / (4.0 * Vec3::dot(&wo, &h))
+    }
+
+    fn is_specular(&self) -> bool {
+        self.distribution.is_smooth()
     }
 }
 
+/// Glass and other clear materials with index of refraction `ref_idx`,
+/// reflecting and refracting by the exact Fresnel equations. Rough surfaces
+/// are a Trowbridge-Reitz distribution of smooth facets, as in Walter et al.'s
+/// "Microfacet Models for Refraction through Rough Surfaces". Radiance is not
+/// scaled by the squared ratio of the indices on refraction, which cancels
+/// for closed objects seen from outside.
 pub struct Dielectric {
     ref_idx: f64,
+    distribution: TrowbridgeReitz,
+    absorption: Vec3,
+    dispersion: Option<Dispersion>,
+}
+
+/// Index of refraction as a function of wavelength
+#[derive(Clone, Copy, Debug)]
+pub enum Dispersion {
+    /// n = a + b / λ², with λ in micrometres
+    Cauchy { a: f64, b: f64 },
+    /// n² = 1 + Σ b λ² / (λ² - c), with λ in micrometres
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    /// Borosilicate crown glass, the most common optical glass
+    pub fn bk7() -> Dispersion {
+        Dispersion::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        }
+    }
+
+    /// Dense flint glass, which splits colors about three times as strongly
+    pub fn sf11() -> Dispersion {
+        Dispersion::Sellmeier {
+            b: [1.73759695, 0.313747346, 1.89878101],
+            c: [0.013188707, 0.0623068142, 155.23629],
+        }
+    }
+
+    pub fn diamond() -> Dispersion {
+        Dispersion::Sellmeier {
+            b: [4.3356, 0.3306, 0.0],
+            c: [0.1060 * 0.1060, 0.1750 * 0.1750, 0.0],
+        }
+    }
+
+    /// Index of refraction at `lambda` nanometres
+    pub fn ior(&self, lambda: f64) -> f64 {
+        let lambda2 = (lambda / 1000.0).powi(2);
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / lambda2,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f64 = (0..3).map(|i| b[i] * lambda2 / (lambda2 - c[i])).sum();
+                f64::sqrt(1.0 + sum)
+            }
+        }
+    }
 }
 
 impl Dielectric {
     pub fn new(ref_idx: f64) -> Dielectric {
-        Dielectric { ref_idx }
+        Dielectric {
+            ref_idx,
+            distribution: TrowbridgeReitz::new(0.0),
+            absorption: Vec3::default(),
+            dispersion: None,
+        }
+    }
+
+    /// Frosts the surface, from 0 for polished glass to 1
+    pub fn with_roughness(mut self, roughness: f64) -> Dielectric {
+        self.distribution = TrowbridgeReitz::from_roughness(roughness);
+        self
+    }
+
+    /// Tints the inside by the Beer-Lambert law, so that light keeps a
+    /// fraction exp(-`coefficient` d) of each channel after a distance d.
+    pub fn with_absorption(mut self, coefficient: Vec3) -> Dielectric {
+        self.absorption = coefficient;
+        self
+    }
+
+    /// Bends each wavelength by its own index of refraction, which then
+    /// replaces `ref_idx`. White light is split into a random wavelength where
+    /// it scatters, and rough glass only disperses light that way. In spectral
+    /// rendering the hero wavelength goes on alone.
+    pub fn with_dispersion(mut self, dispersion: Dispersion) -> Dielectric {
+        self.dispersion = Some(dispersion);
+        self
+    }
+
+    // Index of refraction for light of `wavelength`
+    fn ior(&self, wavelength: Option<Wavelengths>) -> f64 {
+        match (self.dispersion, wavelength) {
+            (Some(dispersion), Some(wavelength)) => dispersion.ior(wavelength.hero()),
+            _ => self.ref_idx,
+        }
+    }
+
+    // The normal on the side of `wo` and the ratio of the index of
+    // refraction on the other side to that on the side of `wo`
+    fn orient(
+        &self,
+        wo: &Vec3,
+        hit_rec: &HitRecord,
+        wavelength: Option<Wavelengths>,
+    ) -> (Vec3, f64) {
+        let ior = self.ior(wavelength);
+        if Vec3::dot(wo, &hit_rec.normal) >= 0.0 {
+            (hit_rec.normal, ior)
+        } else {
+            (-hit_rec.normal, 1.0 / ior)
+        }
+    }
+
+    // `wo`, `wi` and the facet normal between them in the frame around the
+    // normal on the side of `wo`, with the ratio of indices and whether `wi`
+    // is reflected, or None if the BSDF is zero for them
+    fn local_half_vector(
+        &self,
+        wo: &Vec3,
+        wi: &Vec3,
+        hit_rec: &HitRecord,
+    ) -> Option<(Vec3, Vec3, Vec3, f64, bool)> {
+        if self.distribution.is_smooth() {
+            return None;
+        }
+        let (normal, eta) = self.orient(wo, hit_rec, hit_rec.wavelength);
+        let wo = sampling::to_local(wo, &normal);
+        let wi = sampling::to_local(wi, &normal);
+        if wo.z() == 0.0 || wi.z() == 0.0 {
+            return None;
+        }
+        let reflected = wi.z() > 0.0;
+        let h = if reflected { wo + wi } else { wo + eta * wi };
+        if h.is_zero() {
+            return None;
+        }
+        let h = Vec3::unit_vector(&h);
+        let h = if h.z() < 0.0 { -h } else { h };
+        // Facets seen from behind by either direction take no part
+        if Vec3::dot(&wo, &h) <= 0.0 || Vec3::dot(&wi, &h) * wi.z() <= 0.0 {
+            return None;
+        }
+        Some((wo, wi, h, eta, reflected))
     }
 }
 
-fn refract(vec: &Vec3, normal: &Vec3, ni_over_nt: f64) -> Option<Vec3> {
-    let unit_vec = Vec3::unit_vector(vec);
-    let dot = Vec3::dot(&unit_vec, normal);
-    let discriminant = 1.0 - ni_over_nt * ni_over_nt * (1.0 - dot * dot);
-    if discriminant > 0.0 {
-        let refracted = ni_over_nt * (unit_vec - normal * dot) - normal * f64::sqrt(discriminant);
-        Some(refracted)
-    } else {
-        None
+// Refracts `w`, pointing away from the surface, through a boundary with
+// `normal` on its side, or None on total internal reflection
+fn refract(w: &Vec3, normal: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = Vec3::dot(w, normal);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
     }
+    let cos_t = f64::sqrt(1.0 - sin2_t);
+    Some(-*w / eta + (cos_i / eta - cos_t) * normal)
 }
 
-fn schlick(cosine: f64, ref_idx: f64) -> f64 {
-    let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
-    r0 = r0 * r0;
-    r0 + (1.0 - r0) * f64::powi(1.0 - cosine, 5)
+// Unpolarized reflectance at the boundary to a medium whose index of
+// refraction is `eta` times that of the medium `cos_i` is measured in
+fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = f64::sqrt(1.0 - sin2_t);
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, hit_rec: &HitRecord) -> Option<(Ray, Vec3)> {
-        let mut outward_normal = hit_rec.normal.clone();
-        let reflected = reflect(&r_in.direction, &hit_rec.normal);
-        let mut ni_over_nt = 1.0 / self.ref_idx;
-        let attenuation = Vec3::from((1.0, 1.0, 1.0));
-        let mut cosine = -Vec3::dot(&r_in.direction, &hit_rec.normal) / r_in.direction.length();
-
-        // Handle total internal reflection
-        if Vec3::dot(&r_in.direction, &hit_rec.normal) > 0.0 {
-            outward_normal *= -1.0;
-            ni_over_nt = self.ref_idx;
-            cosine = self.ref_idx * Vec3::dot(&r_in.direction, &hit_rec.normal)
-                / r_in.direction.length();
-        }
-
-        if let Some(refracted) = refract(&r_in.direction, &outward_normal, ni_over_nt) {
-            let reflect_prob = schlick(cosine, self.ref_idx);
-
-            if random::<f64>() > reflect_prob {
-                return Some((Ray::from(hit_rec.p, refracted), attenuation));
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        hit_rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Vec3)> {
+        let wo = -Vec3::unit_vector(&r_in.direction);
+        // The way to the hit lies inside if it arrives from behind the surface
+        let mut attenuation = if Vec3::dot(&r_in.direction, &hit_rec.normal) > 0.0 {
+            self.transmittance(hit_rec.t * r_in.direction.length(), hit_rec)
+        } else {
+            Vec3::from((1.0, 1.0, 1.0))
+        };
+        let mut wavelength = hit_rec.wavelength;
+        if self.dispersion.is_some() {
+            match wavelength {
+                None => {
+                    let (lambda, pdf) = spectrum::sample_wavelength(sampler.get_1d());
+                    attenuation *= spectrum::wavelength_rgb(lambda, pdf);
+                    wavelength = Some(Wavelengths::Single(lambda));
+                }
+                // The other wavelengths would refract elsewhere, so the hero
+                // carries the estimate for all three
+                Some(Wavelengths::Hero {
+                    lambda,
+                    secondary: true,
+                }) => {
+                    attenuation *= Vec3::from((3.0, 0.0, 0.0));
+                    wavelength = Some(Wavelengths::Hero {
+                        lambda,
+                        secondary: false,
+                    });
+                }
+                _ => {}
             }
         }
+        let (normal, eta) = self.orient(&wo, hit_rec, wavelength);
+        let spawn = |direction: Vec3| hit_rec.spawn_ray(direction).with_wavelength(wavelength);
+        if self.distribution.is_smooth() {
+            let reflect_prob = fresnel_dielectric(Vec3::dot(&wo, &normal), eta);
+            let scattered = match refract(&wo, &normal, eta) {
+                Some(refracted) if sampler.get_1d() > reflect_prob => refracted,
+                _ => reflect(&-wo, &normal),
+            };
+            return Some((spawn(scattered), attenuation));
+        }
+
+        let wo = sampling::to_local(&wo, &normal);
+        if wo.z() == 0.0 {
+            return None;
+        }
+        let h = self.distribution.sample_visible(&wo, sampler.get_2d());
+        let reflect_prob = fresnel_dielectric(Vec3::dot(&wo, &h), eta);
+        let wi = match refract(&wo, &h, eta) {
+            Some(refracted) if sampler.get_1d() > reflect_prob => refracted,
+            _ => reflect(&-wo, &h),
+        };
+        // The chosen direction must end up on the side of the surface it was
+        // sampled for
+        if (wi.z() > 0.0) != (Vec3::dot(&wi, &h) > 0.0) || wi.z() == 0.0 {
+            return None;
+        }
+        // The facet density and Fresnel term cancel against those of the sample
+        let weight = self.distribution.g(&wo, &wi) / self.distribution.g1(&wo) * attenuation;
+        Some((spawn(sampling::to_world(&wi, &normal)), weight))
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, hit_rec: &HitRecord) -> Vec3 {
+        let Some((wo, wi, h, eta, reflected)) = self.local_half_vector(wo, wi, hit_rec) else {
+            return Vec3::default();
+        };
+        let d = self.distribution.d(&h);
+        let g = self.distribution.g(&wo, &wi);
+        let fresnel = fresnel_dielectric(Vec3::dot(&wo, &h), eta);
+        let f = if reflected {
+            fresnel * d * g / (4.0 * wo.z() * wi.z())
+        } else {
+            let denom = (Vec3::dot(&wi, &h) + Vec3::dot(&wo, &h) / eta).powi(2);
+            (1.0 - fresnel) * d * g * f64::abs(Vec3::dot(&wi, &h) * Vec3::dot(&wo, &h))
+                / (wo.z() * wi.z().abs() * denom)
+        };
+        Vec3::from((f, f, f))
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, hit_rec: &HitRecord) -> f64 {
+        let Some((wo, wi, h, eta, reflected)) = self.local_half_vector(wo, wi, hit_rec) else {
+            return 0.0;
+        };
+        let pdf_h = self.distribution.d_visible(&wo, &h);
+        let fresnel = fresnel_dielectric(Vec3::dot(&wo, &h), eta);
+        if reflected {
+            fresnel * pdf_h / (4.0 * Vec3::dot(&wo, &h))
+        } else {
+            let denom = (Vec3::dot(&wi, &h) + Vec3::dot(&wo, &h) / eta).powi(2);
+            (1.0 - fresnel) * pdf_h * Vec3::dot(&wi, &h).abs() / denom
+        }
+    }
+
+    fn transmittance(&self, distance: f64, hit_rec: &HitRecord) -> Vec3 {
+        let absorption = spectrum::spectral(&self.absorption, hit_rec.wavelength);
+        let mut transmittance = Vec3::default();
+        for i in 0..3 {
+            transmittance[i] = f64::exp(-absorption[i] * distance);
+        }
+        transmittance
+    }
+
+    fn radiance_scale(&self, wo: &Vec3, wi: &Vec3, hit_rec: &HitRecord) -> f64 {
+        let (normal, eta) = self.orient(wo, hit_rec, hit_rec.wavelength);
+        if Vec3::dot(wi, &normal) < 0.0 {
+            1.0 / (eta * eta)
+        } else {
+            1.0
+        }
+    }
+
+    fn is_specular(&self) -> bool {
+        self.distribution.is_smooth()
+    }
 
-        Some((Ray::from(hit_rec.p, reflected), attenuation))
+    fn disperses(&self) -> bool {
+        self.dispersion.is_some()
     }
 }
@@ -0,0 +1,113 @@
+//! Trowbridge-Reitz (GGX) distribution of microfacet normals for rough
+//! surfaces. Directions are unit vectors in a local frame around the +z
+//! macrosurface normal.
+
+use std::f64::consts::PI;
+
+use crate::types::Vec3;
+
+/// Below this alpha surfaces are treated as perfectly smooth
+const SMOOTH_ALPHA: f64 = 1e-3;
+
+#[derive(Clone, Copy, Debug)]
+pub struct TrowbridgeReitz {
+    alpha: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha: f64) -> TrowbridgeReitz {
+        TrowbridgeReitz {
+            alpha: f64::max(alpha, 0.0),
+        }
+    }
+
+    /// Distribution with alpha the square of `roughness`, which makes the
+    /// appearance change more evenly with it
+    pub fn from_roughness(roughness: f64) -> TrowbridgeReitz {
+        let roughness = roughness.clamp(0.0, 1.0);
+        TrowbridgeReitz::new(roughness * roughness)
+    }
+
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    /// Whether the surface should be treated as a perfect mirror
+    pub fn is_smooth(&self) -> bool {
+        self.alpha < SMOOTH_ALPHA
+    }
+
+    /// Density of microfacet normals `h` per unit of macrosurface area
+    pub fn d(&self, h: &Vec3) -> f64 {
+        let cos2 = h.z() * h.z();
+        if cos2 == 0.0 {
+            return 0.0;
+        }
+        let alpha2 = self.alpha * self.alpha;
+        let tan2 = (1.0 - cos2) / cos2;
+        let e = 1.0 + tan2 / alpha2;
+        1.0 / (PI * alpha2 * cos2 * cos2 * e * e)
+    }
+
+    // Smith's auxiliary function: the projected area of back-facing
+    // microfacets relative to the macrosurface, seen from `w`
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let cos2 = w.z() * w.z();
+        if cos2 == 0.0 {
+            return f64::INFINITY;
+        }
+        let tan2 = (1.0 - cos2) / cos2;
+        (f64::sqrt(1.0 + self.alpha * self.alpha * tan2) - 1.0) / 2.0
+    }
+
+    /// Fraction of the microfacets seen from `w` that are not masked
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of the microfacets visible from both `wo` and `wi`, taking
+    /// into account that masking and shadowing are correlated by height
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of the normals of the microfacets seen from `w`, per solid
+    /// angle of `h`
+    pub fn d_visible(&self, w: &Vec3, h: &Vec3) -> f64 {
+        let cos = w.z().abs();
+        if cos == 0.0 {
+            return 0.0;
+        }
+        self.g1(w) * f64::max(Vec3::dot(w, h), 0.0) * self.d(h) / cos
+    }
+
+    /// Samples a normal from `d_visible` for `w` in the upper hemisphere,
+    /// following Heitz's "Sampling the GGX Distribution of Visible Normals"
+    pub fn sample_visible(&self, w: &Vec3, u: (f64, f64)) -> Vec3 {
+        // Stretch the view so the microfacets become a hemisphere
+        let wh = Vec3::unit_vector(&Vec3::new(self.alpha * w.x(), self.alpha * w.y(), w.z()));
+        let len2 = wh.x() * wh.x() + wh.y() * wh.y();
+        let t1 = if len2 > 0.0 {
+            Vec3::new(-wh.y(), wh.x(), 0) / len2.sqrt()
+        } else {
+            Vec3::new(1, 0, 0)
+        };
+        let t2 = wh.cross(&t1);
+
+        // Sample the projection of the hemisphere, half of which the view
+        // foreshortens
+        let r = u.0.sqrt();
+        let phi = 2.0 * PI * u.1;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + wh.z());
+        let p2 = (1.0 - s) * f64::sqrt(1.0 - p1 * p1) + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + f64::sqrt(f64::max(0.0, 1.0 - p1 * p1 - p2 * p2)) * wh;
+
+        // Unstretch back to a microfacet normal
+        Vec3::unit_vector(&Vec3::new(
+            self.alpha * nh.x(),
+            self.alpha * nh.y(),
+            f64::max(nh.z(), 1e-6),
+        ))
+    }
+}
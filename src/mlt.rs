@@ -0,0 +1,274 @@
+//! Primary sample space Metropolis light transport. A path is a function of
+//! the random numbers the path tracer consumes, so rather than drawing fresh
+//! numbers for every sample, Markov chains mutate the numbers of the current
+//! path and spend their time where the image is bright. This finds paths that
+//! are hard to hit by chance, such as light coming through a small opening.
+
+use std::f64::consts::PI;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rayon::prelude::*;
+
+use crate::camera::Camera;
+use crate::film::Film;
+use crate::integrator::{Integrator, PathTracer};
+use crate::sampler::{hash, Sampler};
+use crate::scene::Scene;
+use crate::stats::{self, RayCounts};
+use crate::types::Vec3;
+
+#[derive(Clone, Copy, Default)]
+struct PrimarySample {
+    value: f64,
+    // Iteration that last changed `value`
+    last_modified: u64,
+    value_backup: f64,
+    modify_backup: u64,
+}
+
+impl PrimarySample {
+    fn backup(&mut self) {
+        self.value_backup = self.value;
+        self.modify_backup = self.last_modified;
+    }
+
+    fn restore(&mut self) {
+        self.value = self.value_backup;
+        self.last_modified = self.modify_backup;
+    }
+}
+
+/// Sampler that hands out the primary samples of the current path, mutated
+/// either by a small Gaussian step or replaced by fresh numbers in a large
+/// step. Samples are only mutated once they are asked for, so paths may use
+/// any number of them.
+#[derive(Clone)]
+pub struct MltSampler {
+    rng: StdRng,
+    sigma: f64,
+    large_step_probability: f64,
+    samples: Vec<PrimarySample>,
+    current_iteration: u64,
+    large_step: bool,
+    last_large_step_iteration: u64,
+    sample_index: usize,
+}
+
+impl MltSampler {
+    pub fn new(seed: u64, sigma: f64, large_step_probability: f64) -> MltSampler {
+        MltSampler {
+            rng: StdRng::seed_from_u64(seed),
+            sigma,
+            large_step_probability,
+            samples: Vec::new(),
+            current_iteration: 0,
+            large_step: true,
+            last_large_step_iteration: 0,
+            sample_index: 0,
+        }
+    }
+
+    /// Proposes a mutation of the current path
+    pub fn start_iteration(&mut self) {
+        self.current_iteration += 1;
+        self.large_step = self.rng.gen::<f64>() < self.large_step_probability;
+    }
+
+    /// Rewinds to the first sample of the path before evaluating it
+    pub fn start_path(&mut self) {
+        self.sample_index = 0;
+    }
+
+    /// Makes the proposed path the current one
+    pub fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step_iteration = self.current_iteration;
+        }
+    }
+
+    /// Goes back to the path before the proposed mutation
+    pub fn reject(&mut self) {
+        for sample in self.samples.iter_mut() {
+            if sample.last_modified == self.current_iteration {
+                sample.restore();
+            }
+        }
+        self.current_iteration -= 1;
+    }
+
+    fn ensure_ready(&mut self, index: usize) {
+        if index >= self.samples.len() {
+            self.samples.resize(index + 1, PrimarySample::default());
+        }
+        let sample = &mut self.samples[index];
+
+        // Samples not touched since the last accepted large step would have
+        // been replaced by it
+        if sample.last_modified < self.last_large_step_iteration {
+            sample.value = self.rng.gen();
+            sample.last_modified = self.last_large_step_iteration;
+        }
+
+        sample.backup();
+        if self.large_step {
+            sample.value = self.rng.gen();
+        } else {
+            // Catch up on the small steps missed since the sample was last
+            // used, which add up to one step with a wider Gaussian
+            let missed = (self.current_iteration - sample.last_modified) as f64;
+            let sigma = self.sigma * f64::sqrt(missed);
+            let (u1, u2): (f64, f64) = (self.rng.gen(), self.rng.gen());
+            let normal = f64::sqrt(-2. * f64::ln(1. - u1)) * f64::cos(2. * PI * u2);
+            sample.value += normal * sigma;
+            sample.value -= f64::floor(sample.value);
+        }
+        sample.last_modified = self.current_iteration;
+    }
+}
+
+impl Sampler for MltSampler {
+    fn get_1d(&mut self) -> f64 {
+        let index = self.sample_index;
+        self.sample_index += 1;
+        self.ensure_ready(index);
+        self.samples[index].value
+    }
+}
+
+/// Renders with Markov chains over the primary samples of a path tracer.
+/// Bootstrap paths estimate the overall image brightness and pick where the
+/// chains start, and every mutation splats both the proposed and the current
+/// path weighted by their acceptance probability.
+pub struct MetropolisLightTransport {
+    path_tracer: PathTracer,
+    bootstrap_samples: usize,
+    chains: usize,
+    mutations_per_pixel: usize,
+    sigma: f64,
+    large_step_probability: f64,
+    seed: u64,
+}
+
+impl MetropolisLightTransport {
+    pub fn new(
+        max_depth: usize,
+        bootstrap_samples: usize,
+        chains: usize,
+        mutations_per_pixel: usize,
+        seed: u64,
+    ) -> MetropolisLightTransport {
+        MetropolisLightTransport {
+            path_tracer: PathTracer::new(max_depth),
+            bootstrap_samples,
+            chains,
+            mutations_per_pixel,
+            sigma: 0.01,
+            large_step_probability: 0.3,
+            seed,
+        }
+    }
+
+    /// Traces paths at three wavelengths, as `PathTracer::with_spectral`
+    pub fn with_spectral(mut self, spectral: bool) -> MetropolisLightTransport {
+        self.path_tracer = self.path_tracer.with_spectral(spectral);
+        self
+    }
+
+    /// Renders into `film` and returns the rays traced
+    pub fn render(&self, scene: &Scene, camera: &dyn Camera, film: &mut Film) -> RayCounts {
+        let bootstrap: Vec<(f64, RayCounts)> = (0..self.bootstrap_samples)
+            .into_par_iter()
+            .map(|i| {
+                let mut sampler = self.bootstrap_sampler(i);
+                let ((l, _), rays) =
+                    stats::counted(|| self.radiance(scene, camera, film, &mut sampler));
+                (l.luminance(), rays)
+            })
+            .collect();
+        let mut rays: RayCounts = bootstrap.iter().map(|(_, rays)| *rays).sum();
+        let bootstrap_weights: Vec<f64> = bootstrap.iter().map(|(weight, _)| *weight).collect();
+        let total_weight: f64 = bootstrap_weights.iter().sum();
+        if total_weight <= 0. {
+            return rays;
+        }
+        let b = total_weight / self.bootstrap_samples as f64;
+        let cdf: Vec<f64> = bootstrap_weights
+            .iter()
+            .scan(0., |sum, w| {
+                *sum += w / total_weight;
+                Some(*sum)
+            })
+            .collect();
+
+        let num_pixels = film.get_width() * film.get_height();
+        let mutations_per_chain = self.mutations_per_pixel * num_pixels / self.chains;
+        let shared: &Film = film;
+        let chain_rays: RayCounts = (0..self.chains)
+            .into_par_iter()
+            .map(|chain| {
+                let mut rng = StdRng::seed_from_u64(hash(&[self.seed, 1, chain as u64]));
+                // Start from a bootstrap path chosen in proportion to its
+                // brightness by replaying its samples
+                let u: f64 = rng.gen();
+                let index = usize::min(cdf.partition_point(|&c| c < u), cdf.len() - 1);
+                let mut sampler = self.bootstrap_sampler(index);
+                let ((), rays) = stats::counted(|| {
+                    let mut current = self.radiance(scene, camera, shared, &mut sampler);
+
+                    for _ in 0..mutations_per_chain {
+                        sampler.start_iteration();
+                        let proposed = self.radiance(scene, camera, shared, &mut sampler);
+                        let accept = f64::min(1., proposed.0.luminance() / current.0.luminance());
+                        if accept > 0. {
+                            shared.add_splat(
+                                proposed.1,
+                                proposed.0 * accept / proposed.0.luminance(),
+                            );
+                        }
+                        shared.add_splat(
+                            current.1,
+                            current.0 * (1. - accept) / current.0.luminance(),
+                        );
+
+                        if rng.gen::<f64>() < accept {
+                            current = proposed;
+                            sampler.accept();
+                        } else {
+                            sampler.reject();
+                        }
+                    }
+                });
+                rays
+            })
+            .sum();
+        rays = rays + chain_rays;
+
+        let total_mutations = mutations_per_chain * self.chains;
+        film.set_splat_scale(b * num_pixels as f64 / usize::max(total_mutations, 1) as f64);
+        rays
+    }
+
+    // Sampler that replays bootstrap path `index`
+    fn bootstrap_sampler(&self, index: usize) -> MltSampler {
+        let seed = hash(&[self.seed, 0, index as u64]);
+        MltSampler::new(seed, self.sigma, self.large_step_probability)
+    }
+
+    // Radiance of the path made from the sampler's primary samples, with the
+    // film position it goes through taken from the first two
+    fn radiance(
+        &self,
+        scene: &Scene,
+        camera: &dyn Camera,
+        film: &Film,
+        sampler: &mut MltSampler,
+    ) -> (Vec3, (f64, f64)) {
+        sampler.start_path();
+        let film_pos = sampler.get_2d();
+        let l = match camera.get_ray(film_pos.0, film_pos.1, sampler) {
+            Some((ray, weight)) => weight * self.path_tracer.li(&ray, scene, camera, film, sampler),
+            None => Vec3::default(),
+        };
+        (l, film_pos)
+    }
+}
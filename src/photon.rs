@@ -0,0 +1,461 @@
+//! Photon mapping. Photons are shot from the lights and stored wherever they
+//! land on non-specular surfaces after at least one bounce; camera rays follow
+//! specular bounces to the first non-specular surface, light it directly by
+//! sampling the lights and estimate everything else from the photon density
+//! around the hit.
+
+use std::f64::consts::PI;
+
+use rayon::prelude::*;
+
+use crate::camera::Camera;
+use crate::film::Film;
+use crate::hittable::HitRecord;
+use crate::integrator::{estimate_direct, Integrator};
+use crate::sampler::{hash, IndependentSampler, Sampler};
+use crate::scene::Scene;
+use crate::stats::{self, Counter, RayCounts};
+use crate::types::{Ray, Vec3};
+
+/// Static kd-tree over points, stored implicitly in one array with every node
+/// at the median of its subtree's range
+pub struct KdTree<T> {
+    nodes: Vec<KdNode<T>>,
+}
+
+struct KdNode<T> {
+    p: Vec3,
+    axis: usize,
+    item: T,
+}
+
+impl<T> KdTree<T> {
+    pub fn new(items: Vec<(Vec3, T)>) -> KdTree<T> {
+        let mut nodes: Vec<KdNode<T>> = items
+            .into_iter()
+            .map(|(p, item)| KdNode { p, axis: 0, item })
+            .collect();
+        build(&mut nodes);
+        KdTree { nodes }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Calls `visit` with every item within `radius` of `p`
+    pub fn for_each_within(&self, p: &Vec3, radius: f64, mut visit: impl FnMut(&Vec3, &T)) {
+        search(&self.nodes, p, radius, &mut visit);
+    }
+}
+
+fn build<T>(nodes: &mut [KdNode<T>]) {
+    if nodes.len() <= 1 {
+        return;
+    }
+
+    // Split along the axis with the largest extent
+    let (mut min, mut max) = (nodes[0].p, nodes[0].p);
+    for node in nodes.iter() {
+        for axis in 0..3 {
+            min[axis] = f64::min(min[axis], node.p[axis]);
+            max[axis] = f64::max(max[axis], node.p[axis]);
+        }
+    }
+    let extent = max - min;
+    let axis = (0..3)
+        .max_by(|&a, &b| extent[a].total_cmp(&extent[b]))
+        .unwrap();
+
+    let mid = nodes.len() / 2;
+    nodes.select_nth_unstable_by(mid, |a, b| a.p[axis].total_cmp(&b.p[axis]));
+    nodes[mid].axis = axis;
+    let (left, rest) = nodes.split_at_mut(mid);
+    build(left);
+    build(&mut rest[1..]);
+}
+
+fn search<T>(nodes: &[KdNode<T>], p: &Vec3, radius: f64, visit: &mut impl FnMut(&Vec3, &T)) {
+    if nodes.is_empty() {
+        return;
+    }
+
+    let mid = nodes.len() / 2;
+    let node = &nodes[mid];
+    if (node.p - *p).squared_len() <= radius * radius {
+        visit(&node.p, &node.item);
+    }
+    if nodes.len() == 1 {
+        return;
+    }
+
+    let d = p[node.axis] - node.p[node.axis];
+    let (near, far) = if d < 0. {
+        (&nodes[..mid], &nodes[mid + 1..])
+    } else {
+        (&nodes[mid + 1..], &nodes[..mid])
+    };
+    search(near, p, radius, visit);
+    if d.abs() <= radius {
+        search(far, p, radius, visit);
+    }
+}
+
+/// Follows one photon from a light through the scene, calling `visit` with
+/// every non-specular surface it lands on after bouncing at least once, the
+/// direction it arrived from and its power
+fn trace_photon(
+    scene: &Scene,
+    max_depth: usize,
+    sampler: &mut dyn Sampler,
+    mut visit: impl FnMut(&HitRecord, &Vec3, Vec3),
+) {
+    let (light, light_pdf) = match scene.choose_light(sampler.get_1d()) {
+        Some(choice) => choice,
+        None => return,
+    };
+    let emission = match light.sample_le(sampler.get_2d(), sampler.get_2d(), scene.bounds()) {
+        Some(emission) if emission.pdf_pos > 0. && emission.pdf_dir > 0. => emission,
+        _ => return,
+    };
+    stats::count(Counter::LightPaths, 1);
+
+    let dir = Vec3::unit_vector(&emission.ray.direction);
+    let mut beta = emission.radiance * f64::abs(Vec3::dot(&emission.normal, &dir))
+        / (light_pdf * emission.pdf_pos * emission.pdf_dir);
+    let mut ray = Ray::from(emission.ray.origin, dir);
+    for depth in 0..max_depth {
+        let hit_rec = match scene.hit(&ray) {
+            Some(hit_rec) => hit_rec,
+            None => return,
+        };
+        if depth > 0 && !hit_rec.mat.is_specular() {
+            visit(&hit_rec, &-Vec3::unit_vector(&ray.direction), beta);
+        }
+
+        match hit_rec.mat.scatter(&ray, &hit_rec, sampler) {
+            Some((scattered, attenuation)) => {
+                beta *= attenuation;
+                ray = scattered;
+            }
+            None => return,
+        }
+        if beta.is_zero() {
+            return;
+        }
+    }
+}
+
+// Photons are traced in batches of this size, each batch with its own sampler
+const PHOTON_BATCH: usize = 4096;
+
+/// Traces `num_photons` photons in parallel and returns everything `visit`
+/// pushed for them, in the same order whatever the number of threads, and the
+/// rays traced
+fn trace_photons<T: Send>(
+    scene: &Scene,
+    max_depth: usize,
+    num_photons: usize,
+    seed: u64,
+    visit: impl Fn(&HitRecord, &Vec3, Vec3, &mut Vec<T>) + Sync,
+) -> (Vec<T>, RayCounts) {
+    let batches: Vec<(Vec<T>, RayCounts)> = (0..num_photons.div_ceil(PHOTON_BATCH))
+        .into_par_iter()
+        .map(|batch| {
+            let mut sampler = IndependentSampler::new(hash(&[seed, batch as u64]));
+            let mut found = Vec::new();
+            let count = usize::min(PHOTON_BATCH, num_photons - batch * PHOTON_BATCH);
+            let ((), rays) = stats::counted(|| {
+                for _ in 0..count {
+                    trace_photon(scene, max_depth, &mut sampler, |hit_rec, wi, power| {
+                        visit(hit_rec, wi, power, &mut found)
+                    });
+                }
+            });
+            (found, rays)
+        })
+        .collect();
+    let rays = batches.iter().map(|(_, rays)| *rays).sum();
+    (
+        batches.into_iter().flat_map(|(found, _)| found).collect(),
+        rays,
+    )
+}
+
+/// Follows a camera ray through specular bounces, adding emission picked up on
+/// the way to `l`. Returns the first non-specular hit together with the
+/// direction towards the camera and the path throughput.
+fn trace_visible_point(
+    scene: &Scene,
+    ray: &Ray,
+    max_depth: usize,
+    l: &mut Vec3,
+    sampler: &mut dyn Sampler,
+) -> Option<(HitRecord, Vec3, Vec3)> {
+    let mut ray = *ray;
+    let mut beta = Vec3::from((1., 1., 1.));
+    for _ in 0..max_depth {
+        let hit_rec = match scene.hit(&ray) {
+            Some(hit_rec) => hit_rec,
+            None => {
+                *l += beta * scene.escaped(&ray);
+                return None;
+            }
+        };
+        *l += beta * hit_rec.mat.emitted(&ray, &hit_rec);
+
+        let wo = -Vec3::unit_vector(&ray.direction);
+        if !hit_rec.mat.is_specular() {
+            return Some((hit_rec, wo, beta));
+        }
+        let (scattered, attenuation) = hit_rec.mat.scatter(&ray, &hit_rec, sampler)?;
+        beta *= attenuation;
+        ray = scattered;
+    }
+
+    None
+}
+
+struct Photon {
+    // Unit direction the photon arrived from
+    wi: Vec3,
+    power: Vec3,
+}
+
+/// Classic photon mapping with a single photon map built up front and a fixed
+/// gather radius
+pub struct PhotonMapper {
+    photons: KdTree<Photon>,
+    num_photons: usize,
+    radius: f64,
+    max_depth: usize,
+    rays: RayCounts,
+}
+
+impl PhotonMapper {
+    /// Shoots `num_photons` photons into `scene`, with random numbers derived
+    /// from `seed`, and stores them
+    pub fn new(
+        scene: &Scene,
+        num_photons: usize,
+        radius: f64,
+        max_depth: usize,
+        seed: u64,
+    ) -> PhotonMapper {
+        let (photons, rays) = trace_photons(
+            scene,
+            max_depth,
+            num_photons,
+            seed,
+            |hit_rec, wi, power, photons| photons.push((hit_rec.p, Photon { wi: *wi, power })),
+        );
+
+        PhotonMapper {
+            photons: KdTree::new(photons),
+            num_photons,
+            radius,
+            max_depth,
+            rays,
+        }
+    }
+
+    /// Number of photons stored in the map
+    pub fn stored_photons(&self) -> usize {
+        self.photons.len()
+    }
+
+    /// Rays traced building the map
+    pub fn rays(&self) -> RayCounts {
+        self.rays
+    }
+}
+
+impl Integrator for PhotonMapper {
+    fn li(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        _camera: &dyn Camera,
+        _film: &Film,
+        sampler: &mut dyn Sampler,
+    ) -> Vec3 {
+        let mut l = Vec3::default();
+        let (hit_rec, wo, beta) =
+            match trace_visible_point(scene, ray, self.max_depth, &mut l, sampler) {
+                Some(visible) => visible,
+                None => return l,
+            };
+
+        let mut indirect = Vec3::default();
+        self.photons
+            .for_each_within(&hit_rec.p, self.radius, |_, photon| {
+                indirect += hit_rec.mat.eval(&wo, &photon.wi, &hit_rec) * photon.power;
+            });
+        indirect /= self.num_photons as f64 * PI * self.radius * self.radius;
+
+        l + beta * (estimate_direct(scene, &hit_rec, &wo, false, sampler) + indirect)
+    }
+}
+
+/// Stochastic progressive photon mapping. Every pass finds a new visible point
+/// in each pixel, shoots a fresh batch of photons and shrinks the gather radius
+/// of every pixel that received photons, so the estimate converges to the
+/// correct result as passes accumulate.
+pub struct ProgressivePhotonMapper {
+    photons_per_pass: usize,
+    initial_radius: f64,
+    max_depth: usize,
+    seed: u64,
+    // Fraction of new photons kept in each pass
+    alpha: f64,
+}
+
+struct VisiblePoint {
+    hit_rec: HitRecord,
+    wo: Vec3,
+    beta: Vec3,
+}
+
+struct SppmPixel {
+    radius: f64,
+    ld: Vec3,
+    n: f64,
+    tau: Vec3,
+    vp: Option<VisiblePoint>,
+    phi: Vec3,
+    m: usize,
+}
+
+impl ProgressivePhotonMapper {
+    pub fn new(
+        photons_per_pass: usize,
+        initial_radius: f64,
+        max_depth: usize,
+        seed: u64,
+    ) -> ProgressivePhotonMapper {
+        ProgressivePhotonMapper {
+            photons_per_pass,
+            initial_radius,
+            max_depth,
+            seed,
+            alpha: 2. / 3.,
+        }
+    }
+
+    /// Renders `passes` passes into `film` and returns the rays traced
+    pub fn render(
+        &self,
+        scene: &Scene,
+        camera: &dyn Camera,
+        film: &mut Film,
+        passes: usize,
+    ) -> RayCounts {
+        let width = film.get_width();
+        let height = film.get_height();
+        let mut pixels: Vec<SppmPixel> = (0..width * height)
+            .map(|_| SppmPixel {
+                radius: self.initial_radius,
+                ld: Vec3::default(),
+                n: 0.,
+                tau: Vec3::default(),
+                vp: None,
+                phi: Vec3::default(),
+                m: 0,
+            })
+            .collect();
+
+        let mut rays = RayCounts::default();
+        for pass in 0..passes {
+            let camera_rays: RayCounts = pixels
+                .par_iter_mut()
+                .enumerate()
+                .map_init(
+                    || IndependentSampler::new(self.seed),
+                    |sampler, (idx, pixel)| {
+                        let (x, y) = (idx % width, idx / width);
+                        sampler.start_pixel_sample((x, y), pass);
+                        let (du, dv) = sampler.get_2d();
+                        let u = (x as f64 + du) / (width as f64);
+                        let v = (y as f64 + dv) / (height as f64);
+                        let (mut l, mut weight) = (Vec3::default(), 0.);
+                        let (vp, rays) = stats::counted(|| {
+                            camera.get_ray(u, v, sampler).and_then(|(ray, ray_weight)| {
+                                weight = ray_weight;
+                                trace_visible_point(scene, &ray, self.max_depth, &mut l, sampler)
+                                    .map(|(hit_rec, wo, beta)| {
+                                        l += beta
+                                            * estimate_direct(scene, &hit_rec, &wo, false, sampler);
+                                        let beta = ray_weight * beta;
+                                        VisiblePoint { hit_rec, wo, beta }
+                                    })
+                            })
+                        });
+                        pixel.vp = vp;
+                        pixel.ld += weight * l;
+                        rays
+                    },
+                )
+                .sum();
+            rays = rays + camera_rays;
+
+            let max_radius = pixels.iter().map(|p| p.radius).fold(0., f64::max);
+            let visible_points = KdTree::new(
+                pixels
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(idx, pixel)| pixel.vp.as_ref().map(|vp| (vp.hit_rec.p, idx)))
+                    .collect(),
+            );
+
+            let (contributions, photon_rays) = trace_photons(
+                scene,
+                self.max_depth,
+                self.photons_per_pass,
+                hash(&[self.seed, pass as u64]),
+                |hit_rec, wi, power, found| {
+                    visible_points.for_each_within(&hit_rec.p, max_radius, |p, &idx| {
+                        let pixel = &pixels[idx];
+                        if (*p - hit_rec.p).squared_len() > pixel.radius * pixel.radius {
+                            return;
+                        }
+                        let vp = pixel.vp.as_ref().unwrap();
+                        found.push((idx, vp.hit_rec.mat.eval(&vp.wo, wi, &vp.hit_rec) * power));
+                    });
+                },
+            );
+            rays = rays + photon_rays;
+            for (idx, phi) in contributions {
+                pixels[idx].phi += phi;
+                pixels[idx].m += 1;
+            }
+
+            for pixel in pixels.iter_mut() {
+                let m = pixel.m;
+                if m > 0 {
+                    let n_new = pixel.n + self.alpha * m as f64;
+                    let radius_new = pixel.radius * f64::sqrt(n_new / (pixel.n + m as f64));
+                    let beta = pixel.vp.as_ref().unwrap().beta;
+                    pixel.tau = (pixel.tau + beta * pixel.phi) * (radius_new * radius_new)
+                        / (pixel.radius * pixel.radius);
+                    pixel.n = n_new;
+                    pixel.radius = radius_new;
+                    pixel.phi = Vec3::default();
+                    pixel.m = 0;
+                }
+                pixel.vp = None;
+            }
+        }
+
+        let total_photons = (passes * self.photons_per_pass) as f64;
+        for (idx, pixel) in pixels.iter().enumerate() {
+            let indirect = pixel.tau / (total_photons * PI * pixel.radius * pixel.radius);
+            let l = pixel.ld / passes as f64 + indirect;
+            film.add_samples(idx % width, idx / width, l, 1);
+        }
+        rays
+    }
+}
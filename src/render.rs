@@ -0,0 +1,365 @@
+use std::ops::Range;
+
+use rayon::prelude::*;
+
+use crate::camera::Camera;
+use crate::film::{Film, FilmTile};
+use crate::integrator::Integrator;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::stats::{self, RayCounts};
+use crate::types::Vec3;
+use crate::Ppm;
+
+// Rows of pixels rendered together, into one film tile
+const BAND_ROWS: usize = 8;
+
+/// Takes `num_samples` samples in every pixel of `film`, rendering bands of
+/// rows in parallel with a copy of `sampler` each, and returns the rays traced
+pub fn render(
+    scene: &Scene,
+    camera: &dyn Camera,
+    integrator: &dyn Integrator,
+    sampler: &dyn Sampler,
+    film: &mut Film,
+    num_samples: usize,
+) -> RayCounts {
+    render_samples(scene, camera, integrator, sampler, film, 0..num_samples)
+}
+
+/// Settings for progressive rendering, which takes `num_samples` samples per
+/// pixel in passes of `samples_per_pass` over the whole image
+pub struct ProgressiveRendering {
+    num_samples: usize,
+    samples_per_pass: usize,
+    samples_taken: usize,
+}
+
+impl ProgressiveRendering {
+    pub fn new(num_samples: usize, samples_per_pass: usize) -> ProgressiveRendering {
+        ProgressiveRendering {
+            num_samples,
+            samples_per_pass: usize::max(samples_per_pass, 1),
+            samples_taken: 0,
+        }
+    }
+
+    /// Continues a render whose film already holds `samples_taken` samples
+    /// per pixel, such as one restored from a checkpoint. Resuming after a
+    /// pass gives the same image as not stopping.
+    pub fn resume_from(self, samples_taken: usize) -> ProgressiveRendering {
+        ProgressiveRendering {
+            samples_taken,
+            ..self
+        }
+    }
+}
+
+/// Like `render`, but takes the samples in passes and calls `after_pass`
+/// with the film and the number of samples per pixel taken so far after
+/// every pass, so the image can be looked at while it converges. The samples
+/// are the same ones `render` takes.
+pub fn render_progressive(
+    scene: &Scene,
+    camera: &dyn Camera,
+    integrator: &dyn Integrator,
+    sampler: &dyn Sampler,
+    film: &mut Film,
+    settings: &ProgressiveRendering,
+    mut after_pass: impl FnMut(&Film, usize),
+) -> RayCounts {
+    let mut rays = RayCounts::default();
+    let mut taken = settings.samples_taken;
+    while taken < settings.num_samples {
+        let end = usize::min(taken + settings.samples_per_pass, settings.num_samples);
+        rays = rays + render_samples(scene, camera, integrator, sampler, film, taken..end);
+        taken = end;
+        after_pass(film, taken);
+    }
+    rays
+}
+
+// Takes the samples with indices in `samples` in every pixel
+fn render_samples(
+    scene: &Scene,
+    camera: &dyn Camera,
+    integrator: &dyn Integrator,
+    sampler: &dyn Sampler,
+    film: &mut Film,
+    samples: Range<usize>,
+) -> RayCounts {
+    let width = film.get_width();
+    let height = film.get_height();
+
+    let shared: &Film = film;
+    let tiles: Vec<(FilmTile, RayCounts)> = (0..height.div_ceil(BAND_ROWS))
+        .into_par_iter()
+        .map(|band| {
+            let rows = band * BAND_ROWS..usize::min((band + 1) * BAND_ROWS, height);
+            let mut tile = shared.tile(rows.clone());
+            let mut sampler = sampler.clone_sampler();
+            let ((), rays) = stats::counted(|| {
+                for y in rows {
+                    for x in 0..width {
+                        for index in samples.clone() {
+                            let (l, pos) = sample_pixel(
+                                scene,
+                                camera,
+                                integrator,
+                                sampler.as_mut(),
+                                shared,
+                                (x, y),
+                                index,
+                            );
+                            tile.add_sample(pos, l);
+                        }
+                    }
+                }
+            });
+            (tile, rays)
+        })
+        .collect();
+
+    let mut rays = RayCounts::default();
+    for (tile, tile_rays) in tiles {
+        film.merge_tile(tile);
+        rays = rays + tile_rays;
+    }
+    rays
+}
+
+/// Settings for adaptive sampling. Every pixel takes `min_samples` samples,
+/// then the image keeps taking batches of as many again in the pixels that
+/// have not converged, until they reach `max_samples`. A pixel has converged
+/// once the standard error of its brightness after gamma correction, and
+/// that of its neighbours, is below `threshold`; checking the neighbours too
+/// keeps pixels from stopping just because their first samples all missed a
+/// rare bright path.
+pub struct AdaptiveSampling {
+    min_samples: usize,
+    max_samples: usize,
+    threshold: f64,
+}
+
+impl AdaptiveSampling {
+    pub fn new(min_samples: usize, max_samples: usize, threshold: f64) -> AdaptiveSampling {
+        // The variance needs at least two samples
+        let min_samples = usize::max(min_samples, 2);
+        AdaptiveSampling {
+            min_samples,
+            max_samples: usize::max(max_samples, min_samples),
+            threshold,
+        }
+    }
+
+    /// Standard error of a pixel relative to what is allowed, so pixels with
+    /// values up to 1 have converged
+    fn relative_error(&self, stats: &PixelStats) -> f64 {
+        let error = f64::sqrt(stats.variance_of_mean());
+        if error == 0. {
+            return 0.;
+        }
+        // Gamma 2 turns an error e at brightness m into about e / (2 sqrt(m))
+        error / (self.threshold * 2. * f64::sqrt(f64::max(stats.mean(), 0.)))
+    }
+}
+
+/// Running sums of the brightness of a pixel's samples
+#[derive(Default)]
+struct PixelStats {
+    n: usize,
+    sum: f64,
+    sum_squares: f64,
+}
+
+impl PixelStats {
+    fn add(&mut self, l: &Vec3) {
+        let y = l.luminance();
+        self.n += 1;
+        self.sum += y;
+        self.sum_squares += y * y;
+    }
+
+    fn mean(&self) -> f64 {
+        self.sum / self.n as f64
+    }
+
+    fn variance_of_mean(&self) -> f64 {
+        let mean = self.mean();
+        let variance =
+            f64::max(self.sum_squares - self.n as f64 * mean * mean, 0.) / (self.n - 1) as f64;
+        variance / self.n as f64
+    }
+}
+
+/// How many samples every pixel took during adaptive sampling and the
+/// variance of its estimate
+pub struct SampleMaps {
+    width: usize,
+    height: usize,
+    counts: Vec<usize>,
+    variances: Vec<f64>,
+}
+
+impl SampleMaps {
+    pub fn sample_count(&self, x: usize, y: usize) -> usize {
+        self.counts[y * self.width + x]
+    }
+
+    /// Variance of the mean brightness of pixel (x, y)
+    pub fn variance(&self, x: usize, y: usize) -> f64 {
+        self.variances[y * self.width + x]
+    }
+
+    pub fn total_samples(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    /// Sample counts as a grey image, white for the pixel with the most
+    pub fn sample_count_image(&self) -> Ppm {
+        let max = self.counts.iter().copied().max().unwrap_or(0);
+        self.to_ppm(|idx| self.counts[idx] as f64 / usize::max(max, 1) as f64)
+    }
+
+    /// Standard error of every pixel as a grey image, white for the noisiest
+    pub fn variance_image(&self) -> Ppm {
+        let max = self.variances.iter().copied().fold(0., f64::max);
+        self.to_ppm(|idx| {
+            if max > 0. {
+                f64::sqrt(self.variances[idx] / max)
+            } else {
+                0.
+            }
+        })
+    }
+
+    fn to_ppm(&self, value: impl Fn(usize) -> f64) -> Ppm {
+        let mut ppm = Ppm::from(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let grey = value(y * self.width + x) * 255.99;
+                ppm.set_pixel(x, y, Vec3::from((grey, grey, grey)));
+            }
+        }
+        ppm
+    }
+}
+
+/// Like `render`, but stops sampling pixels once they have converged
+/// according to `settings`
+pub fn render_adaptive(
+    scene: &Scene,
+    camera: &dyn Camera,
+    integrator: &dyn Integrator,
+    sampler: &dyn Sampler,
+    film: &mut Film,
+    settings: &AdaptiveSampling,
+) -> (SampleMaps, RayCounts) {
+    let width = film.get_width();
+    let height = film.get_height();
+
+    let mut pixels: Vec<PixelStats> = (0..width * height).map(|_| PixelStats::default()).collect();
+    let mut active = vec![true; width * height];
+    let mut target = settings.min_samples;
+    let mut rays = RayCounts::default();
+    loop {
+        let shared: &Film = film;
+        let active_pixels = &active;
+        let tiles: Vec<(FilmTile, RayCounts)> = pixels
+            .par_chunks_mut(width * BAND_ROWS)
+            .enumerate()
+            .map(|(band, band_pixels)| {
+                let first_row = band * BAND_ROWS;
+                let mut tile = shared.tile(first_row..first_row + band_pixels.len() / width);
+                let mut sampler = sampler.clone_sampler();
+                let ((), rays) = stats::counted(|| {
+                    for (i, stats) in band_pixels.iter_mut().enumerate() {
+                        let (x, y) = (i % width, first_row + i / width);
+                        if !active_pixels[y * width + x] {
+                            continue;
+                        }
+                        while stats.n < target {
+                            let (l, pos) = sample_pixel(
+                                scene,
+                                camera,
+                                integrator,
+                                sampler.as_mut(),
+                                shared,
+                                (x, y),
+                                stats.n,
+                            );
+                            tile.add_sample(pos, l);
+                            stats.add(&l);
+                        }
+                    }
+                });
+                (tile, rays)
+            })
+            .collect();
+        for (tile, tile_rays) in tiles {
+            film.merge_tile(tile);
+            rays = rays + tile_rays;
+        }
+
+        if target >= settings.max_samples {
+            break;
+        }
+        let errors: Vec<f64> = pixels
+            .iter()
+            .map(|stats| settings.relative_error(stats))
+            .collect();
+        for (idx, is_active) in active.iter_mut().enumerate() {
+            let (x, y) = (idx % width, idx / width);
+            let neighbourhood_error = (y.saturating_sub(1)..usize::min(y + 2, height))
+                .flat_map(|ny| {
+                    (x.saturating_sub(1)..usize::min(x + 2, width)).map(move |nx| (nx, ny))
+                })
+                .map(|(nx, ny)| errors[ny * width + nx])
+                .fold(0., f64::max);
+            *is_active = *is_active && neighbourhood_error > 1.;
+        }
+        if !active.contains(&true) {
+            break;
+        }
+        target = usize::min(target + settings.min_samples, settings.max_samples);
+    }
+
+    let mut counts = Vec::with_capacity(width * height);
+    let mut variances = Vec::with_capacity(width * height);
+    for stats in pixels {
+        counts.push(stats.n);
+        variances.push(stats.variance_of_mean());
+    }
+
+    let maps = SampleMaps {
+        width,
+        height,
+        counts,
+        variances,
+    };
+    (maps, rays)
+}
+
+/// Radiance of sample `index` of `pixel` and where in the film, in pixels,
+/// it was taken
+fn sample_pixel(
+    scene: &Scene,
+    camera: &dyn Camera,
+    integrator: &dyn Integrator,
+    sampler: &mut dyn Sampler,
+    film: &Film,
+    pixel: (usize, usize),
+    index: usize,
+) -> (Vec3, (f64, f64)) {
+    sampler.start_pixel_sample(pixel, index);
+    let (du, dv) = sampler.get_2d();
+    let pos = (pixel.0 as f64 + du, pixel.1 as f64 + dv);
+    let u = pos.0 / (film.get_width() as f64);
+    let v = pos.1 / (film.get_height() as f64);
+
+    let l = match camera.get_ray(u, v, sampler) {
+        Some((ray, weight)) => weight * integrator.li(&ray, scene, camera, film, sampler),
+        None => Vec3::default(),
+    };
+    (l, pos)
+}
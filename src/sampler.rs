@@ -0,0 +1,520 @@
+//! Samplers decide the numbers every pixel sample is built from. Besides
+//! independent random numbers there are samplers that spread the samples of a
+//! pixel evenly over each dimension, which lowers the noise at a given number
+//! of samples per pixel.
+
+use std::sync::OnceLock;
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+/// Source of the random numbers used while rendering. Cameras, materials and
+/// integrators draw from a sampler instead of the thread RNG, so that samplers
+/// can control, and replay, the numbers a path is built from.
+pub trait Sampler: CloneSampler + Send + Sync {
+    /// Called before every pixel sample. Samplers that spread samples over a
+    /// pixel use it to restart their sequence at the first dimension.
+    fn start_pixel_sample(&mut self, _pixel: (usize, usize), _sample_index: usize) {}
+
+    /// Next sample in [0, 1)
+    fn get_1d(&mut self) -> f64;
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+/// Lets renderers give every thread its own copy of a sampler
+pub trait CloneSampler {
+    fn clone_sampler(&self) -> Box<dyn Sampler>;
+}
+
+impl<T: Sampler + Clone + 'static> CloneSampler for T {
+    fn clone_sampler(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+/// Independent uniform random numbers. Every pixel sample restarts the
+/// generator from a hash of the pixel, the sample index and `seed`, so samples
+/// do not depend on the order pixels are rendered in.
+#[derive(Clone)]
+pub struct IndependentSampler {
+    seed: u64,
+    // Counter that is hashed into every number, SplitMix style, which is cheap
+    // enough to restart for every sample
+    state: u64,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> IndependentSampler {
+        IndependentSampler { seed, state: seed }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, pixel: (usize, usize), sample_index: usize) {
+        self.state = hash(&[
+            pixel.0 as u64,
+            pixel.1 as u64,
+            sample_index as u64,
+            self.seed,
+        ]);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        to_unit(mix_bits(self.state))
+    }
+}
+
+/// Where a sampler is in the current pixel sample
+#[derive(Clone, Copy, Default)]
+struct PixelSample {
+    pixel: (u64, u64),
+    index: u64,
+    dimension: u64,
+}
+
+impl PixelSample {
+    fn start(&mut self, pixel: (usize, usize), sample_index: usize) {
+        self.pixel = (pixel.0 as u64, pixel.1 as u64);
+        self.index = sample_index as u64;
+        self.dimension = 0;
+    }
+
+    /// Hash of the pixel and current dimension, advancing to the next
+    /// dimension
+    fn next_dimension(&mut self, seed: u64) -> u64 {
+        let h = hash(&[self.pixel.0, self.pixel.1, self.dimension, seed]);
+        self.dimension += 1;
+        h
+    }
+}
+
+/// Jittered stratified sampling. The samples of a pixel fall in separate
+/// strata of every dimension, visiting the strata in a different random order
+/// in each dimension. 2D samples are stratified on an `x_samples` by
+/// `y_samples` grid.
+#[derive(Clone)]
+pub struct StratifiedSampler {
+    x_samples: usize,
+    y_samples: usize,
+    seed: u64,
+    state: PixelSample,
+}
+
+impl StratifiedSampler {
+    /// Stratifies `samples_per_pixel` samples, using a grid as close to square
+    /// as possible for 2D samples
+    pub fn new(samples_per_pixel: usize, seed: u64) -> StratifiedSampler {
+        let samples_per_pixel = usize::max(samples_per_pixel, 1);
+        let x_samples = (1..=samples_per_pixel)
+            .take_while(|x| x * x <= samples_per_pixel)
+            .filter(|&x| samples_per_pixel.is_multiple_of(x))
+            .last()
+            .unwrap_or(1);
+        StratifiedSampler {
+            x_samples,
+            y_samples: samples_per_pixel / x_samples,
+            seed,
+            state: PixelSample::default(),
+        }
+    }
+
+    fn samples_per_pixel(&self) -> u64 {
+        (self.x_samples * self.y_samples) as u64
+    }
+
+    fn jitter(&self, h: u64) -> f64 {
+        to_unit(hash(&[h, self.state.index]))
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, pixel: (usize, usize), sample_index: usize) {
+        self.state.start(pixel, sample_index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let h = self.state.next_dimension(self.seed);
+        let n = self.samples_per_pixel();
+        let stratum = permutation_element(self.state.index % n, n, h);
+        f64::min(
+            (stratum as f64 + self.jitter(h)) / n as f64,
+            ONE_MINUS_EPSILON,
+        )
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let h = self.state.next_dimension(self.seed);
+        let n = self.samples_per_pixel();
+        let stratum = permutation_element(self.state.index % n, n, h) as usize;
+        let (x, y) = (stratum % self.x_samples, stratum / self.x_samples);
+        (
+            f64::min(
+                (x as f64 + self.jitter(h)) / self.x_samples as f64,
+                ONE_MINUS_EPSILON,
+            ),
+            f64::min(
+                (y as f64 + self.jitter(mix_bits(h))) / self.y_samples as f64,
+                ONE_MINUS_EPSILON,
+            ),
+        )
+    }
+}
+
+/// The Halton sequence, using the radical inverse in the next prime base for
+/// every dimension, with digits Owen scrambled per pixel. Dimensions past the
+/// table of primes fall back to independent random numbers.
+#[derive(Clone)]
+pub struct HaltonSampler {
+    seed: u64,
+    state: PixelSample,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> HaltonSampler {
+        HaltonSampler {
+            seed,
+            state: PixelSample::default(),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, pixel: (usize, usize), sample_index: usize) {
+        self.state.start(pixel, sample_index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.state.dimension as usize;
+        let h = self.state.next_dimension(self.seed);
+        match primes().get(dimension) {
+            Some(&base) => owen_scrambled_radical_inverse(base, self.state.index, h),
+            None => to_unit(hash(&[h, self.state.index])),
+        }
+    }
+}
+
+/// Sobol samples with Owen scrambling. Every 2D sample comes from the first
+/// two dimensions of the Sobol sequence, which are stratified over every
+/// power of two, and each dimension shuffles the order of the samples so
+/// dimensions stay uncorrelated.
+#[derive(Clone)]
+pub struct SobolSampler {
+    samples_per_pixel: u64,
+    seed: u64,
+    state: PixelSample,
+}
+
+impl SobolSampler {
+    pub fn new(samples_per_pixel: usize, seed: u64) -> SobolSampler {
+        SobolSampler {
+            samples_per_pixel: usize::max(samples_per_pixel, 1) as u64,
+            seed,
+            state: PixelSample::default(),
+        }
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, pixel: (usize, usize), sample_index: usize) {
+        self.state.start(pixel, sample_index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let h = self.state.next_dimension(self.seed);
+        let index = shuffled_index(self.state.index, self.samples_per_pixel, h);
+        to_unit_u32(owen_scramble(index.reverse_bits(), (h >> 32) as u32))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let h = self.state.next_dimension(self.seed);
+        let index = shuffled_index(self.state.index, self.samples_per_pixel, h);
+        let (x, y) = sobol_2d(index);
+        (
+            to_unit_u32(owen_scramble(x, (h >> 32) as u32)),
+            to_unit_u32(owen_scramble(y, h as u32)),
+        )
+    }
+}
+
+/// Scrambled Sobol samples shared by all pixels, shifted per pixel by a blue
+/// noise texture. Neighbouring pixels get very different shifts, so what
+/// error remains is spread out as high frequency noise rather than clumps.
+#[derive(Clone)]
+pub struct BlueNoiseSampler {
+    samples_per_pixel: u64,
+    seed: u64,
+    state: PixelSample,
+}
+
+impl BlueNoiseSampler {
+    pub fn new(samples_per_pixel: usize, seed: u64) -> BlueNoiseSampler {
+        BlueNoiseSampler {
+            samples_per_pixel: usize::max(samples_per_pixel, 1) as u64,
+            seed,
+            state: PixelSample::default(),
+        }
+    }
+
+    /// Hash of the current dimension, identical for all pixels, advancing to
+    /// the next dimension
+    fn next_dimension(&mut self) -> u64 {
+        let h = hash(&[self.state.dimension, self.seed]);
+        self.state.dimension += 1;
+        h
+    }
+
+    // Texture value at the pixel, with the texture moved around by `h`
+    fn shift(&self, h: u64) -> f64 {
+        let texture = blue_noise_texture();
+        let x = (self.state.pixel.0 + (h & 0xffff)) as usize % BLUE_NOISE_SIZE;
+        let y = (self.state.pixel.1 + ((h >> 16) & 0xffff)) as usize % BLUE_NOISE_SIZE;
+        texture[y * BLUE_NOISE_SIZE + x]
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_pixel_sample(&mut self, pixel: (usize, usize), sample_index: usize) {
+        self.state.start(pixel, sample_index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let h = self.next_dimension();
+        let index = shuffled_index(self.state.index, self.samples_per_pixel, h);
+        let u = to_unit_u32(owen_scramble(index.reverse_bits(), (h >> 32) as u32));
+        wrap(u + self.shift(h))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let h = self.next_dimension();
+        let index = shuffled_index(self.state.index, self.samples_per_pixel, h);
+        let (x, y) = sobol_2d(index);
+        let u = to_unit_u32(owen_scramble(x, (h >> 32) as u32));
+        let v = to_unit_u32(owen_scramble(y, h as u32));
+        (wrap(u + self.shift(h)), wrap(v + self.shift(h >> 32)))
+    }
+}
+
+const ONE_MINUS_EPSILON: f64 = 1. - f64::EPSILON / 2.;
+
+fn wrap(u: f64) -> f64 {
+    f64::min(u - f64::floor(u), ONE_MINUS_EPSILON)
+}
+
+pub(crate) fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+pub(crate) fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e3779b97f4a7c15, |h, &v| {
+        mix_bits(h ^ v.wrapping_add(0x9e3779b97f4a7c15))
+    })
+}
+
+/// Maps 64 random bits to [0, 1)
+pub(crate) fn to_unit(h: u64) -> f64 {
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn to_unit_u32(v: u32) -> f64 {
+    v as f64 / (1u64 << 32) as f64
+}
+
+/// Element `i` of a random permutation of 0..n chosen by `seed`, found by
+/// walking a bijection on the next power of two until it lands below `n`
+fn permutation_element(mut i: u64, n: u64, seed: u64) -> u64 {
+    let p = seed as u32;
+    let mut w = (n - 1) as u32;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        let mut v = i as u32;
+        v ^= p;
+        v = v.wrapping_mul(0xe170893d);
+        v ^= p >> 16;
+        v ^= (v & w) >> 4;
+        v ^= p >> 8;
+        v = v.wrapping_mul(0x0929eb3f);
+        v ^= p >> 23;
+        v ^= (v & w) >> 1;
+        v = v.wrapping_mul(1 | p >> 27);
+        v = v.wrapping_mul(0x6935fa69);
+        v ^= (v & w) >> 11;
+        v = v.wrapping_mul(0x74dcb303);
+        v ^= (v & w) >> 2;
+        v = v.wrapping_mul(0x9e501cc3);
+        v ^= (v & w) >> 2;
+        v = v.wrapping_mul(0xc860a3df);
+        v &= w;
+        v ^= v >> 5;
+        i = v as u64;
+        if i < n {
+            return (i + p as u64) % n;
+        }
+    }
+}
+
+/// Shuffles sample indices within every block of `n` samples
+fn shuffled_index(index: u64, n: u64, seed: u64) -> u32 {
+    let block = index / n;
+    (block * n + permutation_element(index % n, n, seed)) as u32
+}
+
+/// Random permutation of the binary digits of a fixed point number in
+/// [0, 1), where every digit is flipped depending on the digits before it
+fn owen_scramble(mut v: u32, seed: u32) -> u32 {
+    v = v.reverse_bits();
+    v ^= v.wrapping_mul(0x3d20adea);
+    v = v.wrapping_add(seed);
+    v = v.wrapping_mul((seed >> 16) | 1);
+    v ^= v.wrapping_mul(0x05526c56);
+    v ^= v.wrapping_mul(0x53a22864);
+    v.reverse_bits()
+}
+
+/// First two dimensions of the Sobol sequence as 32 bit fixed point numbers
+fn sobol_2d(mut index: u32) -> (u32, u32) {
+    let (mut x, mut y) = (0, 0);
+    let (mut vx, mut vy) = (1u32 << 31, 1u32 << 31);
+    while index != 0 {
+        if index & 1 != 0 {
+            x ^= vx;
+            y ^= vy;
+        }
+        index >>= 1;
+        vx >>= 1;
+        vy ^= vy >> 1;
+    }
+    (x, y)
+}
+
+/// Radical inverse of `a` in `base` with every digit permuted depending on
+/// the digits before it
+fn owen_scrambled_radical_inverse(base: u64, mut a: u64, seed: u64) -> f64 {
+    let inv_base = 1. / base as f64;
+    let mut inv_base_m = 1.;
+    let mut reversed: u64 = 0;
+    // Keep going past the last non-zero digit, since scrambled zero digits
+    // are not zero, until the digits no longer fit 32 bits of precision
+    while inv_base_m * (1u64 << 32) as f64 >= 1. {
+        let next = a / base;
+        let digit = a - next * base;
+        let digit = permutation_element(digit, base, mix_bits(seed ^ reversed));
+        reversed = reversed * base + digit;
+        inv_base_m *= inv_base;
+        a = next;
+    }
+    f64::min(reversed as f64 * inv_base_m, ONE_MINUS_EPSILON)
+}
+
+fn primes() -> &'static [u64] {
+    static PRIMES: OnceLock<Vec<u64>> = OnceLock::new();
+    PRIMES.get_or_init(|| {
+        let mut primes: Vec<u64> = Vec::with_capacity(1000);
+        let mut n = 2;
+        while primes.len() < 1000 {
+            if primes
+                .iter()
+                .take_while(|&&p| p * p <= n)
+                .all(|&p| n % p != 0)
+            {
+                primes.push(n);
+            }
+            n += 1;
+        }
+        primes
+    })
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// Blue noise values in [0, 1) on a tiling square, made with the void and
+/// cluster method: pixels are ranked by the order in which they join a
+/// pattern that always fills its largest gap
+fn blue_noise_texture() -> &'static [f64] {
+    static TEXTURE: OnceLock<Vec<f64>> = OnceLock::new();
+    TEXTURE.get_or_init(|| {
+        let size = BLUE_NOISE_SIZE;
+        let n = size * size;
+        let sigma: f64 = 1.5;
+        let kernel: Vec<f64> = (0..n)
+            .map(|i| {
+                let (dx, dy) = (i % size, i / size);
+                let (dx, dy) = (usize::min(dx, size - dx), usize::min(dy, size - dy));
+                f64::exp(-((dx * dx + dy * dy) as f64) / (2. * sigma * sigma))
+            })
+            .collect();
+
+        // How crowded every pixel's neighbourhood is by the pattern
+        let toggle = |pattern: &mut [bool], energy: &mut [f64], p: usize| {
+            pattern[p] = !pattern[p];
+            let sign = if pattern[p] { 1. } else { -1. };
+            let (px, py) = (p % size, p / size);
+            for (q, e) in energy.iter_mut().enumerate() {
+                let dx = (q % size + size - px) % size;
+                let dy = (q / size + size - py) % size;
+                *e += sign * kernel[dy * size + dx];
+            }
+        };
+        let tightest_cluster = |pattern: &[bool], energy: &[f64]| {
+            (0..n)
+                .filter(|&p| pattern[p])
+                .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+                .unwrap()
+        };
+        let largest_void = |pattern: &[bool], energy: &[f64]| {
+            (0..n)
+                .filter(|&p| !pattern[p])
+                .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+                .unwrap()
+        };
+
+        // Start from a random tenth of the pixels and move points from
+        // clusters into voids until the pattern is even
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut order: Vec<usize> = (0..n).collect();
+        order.shuffle(&mut rng);
+        let mut pattern = vec![false; n];
+        let mut energy = vec![0.; n];
+        let initial = n / 10;
+        for &p in order.iter().take(initial) {
+            toggle(&mut pattern, &mut energy, p);
+        }
+        for _ in 0..n {
+            let cluster = tightest_cluster(&pattern, &energy);
+            toggle(&mut pattern, &mut energy, cluster);
+            let void = largest_void(&pattern, &energy);
+            toggle(&mut pattern, &mut energy, void);
+            if void == cluster {
+                break;
+            }
+        }
+
+        let mut rank = vec![0; n];
+        let (mut removing, mut removing_energy) = (pattern.clone(), energy.clone());
+        for r in (0..initial).rev() {
+            let cluster = tightest_cluster(&removing, &removing_energy);
+            toggle(&mut removing, &mut removing_energy, cluster);
+            rank[cluster] = r;
+        }
+        for r in initial..n {
+            let void = largest_void(&pattern, &energy);
+            toggle(&mut pattern, &mut energy, void);
+            rank[void] = r;
+        }
+
+        rank.into_iter()
+            .map(|r| (r as f64 + 0.5) / n as f64)
+            .collect()
+    })
+}
@@ -0,0 +1,179 @@
+//! Warping functions that turn uniform samples in [0, 1)^2 into samples of
+//! common distributions
+
+use std::f64::consts::PI;
+
+use crate::types::Vec3;
+
+pub fn uniform_sphere(u: (f64, f64)) -> Vec3 {
+    let z = 1.0 - 2.0 * u.0;
+    let r = f64::sqrt(f64::max(0.0, 1.0 - z * z));
+    let phi = 2.0 * PI * u.1;
+    Vec3::from((r * f64::cos(phi), r * f64::sin(phi), z))
+}
+
+pub fn uniform_sphere_pdf() -> f64 {
+    1.0 / (4.0 * PI)
+}
+
+/// Maps a square sample onto the unit disk, preserving relative areas
+pub fn concentric_disk(u: (f64, f64)) -> (f64, f64) {
+    let ox = 2.0 * u.0 - 1.0;
+    let oy = 2.0 * u.1 - 1.0;
+    if ox == 0.0 && oy == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let (r, theta) = if ox.abs() > oy.abs() {
+        (ox, PI / 4.0 * (oy / ox))
+    } else {
+        (oy, PI / 2.0 - PI / 4.0 * (ox / oy))
+    };
+    (r * f64::cos(theta), r * f64::sin(theta))
+}
+
+/// Cosine weighted direction in the hemisphere around +z
+pub fn cosine_hemisphere(u: (f64, f64)) -> Vec3 {
+    let (x, y) = concentric_disk(u);
+    let z = f64::sqrt(f64::max(0.0, 1.0 - x * x - y * y));
+    Vec3::from((x, y, z))
+}
+
+/// Uniform direction inside the cone around +z with the given half angle
+pub fn uniform_cone(u: (f64, f64), cos_theta_max: f64) -> Vec3 {
+    let cos_theta = (1.0 - u.0) + u.0 * cos_theta_max;
+    let sin_theta = f64::sqrt(f64::max(0.0, 1.0 - cos_theta * cos_theta));
+    let phi = 2.0 * PI * u.1;
+    Vec3::from((
+        sin_theta * f64::cos(phi),
+        sin_theta * f64::sin(phi),
+        cos_theta,
+    ))
+}
+
+pub fn uniform_cone_pdf(cos_theta_max: f64) -> f64 {
+    1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+}
+
+/// Weight of a sample drawn with density `f_pdf` when another strategy could
+/// have drawn it with density `g_pdf`, by the power heuristic
+pub fn power_heuristic(f_pdf: f64, g_pdf: f64) -> f64 {
+    let (f, g) = (f_pdf * f_pdf, g_pdf * g_pdf);
+    if f + g == 0.0 {
+        0.0
+    } else {
+        f / (f + g)
+    }
+}
+
+/// Transforms a direction expressed around +z into the frame around `n`
+pub fn to_world(local: &Vec3, n: &Vec3) -> Vec3 {
+    let (s, t) = n.orthonormal_basis();
+    local.x() * s + local.y() * t + local.z() * n
+}
+
+/// Expresses a direction in the frame around `n` used by `to_world`
+pub fn to_local(w: &Vec3, n: &Vec3) -> Vec3 {
+    let (s, t) = n.orthonormal_basis();
+    Vec3::new(Vec3::dot(w, &s), Vec3::dot(w, &t), Vec3::dot(w, n))
+}
+
+/// Piecewise constant distribution over [0, 1) proportional to `func`, with
+/// one piece per value
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    /// A function that is zero everywhere is sampled uniformly
+    pub fn new(func: Vec<f64>) -> Distribution1D {
+        let n = func.len();
+        let mut cdf = vec![0.; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + f64::max(func[i], 0.) / n as f64;
+        }
+        let integral = cdf[n];
+        for (i, value) in cdf.iter_mut().enumerate() {
+            *value = if integral > 0. {
+                *value / integral
+            } else {
+                i as f64 / n as f64
+            };
+        }
+        Distribution1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    /// Integral of the function over [0, 1)
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// Point in [0, 1), its density and the piece it lies in
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        let n = self.func.len();
+        let index = usize::min(
+            self.cdf.partition_point(|&c| c <= u).saturating_sub(1),
+            n - 1,
+        );
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let du = if width > 0. {
+            (u - self.cdf[index]) / width
+        } else {
+            0.
+        };
+        let x = f64::min((index as f64 + du) / n as f64, 1. - f64::EPSILON);
+        (x, self.pdf(x), index)
+    }
+
+    pub fn pdf(&self, x: f64) -> f64 {
+        if self.integral <= 0. {
+            return 1.;
+        }
+        let n = self.func.len();
+        let index = usize::min((x * n as f64) as usize, n - 1);
+        f64::max(self.func[index], 0.) / self.integral
+    }
+}
+
+/// Piecewise constant distribution over [0, 1)^2 proportional to a function
+/// given as `width` × `height` values, row by row
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[f64], width: usize, height: usize) -> Distribution2D {
+        let rows: Vec<Distribution1D> = func
+            .chunks(width)
+            .take(height)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(|row| row.integral()).collect());
+        Distribution2D { rows, marginal }
+    }
+
+    /// Integral of the function over [0, 1)^2
+    pub fn integral(&self) -> f64 {
+        self.marginal.integral()
+    }
+
+    /// Point (x, y) in [0, 1)^2, with y picking the row, and its density
+    pub fn sample(&self, u: (f64, f64)) -> ((f64, f64), f64) {
+        let (y, pdf_y, row) = self.marginal.sample(u.1);
+        let (x, pdf_x, _) = self.rows[row].sample(u.0);
+        ((x, y), pdf_x * pdf_y)
+    }
+
+    pub fn pdf(&self, (x, y): (f64, f64)) -> f64 {
+        let n = self.rows.len();
+        let row = usize::min((y * n as f64) as usize, n - 1);
+        self.marginal.pdf(y) * self.rows[row].pdf(x)
+    }
+}
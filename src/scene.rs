@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+use crate::hittable::{HitRecord, Hittable, HittableList};
+use crate::light::Light;
+use crate::spectrum;
+use crate::stats::{self, Counter};
+use crate::types::{Ray, Vec3};
+
+/// Geometry together with the lights that integrators can sample
+pub struct Scene {
+    world: HittableList,
+    lights: Vec<Arc<dyn Light>>,
+    bounds: (Vec3, f64),
+}
+
+impl Scene {
+    pub fn new(world: HittableList, lights: Vec<Arc<dyn Light>>) -> Scene {
+        let bounds = world.bounding_box().bounding_sphere();
+        Scene {
+            world,
+            lights,
+            bounds,
+        }
+    }
+
+    pub fn world(&self) -> &HittableList {
+        &self.world
+    }
+
+    pub fn lights(&self) -> &[Arc<dyn Light>] {
+        &self.lights
+    }
+
+    /// Picks one of the lights uniformly with `u` in [0, 1), returning it with
+    /// the probability it was chosen with
+    pub fn choose_light(&self, u: f64) -> Option<(&Arc<dyn Light>, f64)> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let idx = usize::min(
+            (u * self.lights.len() as f64) as usize,
+            self.lights.len() - 1,
+        );
+        Some((&self.lights[idx], self.light_pdf()))
+    }
+
+    /// Probability with which `choose_light` picks any one light
+    pub fn light_pdf(&self) -> f64 {
+        1. / self.lights.len() as f64
+    }
+
+    /// Center and radius of a sphere enclosing all geometry
+    pub fn bounds(&self) -> (Vec3, f64) {
+        self.bounds
+    }
+
+    pub fn hit(&self, ray: &Ray) -> Option<HitRecord> {
+        stats::count(Counter::ExtensionRays, 1);
+        self.world.hit((0.001, f64::MAX), ray)
+    }
+
+    /// Whether nothing blocks the segment between `p0` and `p1` at `time`
+    pub fn unoccluded(&self, p0: &Vec3, p1: &Vec3, time: f64) -> bool {
+        stats::count(Counter::ShadowRays, 1);
+        let dist = (*p1 - *p0).length();
+        let ray = Ray::at_time(*p0, (*p1 - *p0) / dist, time);
+        self.world.hit((0.001, dist - 0.001), &ray).is_none()
+    }
+
+    /// Radiance from the infinite lights carried by a ray that leaves the scene
+    pub fn escaped(&self, ray: &Ray) -> Vec3 {
+        self.lights
+            .iter()
+            .filter(|light| light.is_infinite())
+            .map(|light| spectrum::spectral(&light.le(ray), ray.wavelength))
+            .sum()
+    }
+}
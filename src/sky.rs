@@ -0,0 +1,364 @@
+//! Daylight from the analytic sky model of Preetham, Shirley and Smits, "A
+//! Practical Analytic Model for Daylight" (1999), and the sun seen through
+//! the same atmosphere. Radiance is in kilocandela per square metre, which
+//! [`PhysicalSky::exposure_for`] takes into account for each camera.
+//!
+//! Directions follow the environment light: y is up, and azimuths are in
+//! degrees clockwise from -z seen from above.
+
+use std::f64::consts::PI;
+
+use crate::camera::Camera;
+use crate::hdr::HdrImage;
+use crate::light::{self, EmissionSample, EnvironmentLight, Light, LightSample};
+use crate::sampling;
+use crate::types::{Ray, Vec3};
+
+/// Angular radius of the sun seen from the earth
+pub const SUN_ANGULAR_RADIUS: f64 = 0.2667;
+
+/// Exposure value at ISO 100 of daylight, by the sunny 16 rule
+pub const DAYLIGHT_EV100: f64 = 15.;
+
+/// Exposure for the sky of cameras without settings of their own, that of
+/// a physical camera set to [`DAYLIGHT_EV100`]
+pub const DAYLIGHT_EXPOSURE: f64 = 1000. / (1.2 * 32768.);
+
+/// Exposure value at ISO 100 for the gradient sky and other lights without
+/// physical units. Physical cameras take their radiance to be in the units
+/// this exposure value turns into 1, as cameras without settings see it.
+pub const GRADIENT_SKY_EV100: f64 = 8.;
+
+// Luminance of the sun outside the atmosphere
+const EXTRATERRESTRIAL_SUN: f64 = 1.6e6;
+
+// Size of the tabulated sky used for sampling
+const TABLE_WIDTH: usize = 128;
+const TABLE_HEIGHT: usize = 64;
+
+/// Unit vector towards a point `elevation` degrees above the horizon
+pub fn sun_direction(elevation: f64, azimuth: f64) -> Vec3 {
+    let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+    Vec3::from((
+        f64::cos(elevation) * f64::sin(azimuth),
+        f64::sin(elevation),
+        -f64::cos(elevation) * f64::cos(azimuth),
+    ))
+}
+
+/// Sky lit by the sun, with a ground below the horizon that reflects the
+/// light falling on it diffusely. The sun itself is a separate light, made
+/// by `sun`.
+pub struct PhysicalSky {
+    sun_direction: Vec3,
+    // Luminance Y and chromaticity x and y at the zenith
+    zenith: [f64; 3],
+    // Coefficients of the Perez distribution for Y, x and y
+    perez: [[f64; 5]; 3],
+    sun_radiance: Vec3,
+    ground: Vec3,
+    intensity: f64,
+    // A coarse copy of the sky, which directions are sampled from
+    table: EnvironmentLight,
+}
+
+impl PhysicalSky {
+    /// Exposure for the sky through `camera`. Physical cameras take its
+    /// radiance in cd/m², and other cameras see it as a physical camera set
+    /// for daylight would.
+    pub fn exposure_for(camera: &dyn Camera) -> f64 {
+        if camera.photometric() {
+            1000. * camera.exposure()
+        } else {
+            DAYLIGHT_EXPOSURE * camera.exposure()
+        }
+    }
+
+    /// Turbidity ranges from 2 for a very clear sky to 10 for haze. A sun
+    /// below the horizon is moved up to it.
+    pub fn new(sun_direction: Vec3, turbidity: f64, ground_albedo: f64) -> PhysicalSky {
+        let sun_direction = Vec3::unit_vector(&Vec3::from((
+            sun_direction.x(),
+            f64::max(sun_direction.y(), 0.),
+            sun_direction.z(),
+        )));
+        let t = turbidity;
+        let theta_s = f64::acos(sun_direction.y());
+
+        let chi = (4. / 9. - t / 120.) * (PI - 2. * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * f64::tan(chi) - 0.2155 * t + 2.4192;
+        let chromaticity = |m: [[f64; 4]; 3]| {
+            let angles = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.];
+            let row = |r: [f64; 4]| r.iter().zip(angles).map(|(a, b)| a * b).sum::<f64>();
+            t * t * row(m[0]) + t * row(m[1]) + row(m[2])
+        };
+        let zenith_x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let mut sky = PhysicalSky {
+            sun_direction,
+            zenith: [zenith_luminance, zenith_x, zenith_y],
+            perez,
+            sun_radiance: EXTRATERRESTRIAL_SUN * sun_transmittance(theta_s, t),
+            ground: Vec3::default(),
+            intensity: 1.,
+            table: EnvironmentLight::new(HdrImage::new(1, 1)),
+        };
+
+        // The ground reflects what the sky and the sun shed on it
+        let n = 64;
+        let mut irradiance = Vec3::default();
+        for i in 0..n {
+            for j in 0..4 * n {
+                let u = (
+                    (i as f64 + 0.5) / n as f64,
+                    (j as f64 + 0.5) / (4 * n) as f64,
+                );
+                let dir = sampling::cosine_hemisphere(u);
+                irradiance += sky.sky_radiance(&Vec3::from((dir.x(), dir.z(), dir.y())));
+            }
+        }
+        let sun_solid_angle = 2. * PI * (1. - f64::cos(SUN_ANGULAR_RADIUS.to_radians()));
+        irradiance = PI * irradiance / (4 * n * n) as f64
+            + sky.sun_radiance * sun_solid_angle * sun_direction.y();
+        sky.ground = ground_albedo * irradiance / PI;
+
+        let mut table = HdrImage::new(TABLE_WIDTH, TABLE_HEIGHT);
+        for row in 0..TABLE_HEIGHT {
+            for x in 0..TABLE_WIDTH {
+                let theta = PI * (row as f64 + 0.5) / TABLE_HEIGHT as f64;
+                let longitude = 2. * PI * ((x as f64 + 0.5) / TABLE_WIDTH as f64 - 0.5);
+                let dir = Vec3::from((
+                    f64::sin(theta) * f64::sin(longitude),
+                    f64::cos(theta),
+                    -f64::sin(theta) * f64::cos(longitude),
+                ));
+                table.set_pixel(x, TABLE_HEIGHT - 1 - row, sky.sky_radiance(&dir));
+            }
+        }
+        sky.table = EnvironmentLight::new(table);
+        sky
+    }
+
+    /// Scales the radiance of the sky, and of the sun made afterwards
+    pub fn with_intensity(mut self, intensity: f64) -> PhysicalSky {
+        self.intensity = intensity;
+        self
+    }
+
+    /// The sun disk lit as the sky expects it
+    pub fn sun(&self) -> SunLight {
+        SunLight::new(
+            self.sun_direction,
+            SUN_ANGULAR_RADIUS,
+            self.intensity * self.sun_radiance,
+        )
+    }
+
+    fn sky_radiance(&self, dir: &Vec3) -> Vec3 {
+        let dir = Vec3::unit_vector(dir);
+        if dir.y() <= 0. {
+            return self.ground;
+        }
+        let cos_theta = dir.y();
+        let cos_gamma = Vec3::dot(&dir, &self.sun_direction).clamp(-1., 1.);
+        let gamma = f64::acos(cos_gamma);
+        let theta_s = f64::acos(self.sun_direction.y());
+        let [luminance, x, y] = [0, 1, 2].map(|i| {
+            let perez = |cos_theta: f64, gamma: f64, cos_gamma: f64| {
+                let [a, b, c, d, e] = self.perez[i];
+                (1. + a * f64::exp(b / cos_theta))
+                    * (1. + c * f64::exp(d * gamma) + e * cos_gamma * cos_gamma)
+            };
+            self.zenith[i] * perez(cos_theta, gamma, cos_gamma)
+                / perez(1., theta_s, self.sun_direction.y())
+        });
+        xyy_to_rgb(x, y, luminance)
+    }
+}
+
+impl Light for PhysicalSky {
+    fn sample_li(&self, p: &Vec3, u: (f64, f64), world: (Vec3, f64)) -> Option<LightSample> {
+        let sample = self.table.sample_li(p, u, world)?;
+        Some(LightSample {
+            radiance: self.le(&Ray::from(*p, sample.wi)),
+            ..sample
+        })
+    }
+
+    fn pdf_li(&self, p: &Vec3, wi: &Vec3) -> f64 {
+        self.table.pdf_li(p, wi)
+    }
+
+    fn sample_le(
+        &self,
+        u1: (f64, f64),
+        u2: (f64, f64),
+        world: (Vec3, f64),
+    ) -> Option<EmissionSample> {
+        let sample = self.table.sample_le(u1, u2, world)?;
+        Some(EmissionSample {
+            radiance: self.le(&Ray::from(world.0, -sample.ray.direction)),
+            ..sample
+        })
+    }
+
+    fn pdf_le(&self, ray: &Ray, normal: &Vec3, world: (Vec3, f64)) -> (f64, f64) {
+        self.table.pdf_le(ray, normal, world)
+    }
+
+    fn le(&self, ray: &Ray) -> Vec3 {
+        self.intensity * self.sky_radiance(&ray.direction)
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+}
+
+/// Distant disk of uniform radiance, seen in the same direction from
+/// everywhere in the scene
+pub struct SunLight {
+    direction: Vec3,
+    cos_theta_max: f64,
+    radiance: Vec3,
+}
+
+impl SunLight {
+    /// `direction` points towards the centre of the disk, which is
+    /// `angular_radius` degrees across
+    pub fn new(direction: Vec3, angular_radius: f64, radiance: Vec3) -> SunLight {
+        SunLight {
+            direction: Vec3::unit_vector(&direction),
+            cos_theta_max: f64::cos(angular_radius.to_radians()),
+            radiance,
+        }
+    }
+
+    fn contains(&self, dir: &Vec3) -> bool {
+        Vec3::dot(&Vec3::unit_vector(dir), &self.direction) >= self.cos_theta_max
+    }
+
+    fn pdf_dir(&self, dir: &Vec3) -> f64 {
+        if self.contains(dir) {
+            sampling::uniform_cone_pdf(self.cos_theta_max)
+        } else {
+            0.
+        }
+    }
+}
+
+impl Light for SunLight {
+    fn sample_li(&self, p: &Vec3, u: (f64, f64), world: (Vec3, f64)) -> Option<LightSample> {
+        let local = sampling::uniform_cone(u, self.cos_theta_max);
+        let wi = sampling::to_world(&local, &self.direction);
+        Some(LightSample {
+            wi,
+            p: *p + 2. * world.1 * wi,
+            normal: Vec3::default(),
+            radiance: self.radiance,
+            pdf: sampling::uniform_cone_pdf(self.cos_theta_max),
+        })
+    }
+
+    fn pdf_li(&self, _p: &Vec3, wi: &Vec3) -> f64 {
+        self.pdf_dir(wi)
+    }
+
+    fn sample_le(
+        &self,
+        u1: (f64, f64),
+        u2: (f64, f64),
+        world: (Vec3, f64),
+    ) -> Option<EmissionSample> {
+        let local = sampling::uniform_cone(u1, self.cos_theta_max);
+        let wi = sampling::to_world(&local, &self.direction);
+        Some(light::infinite_emission(
+            wi,
+            u2,
+            world,
+            self.radiance,
+            sampling::uniform_cone_pdf(self.cos_theta_max),
+        ))
+    }
+
+    fn pdf_le(&self, ray: &Ray, _normal: &Vec3, world: (Vec3, f64)) -> (f64, f64) {
+        (1. / (PI * world.1 * world.1), self.pdf_dir(&-ray.direction))
+    }
+
+    fn le(&self, ray: &Ray) -> Vec3 {
+        if self.contains(&ray.direction) {
+            self.radiance
+        } else {
+            Vec3::default()
+        }
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+}
+
+// Fraction of sunlight that gets through Rayleigh and aerosol scattering, at
+// wavelengths standing in for red, green and blue, from the appendix of the
+// paper
+fn sun_transmittance(theta_s: f64, turbidity: f64) -> Vec3 {
+    let degrees = theta_s.to_degrees();
+    let air_mass = 1. / (f64::cos(theta_s) + 0.15 * f64::powf(93.885 - degrees, -1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let transmittance = |wavelength: f64| {
+        let rayleigh = -0.008735 * f64::powf(wavelength, -4.08);
+        let aerosol = -beta * f64::powf(wavelength, -1.3);
+        f64::exp((rayleigh + aerosol) * air_mass)
+    };
+    Vec3::from((
+        transmittance(0.68),
+        transmittance(0.55),
+        transmittance(0.44),
+    ))
+}
+
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Vec3 {
+    if y <= 0. {
+        return Vec3::default();
+    }
+    let big_x = x / y * luminance;
+    let big_z = (1. - x - y) / y * luminance;
+    // Linear sRGB primaries
+    Vec3::from((
+        f64::max(0., 3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z),
+        f64::max(0., -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z),
+        f64::max(0., 0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z),
+    ))
+}
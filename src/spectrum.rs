@@ -0,0 +1,217 @@
+//! Conversions between light of single wavelengths and the RGB the renderer
+//! works in. Wavelengths are in nanometres.
+//!
+//! Spectral rendering keeps the renderer's `Vec3` colors but lets their
+//! components stand for the values of spectra at three wavelengths a path
+//! carries, picked by hero wavelength sampling. Materials and emitters turn
+//! their RGB colors into spectra where a path meets them and the integrator
+//! turns the result back into RGB through CIE XYZ.
+
+use std::sync::OnceLock;
+
+use crate::types::Vec3;
+
+/// Visible wavelengths sampled by `sample_wavelength`
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+/// CIE 1931 color matching functions, by the multi-lobe fit of Wyman et al.'s
+/// "Simple Analytic Approximations to the CIE XYZ Color Matching Functions"
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    // Gaussian with different widths either side of its peak
+    let g = |mu: f64, sigma_below: f64, sigma_above: f64| {
+        let sigma = if lambda < mu {
+            sigma_below
+        } else {
+            sigma_above
+        };
+        f64::exp(-0.5 * ((lambda - mu) / sigma).powi(2))
+    };
+    Vec3::from((
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    ))
+}
+
+/// Linear sRGB with a D65 white point
+pub fn xyz_to_srgb(xyz: &Vec3) -> Vec3 {
+    let (x, y, z) = (xyz.x(), xyz.y(), xyz.z());
+    Vec3::from((
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    ))
+}
+
+/// Samples a wavelength roughly in proportion to how visible it is, following
+/// Radziszowski et al., and returns it with its density
+pub fn sample_wavelength(u: f64) -> (f64, f64) {
+    let lambda = 538.0 - 138.888889 * f64::atanh(0.85691062 - 1.82750197 * u);
+    (lambda, wavelength_pdf(lambda))
+}
+
+pub fn wavelength_pdf(lambda: f64) -> f64 {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda) {
+        return 0.0;
+    }
+    0.0039398042 / f64::cosh(0.0072 * (lambda - 538.0)).powi(2)
+}
+
+/// Color that light of wavelength `lambda` picked with density `pdf` adds to
+/// an estimate, scaled so that on average over the wavelengths it is white.
+/// Deep blues and cyans lie outside sRGB and have negative components.
+pub fn wavelength_rgb(lambda: f64, pdf: f64) -> Vec3 {
+    if pdf == 0.0 {
+        return Vec3::default();
+    }
+    xyz_to_srgb(&cie_xyz(lambda)) / (*white() * pdf)
+}
+
+// Integral of the color of each wavelength over the visible spectrum
+fn white() -> &'static Vec3 {
+    static WHITE: OnceLock<Vec3> = OnceLock::new();
+    WHITE.get_or_init(|| {
+        let steps = 4700;
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+        (0..steps)
+            .map(|i| xyz_to_srgb(&cie_xyz(LAMBDA_MIN + (i as f64 + 0.5) * step)) * step)
+            .sum()
+    })
+}
+
+/// Wavelengths the light a ray carries is made of, when it is not white light
+/// seen in RGB
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wavelengths {
+    /// A single wavelength split from white light by dispersion, whose color
+    /// is already part of the path's RGB weight
+    Single(f64),
+    /// Spectral rendering, where the components of colors are values at these
+    /// wavelengths. Dispersion keeps only the first, the hero, after which
+    /// `secondary` is false and the other components are zero.
+    Hero { lambda: [f64; 3], secondary: bool },
+}
+
+impl Wavelengths {
+    /// Hero wavelength sampling: three wavelengths spread evenly over the
+    /// sampling density from one sample, so each follows `wavelength_pdf`
+    pub fn sample_hero(u: f64) -> Wavelengths {
+        let mut lambda = [0.0; 3];
+        for (i, lambda) in lambda.iter_mut().enumerate() {
+            *lambda = sample_wavelength((u + i as f64 / 3.0).fract()).0;
+        }
+        Wavelengths::Hero {
+            lambda,
+            secondary: true,
+        }
+    }
+
+    /// The wavelength that decides where light of several is refracted
+    pub fn hero(&self) -> f64 {
+        match *self {
+            Wavelengths::Single(lambda) => lambda,
+            Wavelengths::Hero { lambda, .. } => lambda[0],
+        }
+    }
+
+    /// `rgb` as light of these wavelengths sees it: unchanged outside
+    /// spectral rendering, else values of a matching spectrum
+    pub fn spectral(&self, rgb: &Vec3) -> Vec3 {
+        match self {
+            Wavelengths::Single(_) => *rgb,
+            Wavelengths::Hero { lambda, .. } => Vec3::from((
+                upsample(rgb, lambda[0]),
+                upsample(rgb, lambda[1]),
+                upsample(rgb, lambda[2]),
+            )),
+        }
+    }
+
+    /// Estimate in RGB from the values of a spectrum at these wavelengths
+    pub fn to_rgb(&self, values: &Vec3) -> Vec3 {
+        match self {
+            Wavelengths::Single(_) => *values,
+            Wavelengths::Hero { lambda, .. } => {
+                (0..3)
+                    .map(|i| values[i] * wavelength_rgb(lambda[i], wavelength_pdf(lambda[i])))
+                    .sum::<Vec3>()
+                    / 3.0
+            }
+        }
+    }
+}
+
+/// Value at `lambda` of a quantity measured at 650, 550 and 450 nm for the
+/// red, green and blue channels, linear in between and constant beyond
+pub fn interpolate(rgb: &Vec3, lambda: f64) -> f64 {
+    if lambda >= 650.0 {
+        rgb.r()
+    } else if lambda >= 550.0 {
+        let t = (650.0 - lambda) / 100.0;
+        (1.0 - t) * rgb.r() + t * rgb.g()
+    } else if lambda >= 450.0 {
+        let t = (550.0 - lambda) / 100.0;
+        (1.0 - t) * rgb.g() + t * rgb.b()
+    } else {
+        rgb.b()
+    }
+}
+
+/// `rgb` as seen by light of `wavelengths`, for colors that may be met by
+/// rays of either kind
+pub fn spectral(rgb: &Vec3, wavelengths: Option<Wavelengths>) -> Vec3 {
+    match wavelengths {
+        Some(wavelengths) => wavelengths.spectral(rgb),
+        None => *rgb,
+    }
+}
+
+// Spectra of the seven colors Smits builds all others from, in ten bins
+// between 380 and 720 nm
+const SMITS_WHITE: [f64; 10] = [1.0, 1.0, 0.9999, 0.9993, 0.9992, 0.9998, 1.0, 1.0, 1.0, 1.0];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0, 0.0, 0.0,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0, 1.0, 0.9685, 0.2229, 0.0, 0.0458, 0.8369, 1.0, 1.0, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0, 0.1088, 0.6651, 1.0, 1.0, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0, 0.0, 0.0, 0.0, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0, 0.0, 0.0273, 0.7937, 1.0, 0.9418, 0.1719, 0.0, 0.0, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0, 1.0, 0.8916, 0.3323, 0.0, 0.0, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+/// Value at `lambda` of a smooth spectrum with color `rgb`, following Smits's
+/// "An RGB-to-Spectrum Conversion for Reflectances"
+pub fn upsample(rgb: &Vec3, lambda: f64) -> f64 {
+    let bin = ((lambda - 380.0) / 34.0).clamp(0.0, 9.0) as usize;
+    let (r, g, b) = (rgb.r(), rgb.g(), rgb.b());
+    // The smallest component is white, the middle one the secondary color of
+    // the two largest and the rest the primary color of the largest
+    let (white, secondary, primary) = if r <= g && r <= b {
+        if g <= b {
+            (r, (g - r) * SMITS_CYAN[bin], (b - g) * SMITS_BLUE[bin])
+        } else {
+            (r, (b - r) * SMITS_CYAN[bin], (g - b) * SMITS_GREEN[bin])
+        }
+    } else if g <= r && g <= b {
+        if r <= b {
+            (g, (r - g) * SMITS_MAGENTA[bin], (b - r) * SMITS_BLUE[bin])
+        } else {
+            (g, (b - g) * SMITS_MAGENTA[bin], (r - b) * SMITS_RED[bin])
+        }
+    } else if r <= g {
+        (b, (r - b) * SMITS_YELLOW[bin], (g - r) * SMITS_GREEN[bin])
+    } else {
+        (b, (g - b) * SMITS_YELLOW[bin], (r - g) * SMITS_RED[bin])
+    };
+    white * SMITS_WHITE[bin] + secondary + primary
+}
@@ -0,0 +1,280 @@
+//! Statistics about the rays traced while rendering. Every thread counts into
+//! its own counters, which only it writes to, so counting does not slow down
+//! rendering. Renders find the rays they traced from the counts of the
+//! threads that did their work, before and after each piece of it.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::iter::Sum;
+use std::ops::{Add, Sub};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy)]
+pub(crate) enum Counter {
+    CameraRays,
+    LightPaths,
+    ExtensionRays,
+    ShadowRays,
+    IntersectionTests,
+}
+
+const NUM_COUNTERS: usize = 5;
+
+type ThreadCounters = [AtomicU64; NUM_COUNTERS];
+
+static THREAD_COUNTERS: Mutex<Vec<Arc<ThreadCounters>>> = Mutex::new(Vec::new());
+
+thread_local! {
+    static COUNTERS: Arc<ThreadCounters> = {
+        let counters = Arc::new(Default::default());
+        THREAD_COUNTERS.lock().unwrap().push(Arc::clone(&counters));
+        counters
+    };
+}
+
+/// Adds `n` to one of the current thread's counters
+pub(crate) fn count(counter: Counter, n: u64) {
+    COUNTERS.with(|counters| {
+        // Only this thread writes to its counters, so there is no need for
+        // an atomic add
+        let value = &counters[counter as usize];
+        value.store(value.load(Ordering::Relaxed) + n, Ordering::Relaxed);
+    });
+}
+
+/// Runs `f` and returns the rays it traced, as counted by the current thread.
+/// `f` must not wait for work done by other threads, or the thread may run
+/// other work meanwhile whose rays would be counted too.
+pub(crate) fn counted<T>(f: impl FnOnce() -> T) -> (T, RayCounts) {
+    let before = COUNTERS.with(|counters| RayCounts::from_counters(counters));
+    let result = f();
+    let after = COUNTERS.with(|counters| RayCounts::from_counters(counters));
+    (result, after - before)
+}
+
+/// Numbers of rays traced
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RayCounts {
+    /// Rays leaving the camera
+    pub camera_rays: u64,
+    /// Paths started from a light
+    pub light_paths: u64,
+    /// Rays looking for the closest hit, which extend a path by one segment
+    pub extension_rays: u64,
+    /// Rays checking whether two points see each other
+    pub shadow_rays: u64,
+    /// Ray-object intersection tests done for all rays
+    pub intersection_tests: u64,
+}
+
+impl RayCounts {
+    /// Counts of every thread so far, from the start of the process
+    pub fn now() -> RayCounts {
+        THREAD_COUNTERS
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|counters| RayCounts::from_counters(counters))
+            .sum()
+    }
+
+    fn from_counters(counters: &ThreadCounters) -> RayCounts {
+        let value = |counter: Counter| counters[counter as usize].load(Ordering::Relaxed);
+        RayCounts {
+            camera_rays: value(Counter::CameraRays),
+            light_paths: value(Counter::LightPaths),
+            extension_rays: value(Counter::ExtensionRays),
+            shadow_rays: value(Counter::ShadowRays),
+            intersection_tests: value(Counter::IntersectionTests),
+        }
+    }
+
+    pub fn total_rays(&self) -> u64 {
+        self.extension_rays + self.shadow_rays
+    }
+
+    /// Rays that did not come straight from the camera
+    pub fn secondary_rays(&self) -> u64 {
+        self.total_rays().saturating_sub(self.camera_rays)
+    }
+
+    /// Average number of segments of the camera and light paths
+    pub fn average_path_length(&self) -> f64 {
+        self.extension_rays as f64 / u64::max(self.camera_rays + self.light_paths, 1) as f64
+    }
+
+    pub fn intersection_tests_per_ray(&self) -> f64 {
+        self.intersection_tests as f64 / u64::max(self.total_rays(), 1) as f64
+    }
+}
+
+impl Add for RayCounts {
+    type Output = RayCounts;
+
+    fn add(self, other: RayCounts) -> RayCounts {
+        RayCounts {
+            camera_rays: self.camera_rays + other.camera_rays,
+            light_paths: self.light_paths + other.light_paths,
+            extension_rays: self.extension_rays + other.extension_rays,
+            shadow_rays: self.shadow_rays + other.shadow_rays,
+            intersection_tests: self.intersection_tests + other.intersection_tests,
+        }
+    }
+}
+
+impl Sub for RayCounts {
+    type Output = RayCounts;
+
+    fn sub(self, other: RayCounts) -> RayCounts {
+        RayCounts {
+            camera_rays: self.camera_rays - other.camera_rays,
+            light_paths: self.light_paths - other.light_paths,
+            extension_rays: self.extension_rays - other.extension_rays,
+            shadow_rays: self.shadow_rays - other.shadow_rays,
+            intersection_tests: self.intersection_tests - other.intersection_tests,
+        }
+    }
+}
+
+impl Sum for RayCounts {
+    fn sum<I: Iterator<Item = RayCounts>>(iter: I) -> RayCounts {
+        iter.fold(RayCounts::default(), Add::add)
+    }
+}
+
+/// Time taken and rays traced by one phase of a render
+#[derive(Clone, Debug)]
+pub struct PhaseStats {
+    pub name: String,
+    pub time: Duration,
+    pub rays: RayCounts,
+}
+
+/// Statistics of a render made up of phases, such as building a photon map
+/// and then rendering with it
+#[derive(Clone, Debug, Default)]
+pub struct RenderStats {
+    phases: Vec<PhaseStats>,
+}
+
+impl RenderStats {
+    pub fn new() -> RenderStats {
+        RenderStats::default()
+    }
+
+    /// Runs `f` as the phase `name` and records how long it took and the
+    /// rays it returns as having traced
+    pub fn phase(&mut self, name: &str, f: impl FnOnce() -> RayCounts) {
+        let start = Instant::now();
+        let rays = f();
+        self.phases.push(PhaseStats {
+            name: name.to_string(),
+            time: start.elapsed(),
+            rays,
+        });
+    }
+
+    pub fn phases(&self) -> &[PhaseStats] {
+        &self.phases
+    }
+
+    pub fn time(&self) -> Duration {
+        self.phases.iter().map(|phase| phase.time).sum()
+    }
+
+    pub fn rays(&self) -> RayCounts {
+        self.phases.iter().map(|phase| phase.rays).sum()
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        self.rays().total_rays() as f64 / f64::max(self.time().as_secs_f64(), 1e-9)
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rays = self.rays();
+        writeln!(
+            f,
+            "Traced {} rays, {:.2}M rays/s",
+            rays.total_rays(),
+            self.rays_per_second() / 1e6
+        )?;
+        writeln!(
+            f,
+            "  {} primary, {} secondary, of which {} shadow rays",
+            rays.camera_rays,
+            rays.secondary_rays(),
+            rays.shadow_rays
+        )?;
+        writeln!(
+            f,
+            "  {:.2} segments per path, {:.1} intersection tests per ray",
+            rays.average_path_length(),
+            rays.intersection_tests_per_ray()
+        )?;
+        for phase in self.phases.iter() {
+            writeln!(
+                f,
+                "  {}: {}",
+                phase.name,
+                humantime::format_duration(Duration::from_millis(phase.time.as_millis() as u64))
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints how far a render has got and how long it will still take, judged
+/// by how many of the expected camera rays have been traced, until dropped
+pub struct ProgressReporter {
+    stop: Sender<()>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ProgressReporter {
+    pub fn start(expected_camera_rays: u64) -> ProgressReporter {
+        let (stop, stopped) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            let start = Instant::now();
+            let first = RayCounts::now().camera_rays;
+            let mut printed = false;
+            while let Err(RecvTimeoutError::Timeout) =
+                stopped.recv_timeout(Duration::from_millis(500))
+            {
+                let done = RayCounts::now().camera_rays - first;
+                let fraction = f64::min(done as f64 / u64::max(expected_camera_rays, 1) as f64, 1.);
+                let eta = if fraction > 0. {
+                    let remaining = start.elapsed().as_secs_f64() * (1. - fraction) / fraction;
+                    humantime::format_duration(Duration::from_secs(remaining.round() as u64))
+                        .to_string()
+                } else {
+                    "unknown".to_string()
+                };
+                eprint!("\r{:5.1}% done, {eta} left\x1b[K", 100. * fraction);
+                io::stderr().flush().ok();
+                printed = true;
+            }
+            if printed {
+                eprintln!();
+            }
+        });
+        ProgressReporter {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for ProgressReporter {
+    fn drop(&mut self) {
+        self.stop.send(()).ok();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
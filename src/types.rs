@@ -1,5 +1,7 @@
 use std::{ops, iter};
 
+use crate::spectrum::Wavelengths;
+
 #[derive(Clone, Copy, Default)]
 pub struct Vec3 {
     e: [f64; 3],
@@ -62,6 +64,28 @@ impl Vec3 {
         v1.e[0] * v2.e[0] + v1.e[1] * v2.e[1] + v1.e[2] * v2.e[2]
     }
 
+    pub fn is_zero(&self) -> bool {
+        self.e[0] == 0.0 && self.e[1] == 0.0 && self.e[2] == 0.0
+    }
+
+    /// Brightness of a linear RGB color, using the Rec. 709 weights
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.e[0] + 0.7152 * self.e[1] + 0.0722 * self.e[2]
+    }
+
+    /// Returns two unit vectors that together with `self` (assumed to be a
+    /// unit vector) form an orthonormal basis
+    pub fn orthonormal_basis(&self) -> (Vec3, Vec3) {
+        let a = if self.e[0].abs() > 0.9 {
+            Vec3::from((0.0, 1.0, 0.0))
+        } else {
+            Vec3::from((1.0, 0.0, 0.0))
+        };
+        let s = Vec3::unit_vector(&self.cross(&a));
+        let t = self.cross(&s);
+        (s, t)
+    }
+
     pub fn cross(&self, v2: &Vec3) -> Vec3 {
         Vec3 {
             e: [
@@ -271,7 +295,9 @@ impl ops::Div<f64> for Vec3 {
     type Output = Vec3;
 
     fn div(self, rhs: f64) -> Self::Output {
-        &self / rhs
+        Vec3 {
+            e: [self.e[0] / rhs, self.e[1] / rhs, self.e[2] / rhs],
+        }
     }
 }
 
@@ -298,14 +324,26 @@ impl ops::Neg for Vec3 {
 
     fn neg(self) -> Self::Output {
         Vec3 {
-            e: [self.e[0] * -1.0, self.e[1] * -1.0, self.e[2] * -1.0],
+            e: [-self.e[0], -self.e[1], -self.e[2]],
         }
     }
 }
 
+#[derive(Clone, Copy)]
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    /// Time in seconds at which the ray is traced, for motion blur
+    pub time: f64,
+    /// Wavelengths of the light the ray carries, in spectral rendering or once
+    /// something dispersive has split it from white light
+    pub wavelength: Option<Wavelengths>,
+}
+
+impl Default for Ray {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Ray {
@@ -315,11 +353,27 @@ impl Ray {
         Ray {
             origin: Vec3::from((0.0, 0.0, 0.0)),
             direction: Vec3::from((0.0, 0.0, -1.0)),
+            time: 0.0,
+            wavelength: None,
         }
     }
 
     pub fn from(origin: Vec3, direction: Vec3) -> Ray {
-        Ray { origin, direction }
+        Ray::at_time(origin, direction, 0.0)
+    }
+
+    pub fn at_time(origin: Vec3, direction: Vec3, time: f64) -> Ray {
+        Ray {
+            origin,
+            direction,
+            time,
+            wavelength: None,
+        }
+    }
+
+    pub fn with_wavelength(mut self, wavelength: Option<Wavelengths>) -> Ray {
+        self.wavelength = wavelength;
+        self
     }
 
     pub fn pos(&self, t: f64) -> Vec3 {
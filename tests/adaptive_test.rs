@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use raytrace::{
+    camera::PerspectiveCamera,
+    film::Film,
+    hittable::{HittableList, Sphere},
+    integrator::PathTracer,
+    light::{GradientSky, Light, SphereLight},
+    material::Lambertian,
+    render::{render, render_adaptive, AdaptiveSampling},
+    sampler::IndependentSampler,
+    scene::Scene,
+    types::Vec3,
+};
+
+const WIDTH: usize = 24;
+const HEIGHT: usize = 16;
+
+fn scene() -> Scene {
+    let mut world = HittableList::new();
+    world.add(Box::new(Sphere::new(
+        Vec3::new(0, -100, 0),
+        100.,
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+    )));
+
+    let light = Arc::new(SphereLight::new(Vec3::new(0, 4, 0), 1., Vec3::new(4, 4, 4)));
+    world.add(Box::new(light.sphere()));
+    // A constant sky, so pixels that only see the sky have no noise at all
+    let sky = Arc::new(GradientSky::new(
+        Vec3::new(0.1, 0.1, 0.1),
+        Vec3::new(0.1, 0.1, 0.1),
+    ));
+    let lights: Vec<Arc<dyn Light>> = vec![light, sky];
+
+    Scene::new(world, lights)
+}
+
+fn camera() -> PerspectiveCamera {
+    PerspectiveCamera::new(
+        Vec3::new(0, 2, 8),
+        Vec3::new(0, 1, 0),
+        Vec3::new(0, 1, 0),
+        40.,
+        WIDTH as f64 / HEIGHT as f64,
+        0.,
+        8.,
+    )
+}
+
+fn mean(film: &Film) -> Vec3 {
+    let mut sum = Vec3::default();
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            sum += film.pixel(x, y);
+        }
+    }
+    sum / (WIDTH * HEIGHT) as f64
+}
+
+#[test]
+fn converged_pixels_stop_early() {
+    let scene = scene();
+    let mut film = Film::new(WIDTH, HEIGHT);
+    let settings = AdaptiveSampling::new(32, 512, 0.01);
+    let (maps, rays) = render_adaptive(
+        &scene,
+        &camera(),
+        &PathTracer::new(5),
+        &IndependentSampler::new(0),
+        &mut film,
+        &settings,
+    );
+
+    // Top left only sees the sky, bottom centre sees the lit ground
+    assert_eq!(maps.sample_count(0, HEIGHT - 1), 32);
+    assert!(maps.variance(0, HEIGHT - 1) < 1e-12);
+    assert!(maps.sample_count(WIDTH / 2, 0) > 32);
+    assert!(maps.total_samples() < 512 * WIDTH * HEIGHT);
+    assert_eq!(rays.camera_rays, maps.total_samples() as u64);
+
+    let image = maps.sample_count_image();
+    assert_eq!((image.get_width(), image.get_height()), (WIDTH, HEIGHT));
+}
+
+#[test]
+fn adaptive_sampling_matches_fixed_sampling() {
+    let scene = scene();
+    let mut expected = Film::new(WIDTH, HEIGHT);
+    render(
+        &scene,
+        &camera(),
+        &PathTracer::new(5),
+        &IndependentSampler::new(0),
+        &mut expected,
+        256,
+    );
+
+    let mut film = Film::new(WIDTH, HEIGHT);
+    render_adaptive(
+        &scene,
+        &camera(),
+        &PathTracer::new(5),
+        &IndependentSampler::new(1),
+        &mut film,
+        &AdaptiveSampling::new(16, 256, 0.01),
+    );
+
+    let (expected, actual) = (mean(&expected), mean(&film));
+    for i in 0..3 {
+        let rel = f64::abs(expected[i] - actual[i]) / expected[i];
+        assert!(
+            rel < 0.03,
+            "channel {i}: expected {} got {}",
+            expected[i],
+            actual[i]
+        );
+    }
+}
@@ -0,0 +1,187 @@
+mod common;
+
+use std::sync::Arc;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use raytrace::{
+    animation::{
+        frame_time, AnimatedObject, Animation, CameraAnimation, CameraPose, Interpolation,
+        Keyframes, Transform,
+    },
+    bvh::Bvh,
+    camera::PerspectiveCamera,
+    film::Film,
+    hittable::{Hittable, HittableList, Sphere},
+    integrator::PathTracer,
+    light::GradientSky,
+    material::Lambertian,
+    render::render,
+    sampler::IndependentSampler,
+    types::{Ray, Vec3},
+};
+
+use common::{region_mean, Region, HEIGHT, WIDTH};
+
+fn random_spheres(rng: &mut StdRng, count: usize) -> HittableList {
+    let mut list = HittableList::new();
+    for _ in 0..count {
+        list.add(Box::new(Sphere::new(
+            Vec3::new(
+                rng.gen_range(-10. ..10.),
+                rng.gen_range(-10. ..10.),
+                rng.gen_range(-10. ..10.),
+            ),
+            rng.gen_range(0.2..1.5),
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        )));
+    }
+    list
+}
+
+#[test]
+fn keyframes_interpolate_between_keys() {
+    let linear = Keyframes::new(0., 1.).key(2., 3.).key(4., -1.);
+    assert_eq!(linear.at(-1.), 1.);
+    assert_eq!(linear.at(1.), 2.);
+    assert_eq!(linear.at(3.), 1.);
+    assert_eq!(linear.at(5.), -1.);
+
+    // The spline passes through every key without kinks
+    let spline = linear.with_interpolation(Interpolation::Spline);
+    for (time, value) in [(0., 1.), (2., 3.), (4., -1.)] {
+        assert!((spline.at(time) - value).abs() < 1e-12);
+    }
+    let h = 1e-6;
+    let slope_before = (spline.at(2.) - spline.at(2. - h)) / h;
+    let slope_after = (spline.at(2. + h) - spline.at(2.)) / h;
+    assert!((slope_before - slope_after).abs() < 1e-3);
+}
+
+#[test]
+fn bvh_finds_the_same_hits_as_a_list() {
+    let list = random_spheres(&mut StdRng::seed_from_u64(1), 200);
+    let bvh = Bvh::new(random_spheres(&mut StdRng::seed_from_u64(1), 200));
+
+    let mut rng = StdRng::seed_from_u64(2);
+    let mut hits = 0;
+    for _ in 0..1000 {
+        let ray = Ray::from(
+            Vec3::new(rng.gen_range(-15. ..15.), rng.gen_range(-15. ..15.), 20.),
+            Vec3::new(rng.gen_range(-0.5..0.5), rng.gen_range(-0.5..0.5), -1.),
+        );
+        let expected = list.hit((0.001, f64::INFINITY), &ray).map(|hit| hit.t);
+        let actual = bvh.hit((0.001, f64::INFINITY), &ray).map(|hit| hit.t);
+        assert_eq!(expected, actual);
+        hits += expected.is_some() as usize;
+    }
+    assert!(hits > 100);
+}
+
+#[test]
+fn animated_objects_move_between_frames() {
+    let mut sphere = HittableList::new();
+    sphere.add(Box::new(Sphere::new(
+        Vec3::new(0, 0, 0),
+        1.,
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+    )));
+    let object = AnimatedObject::new(Arc::new(sphere))
+        .with_translation(Keyframes::new(0., Vec3::new(0, 0, 0)).key(1., Vec3::new(4, 0, 0)));
+    let animation = Animation::new(HittableList::new(), Vec::new()).with_object(object);
+
+    let ray = Ray::from(Vec3::new(4, 0, 10), Vec3::new(0, 0, -1));
+    let hit = |time: f64| animation.scene_at(time).hit(&ray);
+    assert!(hit(0.).is_none());
+    let hit_rec = hit(1.).expect("the sphere has moved into the ray");
+    assert!((hit_rec.t - 9.).abs() < 1e-9);
+    assert!((hit_rec.normal.z() - 1.).abs() < 1e-9);
+}
+
+#[test]
+fn frames_show_an_object_where_its_keys_put_it() {
+    let at = |x| Transform {
+        translation: Vec3::new(x, 0, 0),
+        ..Transform::default()
+    };
+    let sphere = Sphere::new(
+        Vec3::default(),
+        1.,
+        Arc::new(Lambertian::new(Vec3::new(0.1, 0.1, 0.1))),
+    );
+    let object = AnimatedObject::starting_at(Arc::new(sphere), 0., at(-2)).key(1., at(2));
+    let animation = Animation::new(HittableList::new(), vec![Arc::new(GradientSky::default())])
+        .with_object(object);
+    let camera_animation = CameraAnimation::still(
+        0.,
+        CameraPose {
+            look_from: Vec3::new(0, 0, 10),
+            look_at: Vec3::new(0, 0, 0),
+            vert_fov: 40.,
+        },
+    );
+
+    // The sphere covers the left region in the first frame and the right one
+    // in the second, where the sky shows through before
+    let left = Region {
+        name: "left",
+        x: 6..10,
+        y: 6..10,
+    };
+    let right = Region {
+        name: "right",
+        x: 14..18,
+        y: 6..10,
+    };
+    let frames = 2;
+    let films: Vec<Film> = (0..frames)
+        .map(|frame| {
+            let time = frame_time((0., 2.), frame, frames);
+            let pose = camera_animation.pose(time);
+            let camera = PerspectiveCamera::new(
+                pose.look_from,
+                pose.look_at,
+                Vec3::new(0, 1, 0),
+                pose.vert_fov,
+                WIDTH as f64 / HEIGHT as f64,
+                0.,
+                10.,
+            );
+            let mut film = Film::new(WIDTH, HEIGHT);
+            render(
+                &animation.scene_at(time),
+                &camera,
+                &PathTracer::new(5),
+                &IndependentSampler::new(0),
+                &mut film,
+                4,
+            );
+            film
+        })
+        .collect();
+
+    let brightness = |film, region| region_mean(film, region).luminance();
+    assert!(brightness(&films[0], &left) < 0.5 * brightness(&films[0], &right));
+    assert!(brightness(&films[1], &right) < 0.5 * brightness(&films[1], &left));
+}
+
+#[test]
+fn turntable_circles_the_target() {
+    let look_from = Vec3::new(13, 2, 3);
+    let look_at = Vec3::new(0, 0, 0);
+    let animation = CameraAnimation::turntable(look_from, look_at, 20., (1., 3.));
+
+    let start = animation.pose(1.);
+    assert!((start.look_from - look_from).length() < 1e-12);
+    assert!((animation.pose(3.).look_from - look_from).length() < 1e-9);
+    let radius = f64::hypot(13., 3.);
+    for i in 0..=40 {
+        let pose = animation.pose(1. + i as f64 / 20.);
+        let r = f64::hypot(pose.look_from.x(), pose.look_from.z());
+        assert!((r - radius).abs() < 1e-3 * radius);
+        assert_eq!(pose.look_from.y(), 2.);
+        assert_eq!(pose.vert_fov, 20.);
+    }
+    // Halfway round it is on the other side
+    let half = animation.pose(2.).look_from;
+    assert!((half + Vec3::new(13, -2, 3)).length() < 1e-9);
+}
@@ -0,0 +1,167 @@
+mod common;
+
+use std::io::ErrorKind;
+use std::sync::Arc;
+
+use raytrace::{
+    aperture::{Aperture, ApertureMask},
+    bdpt::BidirectionalPathTracer,
+    camera::{Camera, PerspectiveCamera},
+    integrator::PathTracer,
+    sampler::{IndependentSampler, Sampler},
+    types::Vec3,
+    Ppm,
+};
+
+use common::{assert_regions_close, render_film, Region};
+
+// Estimates the integral of the density over the square around the unit disk
+fn total_probability(aperture: &Aperture) -> f64 {
+    let n = 200;
+    let mut sum = 0.;
+    for i in 0..n {
+        for j in 0..n {
+            let x = 2. * (i as f64 + 0.5) / n as f64 - 1.;
+            let y = 2. * (j as f64 + 0.5) / n as f64 - 1.;
+            sum += aperture.pdf((x, y));
+        }
+    }
+    sum * 4. / (n * n) as f64
+}
+
+#[test]
+fn polygonal_samples_lie_inside() {
+    let aperture = Aperture::polygonal(6, 15.);
+    assert!((total_probability(&aperture) - 1.).abs() < 0.01);
+    for i in 0..64 {
+        for j in 0..64 {
+            let u = ((i as f64 + 0.5) / 64., (j as f64 + 0.5) / 64.);
+            assert!(aperture.pdf(aperture.sample(u)) > 0.);
+        }
+    }
+    // A corner at 15°, the middle of an edge at 45°
+    let point = |degrees: f64, r: f64| {
+        let angle = degrees.to_radians();
+        (r * angle.cos(), r * angle.sin())
+    };
+    assert!(aperture.pdf(point(15., 0.99)) > 0.);
+    assert!(aperture.pdf(point(45., 0.9)) == 0.);
+}
+
+#[test]
+fn mask_follows_image() {
+    // Binary greyscale image, transparent in its left half only
+    let mut data = b"P5\n# mask\n4 2\n255\n".to_vec();
+    data.extend_from_slice(&[255, 128, 0, 0, 255, 128, 0, 0]);
+    let image = Ppm::read(&mut data.as_slice()).unwrap();
+    assert_eq!(image.get_width(), 4);
+    assert_eq!(image.get_pixel(1, 0).x(), 128.);
+    // Sizes the data can not hold are rejected before allocating for them
+    for header in [
+        "P5 4294967295 4294967295 255\n",
+        "P6 18446744073709551615 1 255\n",
+        "P2 0 4294967295 255\n",
+        "P5 4 2 255\n\x01",
+    ] {
+        let result = Ppm::read(&mut header.as_bytes());
+        assert!(result.is_err_and(|err| err.kind() == ErrorKind::InvalidData));
+    }
+
+    let aperture = Aperture::Mask(Arc::new(ApertureMask::from_image(&image).unwrap()));
+    assert!((total_probability(&aperture) - 1.).abs() < 1e-9);
+    // Twice as likely in the brighter of the two columns
+    let ratio = aperture.pdf((-0.75, 0.5)) / aperture.pdf((-0.25, 0.5));
+    assert!((ratio - 255. / 128.).abs() < 1e-9);
+    for i in 0..16 {
+        let (x, _) = aperture.sample(((i as f64 + 0.5) / 16., 0.3));
+        assert!(x < 0.);
+    }
+
+    let black = Ppm::from(2, 2);
+    assert!(ApertureMask::from_image(&black).is_none());
+}
+
+#[test]
+fn cats_eye_vignettes_corners() {
+    let camera = |strength| {
+        PerspectiveCamera::new(
+            Vec3::new(0, 0, 5),
+            Vec3::new(0, 0, 0),
+            Vec3::new(0, 1, 0),
+            60.,
+            1.,
+            1.,
+            5.,
+        )
+        .with_cats_eye(strength)
+    };
+    // Fraction of rays through film position (s, t) the barrel lets through
+    let unblocked = |camera: &PerspectiveCamera, s, t| {
+        let mut sampler = IndependentSampler::new(0);
+        (0..1000)
+            .filter(|&index| {
+                sampler.start_pixel_sample((0, 0), index);
+                camera
+                    .get_ray(s, t, &mut sampler)
+                    .is_some_and(|(_, weight)| weight > 0.)
+            })
+            .count() as f64
+            / 1000.
+    };
+
+    assert_eq!(unblocked(&camera(0.), 0., 0.), 1.);
+    assert_eq!(unblocked(&camera(1.), 0.5, 0.5), 1.);
+    // Two unit circles one apart overlap in 39% of their area
+    let corner = unblocked(&camera(1.), 0.999, 0.999);
+    assert!((corner - 0.391).abs() < 0.05, "{corner}");
+    assert_eq!(unblocked(&camera(2.), 0., 0.999), 0.);
+}
+
+// Connecting light paths to the lens has to use the same aperture and
+// vignetting as the rays the camera generates
+#[test]
+fn bdpt_matches_path_tracer_with_shaped_aperture() {
+    let scene = common::scene(None);
+    let camera = PerspectiveCamera::new(
+        Vec3::new(0, 1, 6),
+        Vec3::new(0, 1, 0),
+        Vec3::new(0, 1, 0),
+        60.,
+        1.5,
+        1.,
+        3.,
+    )
+    .with_aperture(Aperture::polygonal(5, 10.))
+    .with_cats_eye(1.);
+
+    let expected = render_film(&scene, &camera, &PathTracer::new(5), 1024);
+    let actual = render_film(&scene, &camera, &BidirectionalPathTracer::new(5), 256);
+    // The corner is seen through the narrowest part of the barrel
+    let regions = [
+        (
+            Region {
+                name: "defocused light",
+                x: 9..15,
+                y: 12..16,
+            },
+            0.05,
+        ),
+        (
+            Region {
+                name: "lit ground",
+                x: 6..18,
+                y: 3..7,
+            },
+            0.03,
+        ),
+        (
+            Region {
+                name: "corner",
+                x: 0..4,
+                y: 0..8,
+            },
+            0.06,
+        ),
+    ];
+    assert_regions_close(&expected, &actual, &regions);
+}
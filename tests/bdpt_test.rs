@@ -0,0 +1,134 @@
+mod common;
+
+use std::sync::Arc;
+
+use raytrace::{
+    bdpt::BidirectionalPathTracer,
+    camera::PerspectiveCamera,
+    film::Film,
+    integrator::PathTracer,
+    material::{Dielectric, Dispersion, Lambertian},
+    types::Vec3,
+};
+
+use common::{
+    assert_luminance_close, assert_regions_close, camera, render_film, scene, Region, GROUND,
+    HEIGHT, SPHERE, UNDER_SPHERE, WIDTH,
+};
+
+// Regions of the close up of the sphere: the reflection of the light in its
+// top, its body, the light it focuses on the ground and the ground beside it
+const REFLECTION: Region = Region {
+    name: "reflection",
+    x: 9..15,
+    y: 13..15,
+};
+const BODY: Region = Region {
+    name: "body",
+    x: 6..18,
+    y: 3..12,
+};
+const CAUSTIC: Region = Region {
+    name: "caustic",
+    x: 8..16,
+    y: 0..1,
+};
+const BESIDE: Region = Region {
+    name: "ground beside",
+    x: 0..4,
+    y: 0..7,
+};
+
+#[test]
+fn matches_path_tracer_on_diffuse_scene() {
+    let scene = scene(Some(Arc::new(Lambertian::new(Vec3::new(0.7, 0.3, 0.3)))));
+    let expected = render_film(&scene, &camera(), &PathTracer::new(5), 1024);
+    let actual = render_film(&scene, &camera(), &BidirectionalPathTracer::new(5), 256);
+    assert_regions_close(
+        &expected,
+        &actual,
+        &[(UNDER_SPHERE, 0.1), (SPHERE, 0.05), (GROUND, 0.02)],
+    );
+}
+
+#[test]
+fn matches_path_tracer_through_glass() {
+    // The caustic under the sphere takes the most samples to settle
+    let scene = scene(Some(Arc::new(Dielectric::new(1.5))));
+    let expected = render_film(&scene, &camera(), &PathTracer::new(5), 1024);
+    let actual = render_film(&scene, &camera(), &BidirectionalPathTracer::new(5), 512);
+    assert_regions_close(
+        &expected,
+        &actual,
+        &[(UNDER_SPHERE, 0.2), (SPHERE, 0.05), (GROUND, 0.02)],
+    );
+}
+
+#[test]
+fn matches_path_tracer_through_rough_dense_glass() {
+    // Light paths connect to camera paths inside the sphere, where radiance
+    // is concentrated by refraction
+    let scene = scene(Some(Arc::new(Dielectric::new(2.4).with_roughness(0.6))));
+    let expected = render_film(&scene, &camera(), &PathTracer::new(5), 1024);
+    let actual = render_film(&scene, &camera(), &BidirectionalPathTracer::new(5), 256);
+    assert_regions_close(
+        &expected,
+        &actual,
+        &[(UNDER_SPHERE, 0.2), (SPHERE, 0.05), (GROUND, 0.02)],
+    );
+}
+
+#[test]
+fn matches_path_tracer_through_rough_dispersive_glass() {
+    let glass = Dielectric::new(1.5)
+        .with_roughness(0.1)
+        .with_dispersion(Dispersion::sf11());
+    // The colors of single wavelengths are too noisy to compare
+    let (expected, actual) = render_close_up(glass);
+    assert_luminance_close(
+        &expected,
+        &actual,
+        &[
+            (REFLECTION, 0.25),
+            (BODY, 0.02),
+            (CAUSTIC, 0.2),
+            (BESIDE, 0.04),
+        ],
+    );
+}
+
+#[test]
+fn matches_path_tracer_through_rough_tinted_glass() {
+    let glass = Dielectric::new(1.5)
+        .with_roughness(0.6)
+        .with_absorption(Vec3::new(0.6, 2.4, 4.5));
+    let (expected, actual) = render_close_up(glass);
+    assert_regions_close(
+        &expected,
+        &actual,
+        &[
+            (REFLECTION, 0.2),
+            (BODY, 0.05),
+            (CAUSTIC, 0.2),
+            (BESIDE, 0.04),
+        ],
+    );
+}
+
+// Renders a sphere of `glass` seen close enough to fill most of the image
+// with the path tracer and with BDPT
+fn render_close_up(glass: Dielectric) -> (Film, Film) {
+    let scene = scene(Some(Arc::new(glass)));
+    let camera = PerspectiveCamera::new(
+        Vec3::new(0, 1.5, 3.5),
+        Vec3::new(0, 1, 0),
+        Vec3::new(0, 1, 0),
+        35.,
+        WIDTH as f64 / HEIGHT as f64,
+        0.,
+        3.5,
+    );
+    let expected = render_film(&scene, &camera, &PathTracer::new(5), 1024);
+    let actual = render_film(&scene, &camera, &BidirectionalPathTracer::new(5), 256);
+    (expected, actual)
+}
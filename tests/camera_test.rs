@@ -0,0 +1,164 @@
+mod common;
+
+use raytrace::{
+    bdpt::BidirectionalPathTracer,
+    camera::{
+        Camera, CubemapCamera, EquirectangularCamera, FisheyeCamera, FisheyeMapping,
+        OrthographicCamera,
+    },
+    integrator::PathTracer,
+    sampler::IndependentSampler,
+    types::{Ray, Vec3},
+};
+
+use common::{assert_regions_close, render_film, Region, HEIGHT, WIDTH};
+
+fn ray(camera: &dyn Camera, s: f64, t: f64) -> Option<Ray> {
+    camera
+        .get_ray(s, t, &mut IndependentSampler::new(0))
+        .map(|(ray, _)| ray)
+}
+
+fn direction(camera: &dyn Camera, s: f64, t: f64) -> Vec3 {
+    Vec3::unit_vector(&ray(camera, s, t).unwrap().direction)
+}
+
+fn assert_direction(actual: Vec3, expected: Vec3) {
+    assert!(
+        (actual - expected).length() < 1e-9,
+        "expected ({}, {}, {}) got ({}, {}, {})",
+        expected.x(),
+        expected.y(),
+        expected.z(),
+        actual.x(),
+        actual.y(),
+        actual.z()
+    );
+}
+
+// Looking down -z with y up, so the camera's right is +x
+fn look_from() -> Vec3 {
+    Vec3::new(0, 1, 5)
+}
+
+fn look_at() -> Vec3 {
+    Vec3::new(0, 1, 0)
+}
+
+fn up() -> Vec3 {
+    Vec3::new(0, 1, 0)
+}
+
+#[test]
+fn orthographic_rays_are_parallel() {
+    let camera = OrthographicCamera::new(look_from(), look_at(), up(), 4., 1.5);
+    let centre = ray(&camera, 0.5, 0.5).unwrap();
+    let corner = ray(&camera, 0., 1.).unwrap();
+    assert_direction(direction(&camera, 0., 1.), Vec3::new(0, 0, -1));
+    assert_direction(centre.origin, look_from());
+    assert_direction(corner.origin, look_from() + Vec3::new(-3, 2, 0));
+}
+
+#[test]
+fn fisheye_maps_radius_to_angle() {
+    for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+        let camera = FisheyeCamera::new(look_from(), look_at(), up(), 180., 1., mapping);
+        assert_direction(direction(&camera, 0.5, 0.5), Vec3::new(0, 0, -1));
+        // The edge of the image circle is 90° off the viewing direction
+        assert_direction(direction(&camera, 1., 0.5), Vec3::new(1, 0, 0));
+        assert_direction(direction(&camera, 0.5, 1.), Vec3::new(0, 1, 0));
+        assert!(ray(&camera, 0.01, 0.01).is_none());
+    }
+
+    let angle = |mapping| {
+        let camera = FisheyeCamera::new(look_from(), look_at(), up(), 180., 1., mapping);
+        let dir = direction(&camera, 0.75, 0.5);
+        f64::acos(-dir.z()).to_degrees()
+    };
+    assert!((angle(FisheyeMapping::Equidistant) - 45.).abs() < 1e-9);
+    // 2 asin(sin(45°) / 2)
+    assert!((angle(FisheyeMapping::Equisolid) - 41.40962210927086).abs() < 1e-9);
+}
+
+#[test]
+fn equirectangular_covers_sphere() {
+    let camera = EquirectangularCamera::new(look_from(), look_at(), up());
+    assert_direction(direction(&camera, 0.5, 0.5), Vec3::new(0, 0, -1));
+    assert_direction(direction(&camera, 0.75, 0.5), Vec3::new(1, 0, 0));
+    assert_direction(direction(&camera, 0.25, 0.5), Vec3::new(-1, 0, 0));
+    assert_direction(direction(&camera, 0., 0.5), Vec3::new(0, 0, 1));
+    assert_direction(direction(&camera, 0.3, 1.), Vec3::new(0, 1, 0));
+    assert_direction(direction(&camera, 0.6, 0.), Vec3::new(0, -1, 0));
+}
+
+#[test]
+fn cubemap_faces_meet_at_edges() {
+    let camera = CubemapCamera::new(look_from(), look_at(), up());
+    let sqrt3 = f64::sqrt(3.);
+    let diagonal = |x: i32, y: i32, z: i32| Vec3::new(x, y, z) / sqrt3;
+
+    // Face centres: left, front, right on top, then back, down, up
+    assert_direction(direction(&camera, 1. / 6., 0.75), Vec3::new(-1, 0, 0));
+    assert_direction(direction(&camera, 0.5, 0.75), Vec3::new(0, 0, -1));
+    assert_direction(direction(&camera, 5. / 6., 0.75), Vec3::new(1, 0, 0));
+    assert_direction(direction(&camera, 1. / 6., 0.25), Vec3::new(0, 0, 1));
+    assert_direction(direction(&camera, 0.5, 0.25), Vec3::new(0, -1, 0));
+    assert_direction(direction(&camera, 5. / 6., 0.25), Vec3::new(0, 1, 0));
+
+    // The top right corner of the front face is shared with the top left
+    // corner of the right face and the bottom right one of the up face
+    let corner = diagonal(1, 1, -1);
+    assert_direction(direction(&camera, 2. / 3. - 1e-12, 1.), corner);
+    assert_direction(direction(&camera, 2. / 3. + 1e-12, 1.), corner);
+    assert_direction(direction(&camera, 1., 0.), corner);
+    // and its bottom left corner is the top left one of the down face
+    let corner = diagonal(-1, -1, -1);
+    assert_direction(direction(&camera, 1. / 3. + 1e-12, 0.5 + 1e-12), corner);
+    assert_direction(direction(&camera, 1. / 3. + 1e-12, 0.5 - 1e-12), corner);
+}
+
+// Bidirectional path tracing cannot connect light paths to panoramic cameras
+// and has to weight its other strategies accordingly
+#[test]
+fn bdpt_matches_path_tracer_through_panoramic_camera() {
+    let scene = common::scene(None);
+    let camera = EquirectangularCamera::new(look_from(), look_at(), up());
+
+    let expected = render_film(&scene, &camera, &PathTracer::new(5), 1024);
+    let actual = render_film(&scene, &camera, &BidirectionalPathTracer::new(5), 256);
+    let regions = [
+        (
+            Region {
+                name: "light",
+                x: 11..13,
+                y: 9..12,
+            },
+            0.08,
+        ),
+        (
+            Region {
+                name: "lit ground",
+                x: 8..16,
+                y: 3..7,
+            },
+            0.04,
+        ),
+        (
+            Region {
+                name: "far ground",
+                x: 0..WIDTH,
+                y: 0..3,
+            },
+            0.015,
+        ),
+        (
+            Region {
+                name: "sky",
+                x: 0..WIDTH,
+                y: 12..HEIGHT,
+            },
+            0.01,
+        ),
+    ];
+    assert_regions_close(&expected, &actual, &regions);
+}
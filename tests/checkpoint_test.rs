@@ -0,0 +1,119 @@
+use std::{env, fs, path::PathBuf, sync::Arc};
+
+use raytrace::{
+    bdpt::BidirectionalPathTracer,
+    camera::PerspectiveCamera,
+    checkpoint,
+    film::Film,
+    filter::Filter,
+    hittable::{HittableList, Sphere},
+    light::{GradientSky, Light, SphereLight},
+    material::Lambertian,
+    render::{render_progressive, ProgressiveRendering},
+    sampler::HaltonSampler,
+    scene::Scene,
+    types::Vec3,
+};
+
+const WIDTH: usize = 16;
+const HEIGHT: usize = 12;
+const SAMPLES: usize = 8;
+const SAMPLES_PER_PASS: usize = 2;
+const SETTINGS: &str = "bdpt halton seed=3";
+
+fn scene() -> Scene {
+    let mut world = HittableList::new();
+    world.add(Box::new(Sphere::new(
+        Vec3::new(0, -100, 0),
+        100.,
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+    )));
+
+    let light = Arc::new(SphereLight::new(Vec3::new(0, 4, 0), 1., Vec3::new(4, 4, 4)));
+    world.add(Box::new(light.sphere()));
+    let sky = Arc::new(GradientSky::new(
+        Vec3::new(0.1, 0.1, 0.1),
+        Vec3::new(0.05, 0.07, 0.1),
+    ));
+    let lights: Vec<Arc<dyn Light>> = vec![light, sky];
+
+    Scene::new(world, lights)
+}
+
+fn camera() -> PerspectiveCamera {
+    PerspectiveCamera::new(
+        Vec3::new(0, 2, 8),
+        Vec3::new(0, 1, 0),
+        Vec3::new(0, 1, 0),
+        40.,
+        WIDTH as f64 / HEIGHT as f64,
+        0.1,
+        8.,
+    )
+}
+
+fn film() -> Film {
+    Film::with_filter(WIDTH, HEIGHT, Filter::Tent { radius: 1. })
+}
+
+fn render_film(film: &mut Film, settings: &ProgressiveRendering) {
+    render_progressive(
+        &scene(),
+        &camera(),
+        &BidirectionalPathTracer::new(5),
+        &HaltonSampler::new(3),
+        film,
+        settings,
+        |_, _| {},
+    );
+}
+
+fn checkpoint_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("raytrace_{}_{name}.ckpt", std::process::id()))
+}
+
+#[test]
+fn resumed_render_matches_uninterrupted_one() {
+    let mut expected = film();
+    render_film(
+        &mut expected,
+        &ProgressiveRendering::new(SAMPLES, SAMPLES_PER_PASS),
+    );
+
+    let path = checkpoint_path("resume");
+    // Stopped halfway, after saving a checkpoint
+    let mut interrupted = film();
+    render_film(
+        &mut interrupted,
+        &ProgressiveRendering::new(4, SAMPLES_PER_PASS),
+    );
+    checkpoint::save(&path, SETTINGS, &interrupted, 4).unwrap();
+    drop(interrupted);
+
+    let mut resumed = film();
+    let taken = checkpoint::load(&path, SETTINGS, &mut resumed).unwrap();
+    assert_eq!(taken, 4);
+    render_film(
+        &mut resumed,
+        &ProgressiveRendering::new(SAMPLES, SAMPLES_PER_PASS).resume_from(taken),
+    );
+    fs::remove_file(&path).unwrap();
+
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let (a, b) = (resumed.pixel(x, y), expected.pixel(x, y));
+            assert_eq!((a.x(), a.y(), a.z()), (b.x(), b.y(), b.z()));
+        }
+    }
+}
+
+#[test]
+fn checkpoint_with_other_settings_is_rejected() {
+    let path = checkpoint_path("settings");
+    checkpoint::save(&path, SETTINGS, &film(), 2).unwrap();
+
+    let mut resumed = film();
+    assert!(checkpoint::load(&path, "bdpt halton seed=4", &mut resumed).is_err());
+    assert!(checkpoint::load(&path, SETTINGS, &mut Film::new(WIDTH + 1, HEIGHT)).is_err());
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,176 @@
+//! Scene, camera and comparisons shared by the integration tests
+#![allow(dead_code)]
+
+use std::{ops::Range, sync::Arc};
+
+use raytrace::{
+    camera::{Camera, PerspectiveCamera},
+    film::Film,
+    hittable::{HittableList, Sphere},
+    integrator::Integrator,
+    light::{GradientSky, Light, SphereLight},
+    material::{Lambertian, Material},
+    render::render,
+    sampler::IndependentSampler,
+    scene::Scene,
+    types::Vec3,
+};
+
+pub const WIDTH: usize = 24;
+pub const HEIGHT: usize = 16;
+
+/// A block of pixels, counted from the bottom left of the image
+pub struct Region {
+    pub name: &'static str,
+    pub x: Range<usize>,
+    pub y: Range<usize>,
+}
+
+/// The ground just under the sphere seen through `camera`, where glass
+/// focuses the light
+pub const UNDER_SPHERE: Region = Region {
+    name: "under the sphere",
+    x: 10..14,
+    y: 4..6,
+};
+
+/// The middle of the sphere seen through `camera`
+pub const SPHERE: Region = Region {
+    name: "sphere",
+    x: 9..15,
+    y: 6..11,
+};
+
+/// The open ground in front of the sphere seen through `camera`
+pub const GROUND: Region = Region {
+    name: "ground",
+    x: 0..WIDTH,
+    y: 0..4,
+};
+
+/// A grey ground lit by a spherical light above it and a dim sky, with a unit
+/// sphere of `material` resting on the ground if given
+pub fn scene(material: Option<Arc<dyn Material>>) -> Scene {
+    let mut world = HittableList::new();
+    world.add(Box::new(Sphere::new(
+        Vec3::new(0, -100, 0),
+        100.,
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+    )));
+    if let Some(material) = material {
+        world.add(Box::new(Sphere::new(Vec3::new(0, 1, 0), 1., material)));
+    }
+
+    let light = Arc::new(SphereLight::new(Vec3::new(0, 4, 0), 1., Vec3::new(4, 4, 4)));
+    world.add(Box::new(light.sphere()));
+    let sky = Arc::new(GradientSky::new(
+        Vec3::new(0.1, 0.1, 0.1),
+        Vec3::new(0.05, 0.07, 0.1),
+    ));
+    let lights: Vec<Arc<dyn Light>> = vec![light, sky];
+
+    Scene::new(world, lights)
+}
+
+/// Looks at the sphere of `scene` from a little above
+pub fn camera() -> PerspectiveCamera {
+    PerspectiveCamera::new(
+        Vec3::new(0, 2, 8),
+        Vec3::new(0, 1, 0),
+        Vec3::new(0, 1, 0),
+        40.,
+        WIDTH as f64 / HEIGHT as f64,
+        0.,
+        8.,
+    )
+}
+
+pub fn mean(film: &Film) -> Vec3 {
+    let (width, height) = (film.get_width(), film.get_height());
+    let mut sum = Vec3::default();
+    for y in 0..height {
+        for x in 0..width {
+            sum += film.pixel(x, y);
+        }
+    }
+    sum / (width * height) as f64
+}
+
+/// Image of `scene` rendered through `camera`
+pub fn render_film(
+    scene: &Scene,
+    camera: &dyn Camera,
+    integrator: &dyn Integrator,
+    num_samples: usize,
+) -> Film {
+    let mut film = Film::new(WIDTH, HEIGHT);
+    render(
+        scene,
+        camera,
+        integrator,
+        &IndependentSampler::new(0),
+        &mut film,
+        num_samples,
+    );
+    film
+}
+
+/// Mean of an image of `scene` rendered through `camera`
+pub fn mean_radiance(
+    scene: &Scene,
+    camera: &dyn Camera,
+    integrator: &dyn Integrator,
+    num_samples: usize,
+) -> Vec3 {
+    mean(&render_film(scene, camera, integrator, num_samples))
+}
+
+pub fn region_mean(film: &Film, region: &Region) -> Vec3 {
+    let mut sum = Vec3::default();
+    for y in region.y.clone() {
+        for x in region.x.clone() {
+            sum += film.pixel(x, y);
+        }
+    }
+    sum / (region.x.len() * region.y.len()) as f64
+}
+
+/// Checks the mean of each region of `actual` is within its tolerance of the
+/// one of `expected`, relatively
+pub fn assert_regions_close(expected: &Film, actual: &Film, regions: &[(Region, f64)]) {
+    for (region, tolerance) in regions {
+        let (expected, actual) = (region_mean(expected, region), region_mean(actual, region));
+        check_close(region.name, expected, actual, *tolerance);
+    }
+}
+
+/// Like `assert_regions_close` but for the luminance of the regions, for
+/// images whose colors are noisy
+pub fn assert_luminance_close(expected: &Film, actual: &Film, regions: &[(Region, f64)]) {
+    for (region, tolerance) in regions {
+        let expected = region_mean(expected, region).luminance();
+        let actual = region_mean(actual, region).luminance();
+        assert!(
+            f64::abs(expected - actual) / expected < *tolerance,
+            "{}: expected {expected} got {actual}",
+            region.name
+        );
+    }
+}
+
+/// Checks every channel is within `tolerance` of the expected one, relatively
+pub fn assert_close(expected: Vec3, actual: Vec3, tolerance: f64) {
+    check_close("image", expected, actual, tolerance);
+}
+
+fn check_close(what: &str, expected: Vec3, actual: Vec3, tolerance: f64) {
+    for i in 0..3 {
+        let rel = f64::abs(expected[i] - actual[i]) / expected[i];
+        assert!(
+            rel < tolerance,
+            "{what} channel {i}: expected {} got {}",
+            expected[i],
+            actual[i]
+        );
+    }
+}
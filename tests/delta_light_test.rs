@@ -0,0 +1,145 @@
+mod common;
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use raytrace::{
+    bdpt::BidirectionalPathTracer,
+    camera::{Camera, PerspectiveCamera},
+    hittable::{HittableList, Sphere},
+    integrator::PathTracer,
+    light::{DirectionalLight, Light, PointLight, SpotLight},
+    material::Lambertian,
+    photon::PhotonMapper,
+    sampler::IndependentSampler,
+    scene::Scene,
+    types::{Ray, Vec3},
+};
+
+use common::{mean_radiance, HEIGHT, WIDTH};
+
+const ALBEDO: f64 = 0.5;
+
+fn camera() -> PerspectiveCamera {
+    PerspectiveCamera::new(
+        Vec3::new(0, 3, 6),
+        Vec3::new(0, 0, 0),
+        Vec3::new(0, 1, 0),
+        50.,
+        WIDTH as f64 / HEIGHT as f64,
+        0.,
+        6.,
+    )
+}
+
+// A ground that is lit only directly, since it cannot see itself
+fn scene(lights: Vec<Arc<dyn Light>>) -> Scene {
+    let mut world = HittableList::new();
+    world.add(Box::new(Sphere::new(
+        Vec3::new(0, -1000, 0),
+        1000.,
+        Arc::new(Lambertian::new(Vec3::new(ALBEDO, ALBEDO, ALBEDO))),
+    )));
+    Scene::new(world, lights)
+}
+
+#[test]
+fn delta_lights_fall_off_as_expected() {
+    let point = PointLight::new(Vec3::new(0, 2, 0), Vec3::new(8, 8, 8));
+    let world = (Vec3::default(), 10.);
+    let sample = point
+        .sample_li(&Vec3::new(0, -2, 0), (0.3, 0.7), world)
+        .unwrap();
+    assert_eq!(sample.radiance.x(), 0.5);
+    assert_eq!(sample.wi.y(), 1.);
+    assert_eq!(point.pdf_li(&Vec3::default(), &sample.wi), 0.);
+    assert!(point.is_delta() && !point.is_infinite());
+
+    // A 30° cone that fades out over its outer 10°
+    let spot = SpotLight::new(
+        Vec3::default(),
+        Vec3::new(0, -1, 0),
+        Vec3::new(1, 1, 1),
+        30.,
+    )
+    .with_falloff(10.);
+    let at = |degrees: f64| {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let p = Vec3::new(sin, -cos, 0);
+        spot.sample_li(&p, (0.5, 0.5), world).unwrap().radiance.x()
+    };
+    assert_eq!(at(0.), 1.);
+    assert_eq!(at(19.), 1.);
+    assert!(at(22.) > at(25.) && at(25.) > at(28.) && at(28.) > 0.);
+    assert_eq!(at(31.), 0.);
+
+    let directional = DirectionalLight::new(Vec3::new(0, 2, 0), Vec3::new(3, 3, 3));
+    let sample = directional
+        .sample_li(&Vec3::new(5, 0, 5), (0.1, 0.9), world)
+        .unwrap();
+    assert_eq!(
+        (sample.wi.y(), sample.radiance.x(), sample.pdf),
+        (1., 3., 1.)
+    );
+    assert!(directional.is_delta() && directional.is_infinite());
+    assert!(directional
+        .le(&Ray::from(Vec3::default(), Vec3::new(0, 1, 0)))
+        .is_zero());
+}
+
+#[test]
+fn integrators_that_sample_lights_match_direct_lighting() {
+    let position = Vec3::new(1, 3, 0);
+    let intensity = 20.;
+    let direction = Vec3::unit_vector(&Vec3::new(-1, 2, 1));
+    let irradiance = 2.;
+    let scene = scene(vec![
+        Arc::new(PointLight::new(
+            position,
+            Vec3::new(intensity, intensity, intensity),
+        )),
+        Arc::new(DirectionalLight::new(
+            direction,
+            Vec3::new(irradiance, irradiance, irradiance),
+        )),
+    ]);
+
+    // The ground reflects albedo / π of the irradiance from both lights
+    let camera = camera();
+    let mut sampler = IndependentSampler::new(1);
+    let mut rng = StdRng::seed_from_u64(1);
+    let n = 20_000;
+    let mut expected = 0.;
+    for _ in 0..n {
+        let ray = camera
+            .generate_ray(rng.gen(), rng.gen(), &mut sampler)
+            .unwrap();
+        let Some(hit) = scene.hit(&ray) else {
+            continue;
+        };
+        let to_light = position - hit.p;
+        let cos_point = Vec3::dot(&hit.normal, &Vec3::unit_vector(&to_light));
+        let cos_directional = Vec3::dot(&hit.normal, &direction);
+        let e = intensity * f64::max(cos_point, 0.) / to_light.squared_len()
+            + irradiance * f64::max(cos_directional, 0.);
+        expected += ALBEDO / PI * e;
+    }
+    let expected = expected / n as f64;
+
+    let photons = PhotonMapper::new(&scene, 10_000, 0.1, 5, 0);
+    for (name, actual) in [
+        (
+            "path",
+            mean_radiance(&scene, &camera, &PathTracer::new(5), 16),
+        ),
+        (
+            "bdpt",
+            mean_radiance(&scene, &camera, &BidirectionalPathTracer::new(5), 16),
+        ),
+        ("photon", mean_radiance(&scene, &camera, &photons, 16)),
+    ] {
+        let rel = (actual.y() - expected).abs() / expected;
+        assert!(rel < 0.03, "{name}: expected {expected} got {}", actual.y());
+    }
+}
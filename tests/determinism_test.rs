@@ -0,0 +1,109 @@
+mod common;
+
+use std::sync::Arc;
+
+use raytrace::{
+    bdpt::BidirectionalPathTracer,
+    camera::PerspectiveCamera,
+    film::Film,
+    integrator::PathTracer,
+    material::Dielectric,
+    mlt::MetropolisLightTransport,
+    photon::ProgressivePhotonMapper,
+    render::render,
+    sampler::{IndependentSampler, SobolSampler},
+    scene::Scene,
+    stats::RayCounts,
+    types::Vec3,
+};
+
+use common::{camera, HEIGHT, WIDTH};
+
+// Renders with 1 and with 3 threads and checks the images are bit identical
+// and the same rays were traced
+fn assert_independent_of_threads(
+    render_film: impl Fn(&Scene, &PerspectiveCamera, &mut Film) -> RayCounts + Sync,
+) {
+    let scene = common::scene(Some(Arc::new(Dielectric::new(1.5))));
+    let (images, rays): (Vec<Vec<Vec3>>, Vec<RayCounts>) = [1, 3]
+        .iter()
+        .map(|&threads| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let mut film = Film::new(WIDTH, HEIGHT);
+            let rays = pool.install(|| render_film(&scene, &camera(), &mut film));
+            let image = (0..WIDTH * HEIGHT)
+                .map(|i| film.pixel(i % WIDTH, i / WIDTH))
+                .collect();
+            (image, rays)
+        })
+        .unzip();
+    assert_eq!(rays[0], rays[1]);
+
+    for (a, b) in images[0].iter().zip(images[1].iter()) {
+        assert_eq!((a.x(), a.y(), a.z()), (b.x(), b.y(), b.z()));
+    }
+}
+
+#[test]
+fn path_tracing_is_reproducible() {
+    assert_independent_of_threads(|scene, camera, film| {
+        render(
+            scene,
+            camera,
+            &PathTracer::new(5),
+            &IndependentSampler::new(7),
+            film,
+            8,
+        )
+    });
+}
+
+#[test]
+fn bidirectional_splats_are_reproducible() {
+    assert_independent_of_threads(|scene, camera, film| {
+        render(
+            scene,
+            camera,
+            &BidirectionalPathTracer::new(5),
+            &SobolSampler::new(8, 7),
+            film,
+            8,
+        )
+    });
+}
+
+#[test]
+fn photon_mapping_is_reproducible() {
+    assert_independent_of_threads(|scene, camera, film| {
+        ProgressivePhotonMapper::new(10_000, 0.25, 5, 7).render(scene, camera, film, 4)
+    });
+}
+
+#[test]
+fn metropolis_is_reproducible() {
+    assert_independent_of_threads(|scene, camera, film| {
+        MetropolisLightTransport::new(5, 1000, 8, 4, 7).render(scene, camera, film)
+    });
+}
+
+#[test]
+fn splats_keep_large_and_small_values() {
+    let film = Film::new(1, 1);
+    for _ in 0..4 {
+        film.add_splat((0.5, 0.5), Vec3::new(1e9, 1e-12, -3e9));
+    }
+    let pixel = film.pixel(0, 0);
+    assert_eq!(pixel.x(), 4e9);
+    assert!((pixel.y() - 4e-12).abs() < 1e-18, "{}", pixel.y());
+    assert_eq!(pixel.z(), -12e9);
+
+    // Sums beyond the fixed point range saturate rather than wrap around
+    let film = Film::new(1, 1);
+    film.add_splat((0.5, 0.5), Vec3::new(1e30, 1e30, -1e30));
+    film.add_splat((0.5, 0.5), Vec3::new(1e30, 1, -1));
+    let pixel = film.pixel(0, 0);
+    assert!(pixel.x() > 9e18 && pixel.y() > 9e18 && pixel.z() < -9e18);
+}
@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use raytrace::{
+    hittable::{HitRecord, Hittable, Sphere},
+    material::{Dielectric, Material},
+    sampler::IndependentSampler,
+    sampling,
+    types::{Ray, Vec3},
+};
+
+fn hit_record(material: Arc<dyn Material>) -> HitRecord {
+    HitRecord {
+        t: 1.,
+        p: Vec3::default(),
+        normal: Vec3::new(0, 0, 1),
+        mat: material,
+        light: None,
+        time: 0.,
+        wavelength: None,
+    }
+}
+
+// Fraction of the rays arriving against `wo` that are reflected
+fn reflected_fraction(material: Arc<dyn Material>, wo: Vec3, n: usize) -> f64 {
+    let record = hit_record(material);
+    let mut sampler = IndependentSampler::new(0);
+    let reflected = (0..n)
+        .filter(|_| {
+            let (ray, _) = record
+                .mat
+                .scatter(&Ray::from(wo, -wo), &record, &mut sampler)
+                .unwrap();
+            ray.direction.z() * wo.z() > 0.
+        })
+        .count();
+    reflected as f64 / n as f64
+}
+
+#[test]
+fn smooth_glass_follows_the_fresnel_equations() {
+    let glass = Arc::new(Dielectric::new(1.5));
+    assert!(glass.is_specular());
+    let n = 40_000;
+    // Reflectance at normal incidence and at 60°, where Schlick's
+    // approximation would give 0.07
+    for (wo, expected) in [
+        (Vec3::new(0, 0, 1), 0.04),
+        (Vec3::new(0.75f64.sqrt(), 0, 0.5), 0.0892),
+    ] {
+        let fraction = reflected_fraction(glass.clone(), wo, n);
+        assert!((fraction - expected).abs() < 0.003, "{fraction}");
+    }
+    // Past the critical angle light inside is reflected completely
+    let inside = Vec3::new(0.8, 0, -0.6);
+    assert_eq!(reflected_fraction(glass.clone(), inside, 1000), 1.);
+
+    // Refraction bends rays towards the normal by Snell's law
+    let record = hit_record(glass);
+    let wo = Vec3::new(0.6, 0, 0.8);
+    let mut sampler = IndependentSampler::new(1);
+    let refracted = (0..100)
+        .filter_map(|_| {
+            let (ray, weight) = record
+                .mat
+                .scatter(&Ray::from(wo, -wo), &record, &mut sampler)?;
+            (ray.direction.z() < 0.).then_some((Vec3::unit_vector(&ray.direction), weight))
+        })
+        .next()
+        .unwrap();
+    assert!((refracted.0.x() + 0.6 / 1.5).abs() < 1e-12);
+    assert_eq!(refracted.1.g(), 1.);
+}
+
+#[test]
+fn rough_glass_samples_its_bsdf() {
+    let mut sampler = IndependentSampler::new(2);
+    let mut rng = StdRng::seed_from_u64(2);
+    for (roughness, wo) in [
+        (0.3, Vec3::unit_vector(&Vec3::new(0.4, -0.2, 0.9))),
+        (0.6, Vec3::unit_vector(&Vec3::new(-0.3, 0.5, 0.6))),
+        // From inside the glass
+        (0.4, Vec3::unit_vector(&Vec3::new(0.2, 0.3, -0.8))),
+    ] {
+        let glass = Arc::new(Dielectric::new(1.5).with_roughness(roughness));
+        let record = hit_record(glass.clone());
+        assert!(!glass.is_specular());
+
+        // Sample weights agree with the BSDF and density
+        let n = 40_000;
+        let (mut transmitted, mut accepted) = (0., 0.);
+        for _ in 0..n {
+            let Some((ray, weight)) = glass.scatter(&Ray::from(wo, -wo), &record, &mut sampler)
+            else {
+                continue;
+            };
+            let wi = Vec3::unit_vector(&ray.direction);
+            let pdf = glass.pdf(&wo, &wi, &record);
+            let expected = glass.eval(&wo, &wi, &record) * wi.z().abs() / pdf;
+            assert!((weight - expected).length() < 1e-9 * expected.length());
+            accepted += 1.;
+            if wi.z() * wo.z() < 0. {
+                transmitted += weight.g();
+            }
+        }
+        let (transmitted, accepted) = (transmitted / n as f64, accepted / n as f64);
+
+        // and with an estimate that draws half its directions evenly over
+        // the sphere, which also shows the density covers the directions that
+        // are sampled
+        let (mut uniform, mut density) = (0., 0.);
+        for _ in 0..n {
+            let wi = if rng.gen::<bool>() {
+                sampling::uniform_sphere((rng.gen(), rng.gen()))
+            } else {
+                match glass.scatter(&Ray::from(wo, -wo), &record, &mut sampler) {
+                    Some((ray, _)) => Vec3::unit_vector(&ray.direction),
+                    None => continue,
+                }
+            };
+            let pdf = glass.pdf(&wo, &wi, &record);
+            let mixture = 0.5 * sampling::uniform_sphere_pdf() + 0.5 * pdf;
+            if wi.z() * wo.z() < 0. {
+                uniform += glass.eval(&wo, &wi, &record).g() * wi.z().abs() / mixture;
+            }
+            density += pdf / mixture;
+        }
+        let (uniform, density) = (uniform / n as f64, density / n as f64);
+        assert!(
+            (transmitted - uniform).abs() < 0.02 * uniform,
+            "{transmitted} vs {uniform}"
+        );
+        assert!((density - accepted).abs() < 0.02, "{density} vs {accepted}");
+    }
+}
+
+#[test]
+fn absorbing_glass_tints_by_path_length() {
+    let absorption = Vec3::new(0.1, 0.5, 1);
+    let glass = Arc::new(Dielectric::new(1.5).with_absorption(absorption));
+    let sphere = Sphere::new(Vec3::default(), 1., glass);
+    let mut sampler = IndependentSampler::new(3);
+
+    // Weight of a ray along +z at height `offset` that refracts into the
+    // sphere and straight out again
+    let mut through = |offset: f64| loop {
+        let mut ray = Ray::from(Vec3::new(0, offset, -5), Vec3::new(0, 0, 1));
+        let mut weight = Vec3::new(1, 1, 1);
+        let mut bounces = 0;
+        while let Some(hit) = sphere.hit((1e-9, f64::INFINITY), &ray) {
+            let (scattered, attenuation) = hit.mat.scatter(&ray, &hit, &mut sampler).unwrap();
+            weight *= attenuation;
+            ray = scattered;
+            bounces += 1;
+        }
+        if bounces == 2 && ray.direction.z() > 0. {
+            return weight;
+        }
+    };
+
+    // Entering absorbs nothing, leaving absorbs along the chord
+    let center = through(0.);
+    for i in 0..3 {
+        assert!((center[i] - f64::exp(-2. * absorption[i])).abs() < 1e-9);
+    }
+    let sin = 0.6;
+    let chord = 2. * f64::cos(f64::asin(sin / 1.5));
+    let edge = through(sin);
+    assert!((edge.b() - f64::exp(-chord * absorption.b())).abs() < 1e-9);
+    assert!(edge.b() > center.b() && edge.r() > edge.g() && edge.g() > edge.b());
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use std::sync::Arc;
+
+use raytrace::{
+    bdpt::BidirectionalPathTracer,
+    camera::PerspectiveCamera,
+    hittable::{Hittable, Sphere},
+    integrator::PathTracer,
+    material::{Dielectric, Dispersion},
+    sampler::{IndependentSampler, Sampler},
+    spectrum::{self, LAMBDA_MAX, LAMBDA_MIN},
+    types::{Ray, Vec3},
+};
+
+use common::{assert_luminance_close, render_film, Region, HEIGHT, WIDTH};
+
+#[test]
+fn wavelengths_average_to_white() {
+    // The density integrates to one over the visible spectrum
+    let steps = 1000;
+    let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+    let integral: f64 = (0..steps)
+        .map(|i| spectrum::wavelength_pdf(LAMBDA_MIN + (i as f64 + 0.5) * step) * step)
+        .sum();
+    assert!((integral - 1.).abs() < 1e-3, "{integral}");
+
+    let mut sampler = IndependentSampler::new(0);
+    let n = 100_000;
+    let mut sum = Vec3::default();
+    for _ in 0..n {
+        let (lambda, pdf) = spectrum::sample_wavelength(sampler.get_1d());
+        assert!((LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda));
+        assert!((pdf - spectrum::wavelength_pdf(lambda)).abs() < 1e-12);
+        sum += spectrum::wavelength_rgb(lambda, pdf);
+    }
+    let mean = sum / n as f64;
+    for i in 0..3 {
+        assert!((mean[i] - 1.).abs() < 0.02, "channel {i}: {}", mean[i]);
+    }
+
+    // Single wavelengths have the colors of the rainbow
+    let color = |lambda: f64| spectrum::wavelength_rgb(lambda, 1.);
+    let (blue, green, red) = (color(450.), color(530.), color(640.));
+    assert!(blue.b() > blue.g() && blue.b() > blue.r());
+    assert!(green.g() > green.r() && green.g() > green.b());
+    assert!(red.r() > red.g() && red.r() > red.b());
+    let peak = spectrum::cie_xyz(555.).y();
+    assert!((peak - 1.).abs() < 0.02, "{peak}");
+}
+
+#[test]
+fn glasses_match_their_catalog_indices() {
+    // Indices at the F, d and C lines
+    let bk7 = Dispersion::bk7();
+    for (lambda, expected) in [(486.1, 1.5224), (587.6, 1.5168), (656.3, 1.5143)] {
+        assert!((bk7.ior(lambda) - expected).abs() < 2e-4);
+    }
+    let abbe = |glass: Dispersion| (glass.ior(587.6) - 1.) / (glass.ior(486.1) - glass.ior(656.3));
+    assert!((abbe(bk7) - 64.2).abs() < 0.5);
+    assert!((abbe(Dispersion::sf11()) - 25.7).abs() < 0.5);
+    assert!((Dispersion::diamond().ior(589.3) - 2.417).abs() < 2e-3);
+
+    let cauchy = Dispersion::Cauchy { a: 1.5, b: 0.01 };
+    assert!((cauchy.ior(500.) - 1.54).abs() < 1e-12);
+}
+
+#[test]
+fn dispersive_glass_splits_white_light() {
+    let glass = Dielectric::new(1.5).with_dispersion(Dispersion::sf11());
+    let sphere = Sphere::new(Vec3::default(), 1., Arc::new(glass));
+    let mut sampler = IndependentSampler::new(1);
+
+    // Light entering off center picks a wavelength, keeps it inside and
+    // leaves bent further the bluer it is
+    let mut exits = Vec::new();
+    while exits.len() < 2000 {
+        let mut ray = Ray::from(Vec3::new(0, 0.5, -5), Vec3::new(0, 0, 1));
+        let mut weight = Vec3::new(1, 1, 1);
+        let mut bounces = 0;
+        while let Some(hit) = sphere.hit((1e-9, f64::INFINITY), &ray) {
+            let (scattered, attenuation) = hit.mat.scatter(&ray, &hit, &mut sampler).unwrap();
+            if bounces > 0 {
+                assert_eq!(scattered.wavelength, ray.wavelength);
+            }
+            weight *= attenuation;
+            ray = scattered;
+            bounces += 1;
+        }
+        if bounces == 2 {
+            let direction = Vec3::unit_vector(&ray.direction);
+            exits.push((ray.wavelength.unwrap().hero(), direction.y(), weight));
+        }
+    }
+    exits.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let (blue, red) = (exits[100], exits[exits.len() - 100]);
+    assert!(blue.0 < 480. && red.0 > 620.);
+    assert!(blue.1 < red.1 - 0.01, "{} vs {}", blue.1, red.1);
+
+    // The colors of the wavelengths that make it through average to white
+    let mean = exits.iter().map(|exit| exit.2).sum::<Vec3>() / exits.len() as f64;
+    assert!((mean.r() - mean.g()).abs() < 0.1 && (mean.b() - mean.g()).abs() < 0.1);
+}
+
+#[test]
+fn path_tracer_samples_lights_through_rough_dispersive_glass() {
+    // Close up on the reflection of the light in the top of the sphere, which
+    // the path tracer mostly finds by sampling the light
+    let glass = Dielectric::new(1.5)
+        .with_roughness(0.6)
+        .with_dispersion(Dispersion::sf11());
+    let scene = common::scene(Some(Arc::new(glass)));
+    let camera = PerspectiveCamera::new(
+        Vec3::new(0, 2, 8),
+        Vec3::new(0, 1.6, 0.8),
+        Vec3::new(0, 1, 0),
+        10.,
+        WIDTH as f64 / HEIGHT as f64,
+        0.,
+        8.,
+    );
+    let regions = [
+        (
+            Region {
+                name: "reflection",
+                x: 6..18,
+                y: 4..12,
+            },
+            0.1,
+        ),
+        (
+            Region {
+                name: "top half",
+                x: 0..WIDTH,
+                y: HEIGHT / 2..HEIGHT,
+            },
+            0.05,
+        ),
+    ];
+
+    let expected = render_film(&scene, &camera, &BidirectionalPathTracer::new(5), 256);
+    for spectral in [false, true] {
+        let integrator = PathTracer::new(5).with_spectral(spectral);
+        let actual = render_film(&scene, &camera, &integrator, 1024);
+        assert_luminance_close(&expected, &actual, &regions);
+    }
+}
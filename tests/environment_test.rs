@@ -0,0 +1,153 @@
+use std::io::ErrorKind;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use raytrace::{
+    camera::{Camera, EquirectangularCamera},
+    hdr::HdrImage,
+    light::{EnvironmentLight, Light},
+    sampler::IndependentSampler,
+    sampling,
+    types::{Ray, Vec3},
+};
+
+// Dim gradient with a small bright patch, like the sun in a photographed sky
+fn environment() -> HdrImage {
+    let (width, height) = (32, 16);
+    let mut image = HdrImage::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let value = 0.1 + 0.05 * x as f64 / width as f64 + 0.02 * y as f64;
+            image.set_pixel(x, y, Vec3::new(value, value, 1.5 * value));
+        }
+    }
+    image.set_pixel(20, 11, Vec3::new(5000, 4000, 3000));
+    image
+}
+
+fn assert_close(a: Vec3, b: Vec3, tolerance: f64) {
+    assert!(
+        (a - b).length() <= tolerance * b.length(),
+        "({}, {}, {}) differs from ({}, {}, {})",
+        a.x(),
+        a.y(),
+        a.z(),
+        b.x(),
+        b.y(),
+        b.z()
+    );
+}
+
+#[test]
+fn hdr_images_survive_writing_and_reading() {
+    // Narrow images are written flat, wider ones run length encoded
+    for width in [3, 40] {
+        let mut image = HdrImage::new(width, 5);
+        for y in 0..5 {
+            for x in 0..width {
+                image.set_pixel(x, y, Vec3::new(x as f64 * 0.37, y as f64 * 20., 0.001));
+            }
+        }
+        let mut data = Vec::new();
+        image.write(&mut data).unwrap();
+        let read = HdrImage::read(&mut data.as_slice()).unwrap();
+        assert_eq!((read.get_width(), read.get_height()), (width, 5));
+        for y in 0..5 {
+            for x in 0..width {
+                assert_close(read.get_pixel(x, y), image.get_pixel(x, y), 0.01);
+            }
+        }
+    }
+
+    // One scanline of 8 pixels with runs of repeated values
+    let mut data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n".to_vec();
+    data.extend([2, 2, 0, 8]);
+    data.extend([136, 128]);
+    data.extend([4, 0, 32, 64, 96, 132, 128]);
+    data.extend([136, 0]);
+    data.extend([136, 129]);
+    let image = HdrImage::read(&mut data.as_slice()).unwrap();
+    assert_close(image.get_pixel(0, 0), Vec3::new(1, 0, 0), 1e-12);
+    assert_close(image.get_pixel(7, 0), Vec3::new(1, 1, 0), 1e-12);
+
+    assert!(HdrImage::read(&mut b"P3\n1 1\n255\n0 0 0\n".as_slice()).is_err());
+    // Sizes the data can not hold are rejected before allocating for them
+    for resolution in [
+        "-Y 4294967295 +X 4294967295",
+        "-Y 18446744073709551615 +X 2",
+        "-Y 1000 +X 1000",
+    ] {
+        let mut data = format!("#?RADIANCE\n\n{resolution}\n").into_bytes();
+        data.extend([2, 2, 3, 232]);
+        let result = HdrImage::read(&mut data.as_slice());
+        assert!(result.is_err_and(|err| err.kind() == ErrorKind::InvalidData));
+    }
+}
+
+#[test]
+fn sampling_follows_the_brightness_of_the_image() {
+    let light = EnvironmentLight::new(environment()).with_rotation(30.);
+    let p = Vec3::new(1, 2, 3);
+    let mut rng = StdRng::seed_from_u64(1);
+
+    // Samples agree with the densities and radiance looked up afterwards, and
+    // most of them head for the bright patch
+    let mut bright = 0;
+    for _ in 0..1000 {
+        let sample = light
+            .sample_li(&p, (rng.gen(), rng.gen()), (Vec3::default(), 10.))
+            .unwrap();
+        let pdf = light.pdf_li(&p, &sample.wi);
+        assert!((sample.pdf - pdf).abs() <= 1e-6 * pdf);
+        assert_close(light.le(&Ray::from(p, sample.wi)), sample.radiance, 1e-9);
+        bright += (sample.radiance.x() > 100.) as usize;
+    }
+    assert!(bright > 900);
+
+    // The density integrates to one over the sphere
+    let n = 200_000;
+    let integral: f64 = (0..n)
+        .map(|_| {
+            let wi = sampling::uniform_sphere((rng.gen(), rng.gen()));
+            light.pdf_li(&p, &wi) / sampling::uniform_sphere_pdf()
+        })
+        .sum::<f64>()
+        / n as f64;
+    assert!(
+        (integral - 1.).abs() < 0.05,
+        "density integrates to {integral}"
+    );
+}
+
+#[test]
+fn rotation_turns_the_environment_around_the_y_axis() {
+    let light = EnvironmentLight::new(environment()).with_intensity(2.);
+    let rotated = EnvironmentLight::new(environment())
+        .with_rotation(90.)
+        .with_intensity(2.);
+    // Turning counterclockwise seen from above takes -z to -x
+    for (dir, turned) in [
+        (Vec3::new(0, 0, -1), Vec3::new(-1, 0, 0)),
+        (Vec3::new(1, 0.5, 0), Vec3::new(0, 0.5, -1)),
+    ] {
+        let unturned = light.le(&Ray::from(Vec3::default(), dir));
+        assert_close(
+            rotated.le(&Ray::from(Vec3::default(), turned)),
+            unturned,
+            1e-12,
+        );
+    }
+}
+
+#[test]
+fn equirectangular_camera_sees_the_image() {
+    let image = environment();
+    let light = EnvironmentLight::new(image.clone());
+    let camera =
+        EquirectangularCamera::new(Vec3::default(), Vec3::new(0, 0, -1), Vec3::new(0, 1, 0));
+    let mut sampler = IndependentSampler::new(0);
+    for (x, y) in [(0, 0), (20, 11), (16, 8), (31, 15), (5, 3)] {
+        let (s, t) = ((x as f64 + 0.5) / 32., (y as f64 + 0.5) / 16.);
+        let ray = camera.generate_ray(s, t, &mut sampler).unwrap();
+        assert_close(light.le(&ray), image.get_pixel(x, y), 1e-12);
+    }
+}
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use raytrace::{
+    camera::PerspectiveCamera,
+    film::Film,
+    filter::Filter,
+    hittable::HittableList,
+    integrator::PathTracer,
+    light::{GradientSky, Light},
+    render::render,
+    sampler::IndependentSampler,
+    scene::Scene,
+    types::Vec3,
+};
+
+const WIDTH: usize = 12;
+const HEIGHT: usize = 8;
+
+fn filters() -> Vec<Filter> {
+    vec![
+        Filter::Box { radius: 0.5 },
+        Filter::Box { radius: 1. },
+        Filter::Tent { radius: 1. },
+        Filter::Gaussian {
+            radius: 1.5,
+            sigma: 0.5,
+        },
+        Filter::Mitchell {
+            radius: 2.,
+            b: 1. / 3.,
+            c: 1. / 3.,
+        },
+        Filter::Lanczos {
+            radius: 3.,
+            tau: 3.,
+        },
+    ]
+}
+
+#[test]
+fn filters_vanish_outside_radius() {
+    for filter in filters() {
+        let r = filter.radius();
+        assert!(filter.eval(0., 0.) > 0., "{filter:?}");
+        assert_eq!(filter.eval(r + 0.01, 0.), 0., "{filter:?}");
+        assert_eq!(filter.eval(0., -r - 0.01), 0., "{filter:?}");
+        assert_eq!(filter.eval(0.3, 0.2), filter.eval(-0.3, -0.2), "{filter:?}");
+    }
+
+    let mitchell = Filter::Mitchell {
+        radius: 2.,
+        b: 1. / 3.,
+        c: 1. / 3.,
+    };
+    assert!(mitchell.eval(1.5, 0.) < 0.);
+    assert_eq!(
+        mitchell.with_radius(4.).eval(3., 0.),
+        mitchell.eval(1.5, 0.)
+    );
+}
+
+#[test]
+fn filtered_film_keeps_constant_image_constant() {
+    let sky = Vec3::new(0.2, 0.4, 0.6);
+    let lights: Vec<Arc<dyn Light>> = vec![Arc::new(GradientSky::new(sky, sky))];
+    let scene = Scene::new(HittableList::new(), lights);
+    let camera = PerspectiveCamera::new(
+        Vec3::new(0, 0, 0),
+        Vec3::new(0, 0, -1),
+        Vec3::new(0, 1, 0),
+        60.,
+        WIDTH as f64 / HEIGHT as f64,
+        0.,
+        1.,
+    );
+
+    for filter in filters() {
+        let mut film = Film::with_filter(WIDTH, HEIGHT, filter);
+        render(
+            &scene,
+            &camera,
+            &PathTracer::new(1),
+            &IndependentSampler::new(0),
+            &mut film,
+            4,
+        );
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let p = film.pixel(x, y);
+                for i in 0..3 {
+                    assert!((p[i] - sky[i]).abs() < 1e-9, "{filter:?} at ({x}, {y})");
+                }
+            }
+        }
+    }
+}
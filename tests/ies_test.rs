@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use raytrace::{
+    ies::IesProfile,
+    light::{Light, PointLight, SpotLight},
+    types::Vec3,
+};
+
+// A luminaire measured over one quadrant, brightest straight down and
+// brighter along its length than across it
+const QUADRANT: &str = "IESNA:LM-63-2002
+[TEST] quadrant
+[MANUFAC] none
+TILT=NONE
+1 1000 2 3 2 1 2 0.5 0.5 0.1
+1 1 40
+0 45 90
+0 90
+100 80 20
+60 40 0
+";
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() < 1e-9,
+        "expected {expected} got {actual}"
+    );
+}
+
+#[test]
+fn profiles_interpolate_between_measured_angles() {
+    let profile = IesProfile::parse(QUADRANT).unwrap();
+    // Candela values are scaled by the multiplier
+    assert_close(profile.max_candela(), 200.);
+    assert_close(profile.candela(0., 0.), 200.);
+    assert_close(profile.candela(45., 90.), 80.);
+    assert_close(profile.candela(22.5, 0.), 180.);
+    assert_close(profile.candela(45., 45.), 120.);
+    assert_close(profile.candela(22.5, 45.), 140.);
+    assert_close(profile.relative_intensity(90., 0.), 0.2);
+    // The other quadrants mirror the measured one
+    for h in [180., 360., -180.] {
+        assert_close(profile.candela(45., h), 160.);
+    }
+    for h in [30., 150., 210., 330., -30.] {
+        assert_close(profile.candela(30., h), profile.candela(30., 30.));
+    }
+    // Nothing is measured above the horizon
+    assert_eq!(profile.candela(120., 0.), 0.);
+
+    // A full circle closes back onto its first angle
+    let full = IesProfile::parse(
+        "TILT=NONE\n1 -1 1 2 4 1 2 0 0 0\n1 1 10\n0 90\n0 90 180 270\n4 0 8 0 4 0 2 0\n",
+    )
+    .unwrap();
+    assert_close(full.candela(0., 135.), 6.);
+    assert_close(full.candela(0., 315.), 3.);
+}
+
+#[test]
+fn malformed_profiles_are_rejected() {
+    for text in [
+        "1 1000 1 2 1 1 2 0 0 0\n1 1 10\n0 90\n0\n1 1\n",
+        "TILT=NONE\n1 1000 1 2 1 1 2 0 0 0\n1 1 10\n0 90\n0\n1\n",
+        "TILT=NONE\n1 1000 1 2 1 3 2 0 0 0\n1 1 10\n0 90\n0\n1 1\n",
+        "TILT=NONE\n1 1000 1 2 1 1 2 0 0 0\n1 1 10\n90 0\n0\n1 1\n",
+        "TILT=NONE\n1 1000 1 2 1 1 2 0 0 0\n1 1 10\n0 ninety\n0\n1 1\n",
+    ] {
+        assert!(IesProfile::parse(text).is_err(), "{text}");
+    }
+    let text = "TILT=INCLUDE\n1\n2\n0 90\n1 0.5\n1 1000 1 2 1 1 2 0 0 0\n1 1 10\n0 90\n0\n3 1\n";
+    assert_close(IesProfile::parse(text).unwrap().max_candela(), 3.);
+}
+
+#[test]
+fn profiles_shape_point_and_spot_lights() {
+    let profile = Arc::new(IesProfile::parse(QUADRANT).unwrap());
+    let world = (Vec3::default(), 10.);
+    let intensity = Vec3::new(10, 10, 10);
+
+    // Hanging down, with the luminaire's length along x
+    let point = PointLight::new(Vec3::default(), intensity).with_profile(profile.clone());
+    let at = |p: Vec3| point.sample_li(&p, (0.5, 0.5), world).unwrap().radiance.x();
+    assert_close(at(Vec3::new(0, -1, 0)), 10.);
+    assert_close(at(Vec3::new(1, -1, 0)), 0.5 * 10. * 0.8);
+    assert_close(at(Vec3::new(0, -1, 1)), 0.5 * 10. * 0.4);
+    assert_close(at(Vec3::new(-1, 0, 0)), 10. * 0.2);
+    assert_eq!(at(Vec3::new(0, 1, 0)), 0.);
+
+    // Aimed sideways, the profile turns with the spot's axis and is cut off
+    // by the cone
+    let spot =
+        SpotLight::new(Vec3::default(), Vec3::new(1, 0, 0), intensity, 60.).with_profile(profile);
+    let at = |p: Vec3| spot.sample_li(&p, (0.5, 0.5), world).unwrap().radiance.x();
+    assert_close(at(Vec3::new(2, 0, 0)), 10. / 4.);
+    let (sin, cos) = 45f64.to_radians().sin_cos();
+    let side = at(Vec3::new(cos, sin, 0));
+    let up = at(Vec3::new(cos, 0, sin));
+    assert!(side > 0. && up > 0. && side != up);
+    assert_close(side.max(up), 8.);
+    assert_close(side.min(up), 4.);
+    assert_eq!(at(Vec3::new(0, 1, 0)), 0.);
+}
@@ -0,0 +1,131 @@
+use std::path::Path;
+
+use raytrace::{
+    camera::{Camera, RealisticCamera},
+    lens::LensSystem,
+    sampler::{IndependentSampler, Sampler},
+    types::{Ray, Vec3},
+};
+
+fn double_gauss() -> LensSystem {
+    LensSystem::load(Path::new("lenses/dgauss-50mm.txt")).unwrap()
+}
+
+fn camera(focus_dist: f64) -> RealisticCamera {
+    RealisticCamera::new(
+        Vec3::new(0, 0, 0),
+        Vec3::new(0, 0, -1),
+        Vec3::new(0, 1, 0),
+        double_gauss(),
+        43.27,
+        1.5,
+        focus_dist,
+    )
+    .unwrap()
+}
+
+const SAMPLES: usize = 2000;
+
+// Rays through film position (s, t) that make it through the lens, with
+// their weights
+fn weighted_rays(camera: &RealisticCamera, s: f64, t: f64) -> Vec<(Ray, f64)> {
+    let mut sampler = IndependentSampler::new(0);
+    (0..SAMPLES)
+        .filter_map(|index| {
+            sampler.start_pixel_sample((0, 0), index);
+            camera.get_ray(s, t, &mut sampler)
+        })
+        .collect()
+}
+
+fn rays(camera: &RealisticCamera, s: f64, t: f64) -> Vec<Ray> {
+    weighted_rays(camera, s, t)
+        .into_iter()
+        .map(|(ray, _)| ray)
+        .collect()
+}
+
+// Light reaching film position (s, t) for unit radiance in front of the lens
+fn irradiance(camera: &RealisticCamera, s: f64, t: f64) -> f64 {
+    let rays = weighted_rays(camera, s, t);
+    rays.iter().map(|(_, weight)| weight).sum::<f64>() / SAMPLES as f64
+}
+
+#[test]
+fn parses_prescription() {
+    let lens = double_gauss();
+    assert_eq!(lens.elements().len(), 11);
+    assert_eq!(lens.elements()[5].curvature_radius, 0.);
+    assert_eq!(lens.elements()[5].ior, 1.);
+    assert_eq!(lens.elements()[0].aperture_radius, 12.6);
+    let focal_length = lens.focal_length().unwrap();
+    assert!((focal_length - 50.).abs() < 1.5, "{focal_length}");
+
+    let err = LensSystem::parse("# comment\n29.475 3.76 1.67\n").unwrap_err();
+    assert!(err.to_string().contains("line 2"), "{err}");
+}
+
+#[test]
+fn rays_from_film_centre_meet_at_focus_distance() {
+    for focus_dist in [1., 5.] {
+        let camera = camera(focus_dist);
+        let rays = rays(&camera, 0.5, 0.5);
+        assert!(rays.len() > 500);
+        // Distance from each ray to the point on the axis it should focus on
+        let target = Vec3::new(0., 0., -focus_dist);
+        for ray in rays {
+            let dir = Vec3::unit_vector(&ray.direction);
+            let to_target = target - ray.origin;
+            let miss = to_target - Vec3::dot(&to_target, &dir) * dir;
+            assert!(
+                miss.length() < 0.01 * focus_dist,
+                "missed by {}",
+                miss.length()
+            );
+        }
+    }
+}
+
+#[test]
+fn lens_vignettes_and_breathes() {
+    let far = camera(10.);
+    let near = camera(0.5);
+    assert!(near.film_distance() > far.film_distance());
+
+    // Less light gets through to the corners of the film
+    let centre = irradiance(&far, 0.5, 0.5);
+    let corner = irradiance(&far, 0.999, 0.999);
+    assert!(corner < 0.75 * centre, "{corner} of {centre}");
+
+    // Moving the lens out to focus closer narrows the field of view
+    let angle = |camera: &RealisticCamera| {
+        let rays = rays(camera, 0.999, 0.5);
+        let mean = rays
+            .iter()
+            .map(|ray| Vec3::unit_vector(&ray.direction))
+            .fold(Vec3::default(), |sum, dir| sum + dir);
+        f64::atan2(mean.x(), -mean.z()).to_degrees()
+    };
+    let (far_angle, near_angle) = (angle(&far), angle(&near));
+    assert!(far_angle > near_angle + 0.5, "{far_angle} {near_angle}");
+    // The right of the film sees the right of the scene
+    assert!(near_angle > 0.);
+}
+
+#[test]
+fn rays_aim_at_the_exit_pupil() {
+    let camera = camera(5.);
+    // Most rays sampled within the bounds of the exit pupil get through, even
+    // in the corners
+    for (s, t) in [(0.5, 0.5), (0.8, 0.3), (0.999, 0.999)] {
+        let through = rays(&camera, s, t).len();
+        assert!(through > SAMPLES / 3, "{through} at {s} {t}");
+    }
+
+    // Weights are the irradiance unit radiance brings, π / (4 N²) at the
+    // centre of the film behind an f/2 lens, which the exposure undoes
+    let centre = irradiance(&camera, 0.5, 0.5);
+    let expected = std::f64::consts::PI / 16.;
+    assert!((centre - expected).abs() < 0.1 * expected, "{centre}");
+    assert!((centre * camera.exposure() - 1.).abs() < 0.05, "{centre}");
+}
@@ -0,0 +1,142 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use raytrace::{
+    hittable::HitRecord,
+    material::{Material, Metal},
+    microfacet::TrowbridgeReitz,
+    sampler::IndependentSampler,
+    sampling,
+    types::{Ray, Vec3},
+};
+
+fn hit_record(material: Arc<dyn Material>) -> HitRecord {
+    HitRecord {
+        t: 1.,
+        p: Vec3::default(),
+        normal: Vec3::new(0, 0, 1),
+        mat: material,
+        light: None,
+        time: 0.,
+        wavelength: None,
+    }
+}
+
+fn incoming(wo: Vec3) -> Ray {
+    Ray::from(wo, -wo)
+}
+
+#[test]
+fn ggx_distributions_are_normalized() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let n = 400_000;
+    for alpha in [0.1, 0.5, 1.] {
+        let distribution = TrowbridgeReitz::new(alpha);
+        let wo = Vec3::unit_vector(&Vec3::new(0.6, 0.2, 0.5));
+        // Projected microfacet area and visible normals both integrate to one
+        let (mut projected, mut visible, mut in_cone) = (0., 0., 0.);
+        for _ in 0..n {
+            let h = sampling::cosine_hemisphere((rng.gen(), rng.gen()));
+            projected += distribution.d(&h) * h.z() / (h.z() / PI);
+            let weight = distribution.d_visible(&wo, &h) / (h.z() / PI);
+            visible += weight;
+            in_cone += if h.z() > 0.8 { weight } else { 0. };
+        }
+        let (projected, visible, in_cone) =
+            (projected / n as f64, visible / n as f64, in_cone / n as f64);
+        assert!((projected - 1.).abs() < 0.03, "alpha {alpha}: {projected}");
+        assert!((visible - 1.).abs() < 0.03, "alpha {alpha}: {visible}");
+
+        // Sampled normals follow the density of visible normals
+        let sampled = (0..n)
+            .filter(|_| distribution.sample_visible(&wo, (rng.gen(), rng.gen())).z() > 0.8)
+            .count() as f64
+            / n as f64;
+        assert!((sampled - in_cone).abs() < 0.01, "{sampled} vs {in_cone}");
+    }
+}
+
+#[test]
+fn conductors_follow_the_fresnel_equations() {
+    // Gold is yellow, aluminum bright and nearly neutral
+    let gold = Metal::gold(0.).fresnel(1.);
+    assert!(gold.r() > 0.9 && gold.g() > 0.6 && gold.b() < 0.4);
+    let aluminum = Metal::aluminum(0.).fresnel(1.);
+    assert!(aluminum.b() > 0.9 && aluminum.r() > 0.85);
+    let copper = Metal::copper(0.).fresnel(1.);
+    assert!(copper.r() > copper.g() && copper.g() > copper.b());
+
+    // All metals turn white at grazing angles
+    let albedo = Vec3::new(0.9, 0.5, 0.1);
+    let metal = Metal::new(albedo, 0.);
+    let normal = metal.fresnel(1.);
+    assert!((normal - albedo).length() < 1e-9);
+    let grazing = metal.fresnel(0.02);
+    assert!(grazing.b() > 0.8 && grazing.b() < 1.);
+
+    // A smooth metal is a mirror weighted by the reflectance
+    let record = hit_record(Arc::new(Metal::copper(0.)));
+    let wo = Vec3::unit_vector(&Vec3::new(1, 0, 1));
+    let mut sampler = IndependentSampler::new(0);
+    let (ray, weight) = record
+        .mat
+        .scatter(&incoming(wo), &record, &mut sampler)
+        .unwrap();
+    let expected = Metal::copper(0.).fresnel(wo.z());
+    assert!((Vec3::unit_vector(&ray.direction) - Vec3::new(-wo.x(), 0, wo.z())).length() < 1e-12);
+    assert!((weight - expected).length() < 1e-12);
+    assert!(record.mat.is_specular());
+}
+
+#[test]
+fn rough_metals_sample_their_brdf() {
+    let mut sampler = IndependentSampler::new(2);
+    let mut rng = StdRng::seed_from_u64(2);
+    let mut albedos = Vec::new();
+    for roughness in [0.3, 0.7] {
+        let metal = Arc::new(Metal::new(Vec3::new(0.95, 0.95, 0.95), roughness));
+        let record = hit_record(metal.clone());
+        assert!(!metal.is_specular());
+        let wo = Vec3::unit_vector(&Vec3::new(-0.5, 0.3, 0.8));
+
+        // Sample weights agree with the BRDF and density
+        let n = 20_000;
+        let (mut albedo, mut accepted) = (0., 0.);
+        for _ in 0..n {
+            let Some((ray, weight)) = metal.scatter(&incoming(wo), &record, &mut sampler) else {
+                continue;
+            };
+            let wi = Vec3::unit_vector(&ray.direction);
+            let pdf = metal.pdf(&wo, &wi, &record);
+            let expected = metal.eval(&wo, &wi, &record) * wi.z() / pdf;
+            assert!((weight - expected).length() < 1e-9 * expected.length());
+            albedo += weight.g();
+            accepted += 1.;
+        }
+        let (albedo, accepted) = (albedo / n as f64, accepted / n as f64);
+
+        // and so does an estimate that samples the hemisphere evenly, which
+        // also shows the density covers the reflections that stay above the
+        // surface
+        let (mut uniform, mut density) = (0., 0.);
+        for _ in 0..n {
+            let wi = sampling::cosine_hemisphere((rng.gen(), rng.gen()));
+            uniform += metal.eval(&wo, &wi, &record).g() * PI;
+            density += metal.pdf(&wo, &wi, &record) * PI / wi.z();
+        }
+        let (uniform, density) = (uniform / n as f64, density / n as f64);
+        assert!((albedo - uniform).abs() < 0.03, "{albedo} vs {uniform}");
+        assert!((density - accepted).abs() < 0.03, "{density} vs {accepted}");
+        albedos.push(albedo);
+
+        // Surfaces seen from behind reflect to that side
+        let below = Vec3::new(wo.x(), wo.y(), -wo.z());
+        let (ray, _) = metal
+            .scatter(&incoming(below), &record, &mut sampler)
+            .unwrap();
+        assert!(ray.direction.z() < 0.);
+    }
+    // Single scattering loses energy, more of it on rougher surfaces
+    assert!(0.95 > albedos[0] && albedos[0] > albedos[1] + 0.1);
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use std::sync::Arc;
+
+use raytrace::{
+    film::Film,
+    integrator::PathTracer,
+    material::Lambertian,
+    mlt::{MetropolisLightTransport, MltSampler},
+    sampler::Sampler,
+    scene::Scene,
+    types::Vec3,
+};
+
+use common::{
+    assert_regions_close, camera, render_film, GROUND, HEIGHT, SPHERE, UNDER_SPHERE, WIDTH,
+};
+
+fn scene() -> Scene {
+    common::scene(Some(Arc::new(Lambertian::new(Vec3::new(0.7, 0.3, 0.3)))))
+}
+
+#[test]
+fn small_steps_stay_close_to_current_path() {
+    let mut sampler = MltSampler::new(7, 0.01, 0.);
+    sampler.start_path();
+    let current: Vec<f64> = (0..8).map(|_| sampler.get_1d()).collect();
+
+    // Every proposal is a single small step away from the current path
+    for _ in 0..200 {
+        sampler.start_iteration();
+        sampler.start_path();
+        for value in current.iter() {
+            let d = f64::abs(sampler.get_1d() - value);
+            assert!(f64::min(d, 1. - d) < 0.06);
+        }
+        sampler.reject();
+    }
+}
+
+#[test]
+fn metropolis_matches_path_tracer() {
+    let scene = scene();
+    let expected = render_film(&scene, &camera(), &PathTracer::new(5), 1024);
+
+    let mut actual = Film::new(WIDTH, HEIGHT);
+    MetropolisLightTransport::new(5, 100_000, 256, 4096, 0).render(&scene, &camera(), &mut actual);
+
+    // Correlated samples move light between regions even when the image
+    // mean is right
+    assert_regions_close(
+        &expected,
+        &actual,
+        &[(UNDER_SPHERE, 0.08), (SPHERE, 0.06), (GROUND, 0.07)],
+    );
+}
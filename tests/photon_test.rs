@@ -0,0 +1,73 @@
+mod common;
+
+use std::sync::Arc;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use raytrace::{
+    film::Film,
+    integrator::PathTracer,
+    material::Dielectric,
+    photon::{KdTree, PhotonMapper, ProgressivePhotonMapper},
+    scene::Scene,
+    types::Vec3,
+};
+
+use common::{
+    assert_regions_close, camera, render_film, Region, GROUND, HEIGHT, SPHERE, UNDER_SPHERE, WIDTH,
+};
+
+// Photons from the sky spread over the whole ground, so the few that land
+// near the sphere make every region noisy. A missing caustic would still
+// halve the light under the sphere.
+const REGIONS: [(Region, f64); 3] = [(UNDER_SPHERE, 0.25), (SPHERE, 0.1), (GROUND, 0.1)];
+
+fn glass_scene() -> Scene {
+    common::scene(Some(Arc::new(Dielectric::new(1.5))))
+}
+
+#[test]
+fn kd_tree_finds_points_within_radius() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let points: Vec<(Vec3, usize)> = (0..2000)
+        .map(|i| {
+            (
+                Vec3::new(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>()),
+                i,
+            )
+        })
+        .collect();
+    let tree = KdTree::new(points.clone());
+
+    for _ in 0..50 {
+        let query = Vec3::new(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>());
+        let radius = 0.2 * rng.gen::<f64>();
+
+        let mut found = Vec::new();
+        tree.for_each_within(&query, radius, |_, &i| found.push(i));
+        found.sort();
+        let expected: Vec<usize> = points
+            .iter()
+            .filter(|(p, _)| (*p - query).length() <= radius)
+            .map(|&(_, i)| i)
+            .collect();
+        assert_eq!(found, expected);
+    }
+}
+
+#[test]
+fn photon_mapper_matches_path_tracer() {
+    let scene = glass_scene();
+    let expected = render_film(&scene, &camera(), &PathTracer::new(5), 1024);
+    let photon_mapper = PhotonMapper::new(&scene, 1_000_000, 0.1, 5, 0);
+    let actual = render_film(&scene, &camera(), &photon_mapper, 64);
+    assert_regions_close(&expected, &actual, &REGIONS);
+}
+
+#[test]
+fn progressive_photon_mapper_matches_path_tracer() {
+    let scene = glass_scene();
+    let expected = render_film(&scene, &camera(), &PathTracer::new(5), 1024);
+    let mut film = Film::new(WIDTH, HEIGHT);
+    ProgressivePhotonMapper::new(50_000, 0.25, 5, 0).render(&scene, &camera(), &mut film, 64);
+    assert_regions_close(&expected, &film, &REGIONS);
+}
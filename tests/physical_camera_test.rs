@@ -0,0 +1,107 @@
+use std::sync::Arc;
+
+use raytrace::{
+    camera::{Camera, PerspectiveCamera, PhysicalCamera},
+    film::Film,
+    hittable::{HittableList, Sphere},
+    integrator::PathTracer,
+    light::{GradientSky, Light},
+    material::Lambertian,
+    render::render,
+    sampler::IndependentSampler,
+    scene::Scene,
+    sky::GRADIENT_SKY_EV100,
+    types::Vec3,
+};
+
+fn camera() -> PhysicalCamera {
+    PhysicalCamera::new(
+        Vec3::new(0, 0, 10),
+        Vec3::new(0, 0, 0),
+        Vec3::new(0, 1, 0),
+        1.,
+        10.,
+    )
+}
+
+#[test]
+fn exposure_follows_aperture_shutter_and_iso() {
+    let base = camera().exposure();
+    let ratio = |camera: PhysicalCamera| camera.exposure() / base;
+    assert!((ratio(camera().with_shutter_speed(1. / 250.)) - 0.5).abs() < 1e-9);
+    assert!((ratio(camera().with_iso(200.)) - 2.).abs() < 1e-9);
+    // Full stops are rounded, f/5.6 is really f/4√2
+    assert!((ratio(camera().with_f_number(5.6)) - 2.).abs() < 0.05);
+    assert!((camera().with_f_number(1.).with_shutter_speed(1.).ev100()).abs() < 1e-9);
+}
+
+// Metered for the gradient sky, a physical camera sees it as cameras without
+// settings do
+#[test]
+fn metering_sets_the_shutter_for_the_exposure_value() {
+    let camera = camera()
+        .with_f_number(2.8)
+        .with_iso(400.)
+        .with_exposure_value(GRADIENT_SKY_EV100);
+    assert!((camera.ev100() - GRADIENT_SKY_EV100).abs() < 1e-9);
+    assert!((GradientSky::exposure_for(&camera) - 1.).abs() < 1e-9);
+
+    let pinhole = PerspectiveCamera::new(
+        Vec3::new(0, 0, 10),
+        Vec3::new(0, 0, 0),
+        Vec3::new(0, 1, 0),
+        40.,
+        1.,
+        0.,
+        10.,
+    );
+    assert_eq!(GradientSky::exposure_for(&pinhole), 1.);
+}
+
+// A black sphere crosses the middle of the image while the shutter is open,
+// blocking the white sky behind it for about a quarter of the time
+#[test]
+fn moving_sphere_is_blurred() {
+    let mut world = HittableList::new();
+    world.add(Box::new(
+        Sphere::new(
+            Vec3::new(-2, 0, 0),
+            0.5,
+            Arc::new(Lambertian::new(Vec3::new(0, 0, 0))),
+        )
+        .with_motion(Vec3::new(2, 0, 0), 0.1),
+    ));
+    let sky = Arc::new(GradientSky::new(Vec3::new(1, 1, 1), Vec3::new(1, 1, 1)));
+    let lights: Vec<Arc<dyn Light>> = vec![sky];
+    let scene = Scene::new(world, lights);
+    let camera = camera().with_f_number(1000.).with_shutter_speed(0.1);
+
+    let render_film = || {
+        let mut film = Film::new(41, 41);
+        render(
+            &scene,
+            &camera,
+            &PathTracer::new(2),
+            &IndependentSampler::new(0),
+            &mut film,
+            256,
+        );
+        film
+    };
+    let film = render_film();
+    let centre = film.pixel(20, 20).x();
+    assert!((centre - 0.75).abs() < 0.08, "centre pixel is {centre}");
+    assert_eq!(film.pixel(0, 0).x(), 1.);
+    assert_eq!(render_film().pixel(20, 20).x(), centre);
+}
+
+#[test]
+fn auto_exposure_maps_average_to_middle_grey() {
+    let mut film = Film::new(4, 4);
+    for y in 0..4 {
+        for x in 0..4 {
+            film.add_samples(x, y, Vec3::new(2, 2, 2), 1);
+        }
+    }
+    assert!((film.auto_exposure() * 2. - 0.18).abs() < 1e-4);
+}
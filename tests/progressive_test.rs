@@ -0,0 +1,60 @@
+mod common;
+
+use raytrace::{
+    film::Film,
+    filter::Filter,
+    integrator::PathTracer,
+    render::{render, render_progressive, ProgressiveRendering},
+    sampler::SobolSampler,
+};
+
+use common::{camera, HEIGHT, WIDTH};
+
+const SAMPLES: usize = 10;
+
+#[test]
+fn passes_add_up_to_full_render() {
+    let scene = common::scene(None);
+    let filter = Filter::Tent { radius: 1. };
+    let sampler = SobolSampler::new(SAMPLES, 0);
+    let integrator = PathTracer::new(5);
+
+    let mut expected = Film::with_filter(WIDTH, HEIGHT, filter);
+    render(
+        &scene,
+        &camera(),
+        &integrator,
+        &sampler,
+        &mut expected,
+        SAMPLES,
+    );
+
+    let mut film = Film::with_filter(WIDTH, HEIGHT, filter);
+    let mut passes = Vec::new();
+    render_progressive(
+        &scene,
+        &camera(),
+        &integrator,
+        &sampler,
+        &mut film,
+        &ProgressiveRendering::new(SAMPLES, 4),
+        |film, taken| passes.push((taken, film.pixel(WIDTH / 2, HEIGHT / 2))),
+    );
+
+    assert_eq!(
+        passes.iter().map(|pass| pass.0).collect::<Vec<_>>(),
+        vec![4, 8, 10]
+    );
+    // Every pass gives a complete image, not a partial sum
+    let centre = expected.pixel(WIDTH / 2, HEIGHT / 2);
+    assert!(passes[0].1.luminance() > 0.5 * centre.luminance());
+
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let (a, b) = (film.pixel(x, y), expected.pixel(x, y));
+            for i in 0..3 {
+                assert!((a[i] - b[i]).abs() < 1e-12, "pixel ({x}, {y})");
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use raytrace::sampler::{
+    BlueNoiseSampler, HaltonSampler, IndependentSampler, Sampler, SobolSampler, StratifiedSampler,
+};
+
+const SAMPLES: usize = 64;
+
+fn seeded_samplers() -> Vec<(&'static str, Box<dyn Sampler>)> {
+    vec![
+        ("stratified", Box::new(StratifiedSampler::new(SAMPLES, 1))),
+        ("halton", Box::new(HaltonSampler::new(1))),
+        ("sobol", Box::new(SobolSampler::new(SAMPLES, 1))),
+        ("bluenoise", Box::new(BlueNoiseSampler::new(SAMPLES, 1))),
+    ]
+}
+
+fn draw(sampler: &mut dyn Sampler, pixel: (usize, usize), index: usize) -> Vec<f64> {
+    sampler.start_pixel_sample(pixel, index);
+    let mut values = Vec::new();
+    for _ in 0..20 {
+        let (u, v) = sampler.get_2d();
+        values.extend([u, v, sampler.get_1d()]);
+    }
+    values
+}
+
+// Root mean square error, over a number of pixels, of estimating the integral
+// of u * v over the unit square from the second 2D sample of every pixel sample
+fn rms_error(sampler: &mut dyn Sampler) -> f64 {
+    let pixels = 100;
+    let mut squared_error = 0.;
+    for p in 0..pixels {
+        let mut sum = 0.;
+        for i in 0..SAMPLES {
+            sampler.start_pixel_sample((p % 10, p / 10), i);
+            sampler.get_2d();
+            let (u, v) = sampler.get_2d();
+            sum += u * v;
+        }
+        squared_error += (sum / SAMPLES as f64 - 0.25).powi(2);
+    }
+    f64::sqrt(squared_error / pixels as f64)
+}
+
+#[test]
+fn samples_are_in_unit_interval_and_reproducible() {
+    for (name, mut sampler) in seeded_samplers() {
+        let mut copy = sampler.clone_sampler();
+        for i in 0..SAMPLES {
+            let values = draw(sampler.as_mut(), (3, 5), i);
+            assert!(values.iter().all(|x| (0. ..1.).contains(x)), "{name}");
+            assert_eq!(values, draw(copy.as_mut(), (3, 5), i), "{name}");
+        }
+    }
+}
+
+#[test]
+fn pixel_samples_are_stratified() {
+    let mut stratified = StratifiedSampler::new(16, 1);
+    let mut sobol = SobolSampler::new(16, 1);
+    for sampler in [&mut stratified as &mut dyn Sampler, &mut sobol] {
+        for dimension in 0..4 {
+            let mut cells = [false; 16];
+            for i in 0..16 {
+                sampler.start_pixel_sample((7, 2), i);
+                for _ in 0..dimension {
+                    sampler.get_2d();
+                }
+                let (u, v) = sampler.get_2d();
+                let cell = (v * 4.) as usize * 4 + (u * 4.) as usize;
+                assert!(!cells[cell], "two samples in cell {cell}");
+                cells[cell] = true;
+            }
+        }
+    }
+}
+
+#[test]
+fn low_discrepancy_samplers_reduce_error() {
+    let independent = rms_error(&mut IndependentSampler::new(0));
+    for (name, mut sampler) in seeded_samplers() {
+        let error = rms_error(sampler.as_mut());
+        assert!(
+            error < 0.5 * independent,
+            "{name}: error {error}, independent error {independent}"
+        );
+    }
+}
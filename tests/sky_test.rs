@@ -0,0 +1,165 @@
+mod common;
+
+use std::sync::Arc;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use raytrace::{
+    bdpt::BidirectionalPathTracer,
+    camera::{Camera, PhysicalCamera},
+    film::Film,
+    hittable::{HittableList, Sphere},
+    light::Light,
+    material::Lambertian,
+    render::render,
+    sampler::IndependentSampler,
+    sampling,
+    scene::Scene,
+    sky::{sun_direction, PhysicalSky, DAYLIGHT_EV100, DAYLIGHT_EXPOSURE, SUN_ANGULAR_RADIUS},
+    types::{Ray, Vec3},
+};
+
+use common::{HEIGHT, WIDTH};
+
+fn radiance(light: &dyn Light, dir: Vec3) -> Vec3 {
+    light.le(&Ray::from(Vec3::default(), dir))
+}
+
+#[test]
+fn sky_matches_the_preetham_model() {
+    let (turbidity, elevation) = (3., 40f64);
+    let sky = PhysicalSky::new(sun_direction(elevation, 90.), turbidity, 0.);
+
+    // Zenith luminance from the paper's fit, in kilocandela per square metre
+    let theta_s = (90. - elevation).to_radians();
+    let chi = (4. / 9. - turbidity / 120.) * (std::f64::consts::PI - 2. * theta_s);
+    let expected = (4.0453 * turbidity - 4.9710) * chi.tan() - 0.2155 * turbidity + 2.4192;
+    let zenith = radiance(&sky, Vec3::new(0, 1, 0));
+    assert!((zenith.luminance() - expected).abs() < 0.02 * expected);
+
+    // A clear sky is blue, brightest around the sun and darkest opposite it
+    assert!(zenith.b() > zenith.r());
+    let near_sun = radiance(&sky, sun_direction(elevation + 5., 90.));
+    let opposite = radiance(&sky, sun_direction(elevation, -90.));
+    assert!(near_sun.luminance() > 3. * opposite.luminance());
+
+    // A black ground reflects nothing
+    assert!(radiance(&sky, Vec3::new(0, -1, 0)).is_zero());
+}
+
+#[test]
+fn low_sun_is_dimmer_and_redder() {
+    let color = |elevation: f64, turbidity: f64| {
+        let sun = PhysicalSky::new(sun_direction(elevation, 0.), turbidity, 0.3).sun();
+        radiance(&sun, sun_direction(elevation, 0.))
+    };
+    let noon = color(70., 3.);
+    let sunset = color(3., 3.);
+    assert!(sunset.luminance() < 0.5 * noon.luminance());
+    assert!(sunset.r() / sunset.b() > 2. * noon.r() / noon.b());
+    assert!(color(70., 8.).luminance() < noon.luminance());
+
+    // About 100 thousand lux falls from a high sun
+    let solid_angle = 2. * std::f64::consts::PI * (1. - f64::cos(SUN_ANGULAR_RADIUS.to_radians()));
+    let illuminance = noon.luminance() * solid_angle;
+    assert!((60. ..130.).contains(&illuminance), "{illuminance} klx");
+}
+
+#[test]
+fn ground_reflects_the_sun_and_sky() {
+    let (albedo, elevation) = (0.4, 35.);
+    let sky = PhysicalSky::new(sun_direction(elevation, 20.), 4., albedo);
+    let sun = sky.sun();
+
+    // Irradiance on the ground from a Monte Carlo estimate over the sky, and
+    // from the sun
+    let mut rng = StdRng::seed_from_u64(3);
+    let n = 100_000;
+    let sky_irradiance = (0..n)
+        .map(|_| {
+            let local = sampling::cosine_hemisphere((rng.gen(), rng.gen()));
+            radiance(&sky, Vec3::new(local.x(), local.z(), local.y()))
+        })
+        .fold(Vec3::default(), |sum, l| sum + l)
+        * (std::f64::consts::PI / n as f64);
+    let sun_sample = sun
+        .sample_li(&Vec3::default(), (0.5, 0.5), (Vec3::default(), 1.))
+        .unwrap();
+    let sun_irradiance = sun_sample.radiance * (sun_sample.wi.y() / sun_sample.pdf);
+
+    let expected = albedo * (sky_irradiance + sun_irradiance) / std::f64::consts::PI;
+    let ground = radiance(&sky, Vec3::new(0.3, -1., 0.2));
+    assert!((ground - expected).length() < 0.01 * expected.length());
+}
+
+#[test]
+fn sampling_agrees_with_the_densities() {
+    let sky = PhysicalSky::new(sun_direction(20., 130.), 2.5, 0.2).with_intensity(2.);
+    let sun = sky.sun();
+    let p = Vec3::new(0, 1, 0);
+    let world = (Vec3::default(), 5.);
+    let mut rng = StdRng::seed_from_u64(4);
+    for light in [&sky as &dyn Light, &sun] {
+        for _ in 0..500 {
+            let sample = light.sample_li(&p, (rng.gen(), rng.gen()), world).unwrap();
+            let pdf = light.pdf_li(&p, &sample.wi);
+            assert!((sample.pdf - pdf).abs() <= 1e-6 * pdf);
+            let le = light.le(&Ray::from(p, sample.wi));
+            assert!((le - sample.radiance).length() <= 1e-9 * le.length());
+        }
+    }
+    // The sky is sampled more often where it is bright
+    let pdf = |dir: Vec3| sky.pdf_li(&p, &dir);
+    assert!(pdf(sun_direction(22., 130.)) > 2. * pdf(sun_direction(22., -50.)));
+}
+
+#[test]
+fn daylight_exposure_does_not_saturate() {
+    let mut world = HittableList::new();
+    let grey = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+    world.add(Box::new(Sphere::new(
+        Vec3::new(0, -100, 0),
+        100.,
+        grey.clone(),
+    )));
+    world.add(Box::new(Sphere::new(Vec3::new(0, 1, 0), 1., grey)));
+    let sky = PhysicalSky::new(sun_direction(45., 30.), 3., 0.3);
+    let lights: Vec<Arc<dyn Light>> = vec![Arc::new(sky.sun()), Arc::new(sky)];
+    let scene = Scene::new(world, lights);
+
+    // Cameras without settings, and a physical one at f/2.8 metered for
+    // daylight, which sees the sky as they do
+    let physical = PhysicalCamera::new(
+        Vec3::new(0, 2, 8),
+        Vec3::new(0, 1, 0),
+        Vec3::new(0, 1, 0),
+        WIDTH as f64 / HEIGHT as f64,
+        8.,
+    )
+    .with_f_number(2.8)
+    .with_iso(400.)
+    .with_exposure_value(DAYLIGHT_EV100);
+    assert_eq!(
+        PhysicalSky::exposure_for(&common::camera()),
+        DAYLIGHT_EXPOSURE
+    );
+    assert!((PhysicalSky::exposure_for(&physical) / DAYLIGHT_EXPOSURE - 1.).abs() < 1e-9);
+    let cameras: [&dyn Camera; 2] = [&common::camera(), &physical];
+    for camera in cameras {
+        let exposure = PhysicalSky::exposure_for(camera);
+        let mut film = Film::new(WIDTH, HEIGHT);
+        render(
+            &scene,
+            camera,
+            &BidirectionalPathTracer::new(5),
+            &IndependentSampler::new(0),
+            &mut film,
+            16,
+        );
+        let luminance = |x, y| exposure * film.pixel(x, y).luminance();
+        let pixels = || (0..HEIGHT).flat_map(|y| (0..WIDTH).map(move |x| (x, y)));
+        let mean = pixels().map(|(x, y)| luminance(x, y)).sum::<f64>() / (WIDTH * HEIGHT) as f64;
+        let saturated = pixels().filter(|&(x, y)| luminance(x, y) >= 1.).count();
+        assert!((0.1..0.8).contains(&mean), "{mean}");
+        assert!(saturated < WIDTH * HEIGHT / 20, "{saturated} saturated");
+    }
+}
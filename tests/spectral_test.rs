@@ -0,0 +1,98 @@
+mod common;
+
+use std::sync::Arc;
+
+use raytrace::{
+    hittable::{Hittable, Sphere},
+    integrator::PathTracer,
+    material::{Dielectric, Dispersion, Lambertian},
+    sampler::{IndependentSampler, Sampler},
+    spectrum::{self, Wavelengths, LAMBDA_MAX, LAMBDA_MIN},
+    types::{Ray, Vec3},
+};
+
+use common::{assert_regions_close, camera, render_film, GROUND, SPHERE, UNDER_SPHERE};
+
+#[test]
+fn upsampled_colors_keep_their_rgb() {
+    let steps = 2000;
+    let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+    for rgb in [
+        Vec3::new(1, 1, 1),
+        Vec3::new(0.7, 0.3, 0.3),
+        Vec3::new(0.1, 0.8, 0.2),
+        Vec3::new(0.2, 0.4, 0.9),
+        Vec3::new(4, 2, 1),
+    ] {
+        let round_trip: Vec3 = (0..steps)
+            .map(|i| {
+                let lambda = LAMBDA_MIN + (i as f64 + 0.5) * step;
+                spectrum::upsample(&rgb, lambda) * spectrum::wavelength_rgb(lambda, 1.) * step
+            })
+            .sum();
+        assert!(
+            (round_trip - rgb).length() < 0.02 * rgb.length(),
+            "{} {} {}",
+            round_trip.r(),
+            round_trip.g(),
+            round_trip.b()
+        );
+    }
+
+    // Hero wavelengths estimate the same
+    let mut sampler = IndependentSampler::new(0);
+    let rgb = Vec3::new(0.7, 0.3, 0.3);
+    let n = 100_000;
+    let mean = (0..n)
+        .map(|_| {
+            let wavelengths = Wavelengths::sample_hero(sampler.get_1d());
+            wavelengths.to_rgb(&wavelengths.spectral(&rgb))
+        })
+        .sum::<Vec3>()
+        / n as f64;
+    assert!(
+        (mean - rgb).length() < 0.02,
+        "{} {} {}",
+        mean.r(),
+        mean.g(),
+        mean.b()
+    );
+}
+
+#[test]
+fn spectral_path_tracer_matches_rgb() {
+    let scene = common::scene(Some(Arc::new(Lambertian::new(Vec3::new(0.7, 0.3, 0.3)))));
+    let film = |spectral: bool| {
+        let integrator = PathTracer::new(5).with_spectral(spectral);
+        render_film(&scene, &camera(), &integrator, 1024)
+    };
+    assert_regions_close(
+        &film(false),
+        &film(true),
+        &[(UNDER_SPHERE, 0.08), (SPHERE, 0.03), (GROUND, 0.02)],
+    );
+}
+
+#[test]
+fn dispersion_keeps_only_the_hero_wavelength() {
+    let glass = Dielectric::new(1.5).with_dispersion(Dispersion::sf11());
+    let sphere = Sphere::new(Vec3::default(), 1., Arc::new(glass));
+    let mut sampler = IndependentSampler::new(1);
+
+    let wavelengths = Wavelengths::sample_hero(0.3);
+    let mut ray =
+        Ray::from(Vec3::new(0, 0.5, -5), Vec3::new(0, 0, 1)).with_wavelength(Some(wavelengths));
+    let mut weight = Vec3::new(1, 1, 1);
+    while let Some(hit) = sphere.hit((1e-9, f64::INFINITY), &ray) {
+        let (scattered, attenuation) = hit.mat.scatter(&ray, &hit, &mut sampler).unwrap();
+        weight *= attenuation;
+        ray = scattered;
+    }
+    // The hero stands in for all three wavelengths, once
+    assert!((weight - Vec3::new(3, 0, 0)).length() < 1e-12);
+    let Some(Wavelengths::Hero { lambda, secondary }) = ray.wavelength else {
+        panic!("lost the wavelengths");
+    };
+    assert!(!secondary);
+    assert_eq!(lambda[0], wavelengths.hero());
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use std::{sync::Arc, thread};
+
+use raytrace::{
+    bdpt::BidirectionalPathTracer,
+    film::Film,
+    integrator::{Integrator, PathTracer},
+    material::Lambertian,
+    render::render,
+    sampler::IndependentSampler,
+    stats::RenderStats,
+    types::Vec3,
+};
+
+use common::{camera, HEIGHT, WIDTH};
+
+const SAMPLES: usize = 4;
+
+#[test]
+fn phases_count_their_rays() {
+    let scene = common::scene(Some(Arc::new(Lambertian::new(Vec3::new(0.5, 0.2, 0.2)))));
+    let mut stats = RenderStats::new();
+    for (name, integrator) in [
+        ("path", &PathTracer::new(5) as &dyn Integrator),
+        ("bdpt", &BidirectionalPathTracer::new(5)),
+    ] {
+        let mut film = Film::new(WIDTH, HEIGHT);
+        stats.phase(name, || {
+            render(
+                &scene,
+                &camera(),
+                integrator,
+                &IndependentSampler::new(0),
+                &mut film,
+                SAMPLES,
+            )
+        });
+    }
+
+    let phases = stats.phases();
+    assert_eq!(phases.len(), 2);
+    let (path, bdpt) = (phases[0].rays, phases[1].rays);
+    for rays in [path, bdpt] {
+        assert_eq!(rays.camera_rays, (WIDTH * HEIGHT * SAMPLES) as u64);
+        assert!(rays.secondary_rays() > 0);
+        // Every ray is tested against all three spheres
+        assert_eq!(rays.intersection_tests, 3 * rays.total_rays());
+        assert!(rays.average_path_length() >= 1.);
+    }
+
+    // The path tracer only traces paths from the camera, and samples the
+    // lights at most once at each of their hits
+    assert_eq!(path.light_paths, 0);
+    assert!(path.shadow_rays > 0 && path.shadow_rays < path.extension_rays);
+    assert!(path.average_path_length() <= 6.);
+    assert!(bdpt.light_paths > 0);
+    assert!(bdpt.shadow_rays > 0);
+
+    assert_eq!(stats.rays(), path + bdpt);
+    assert!(stats.rays_per_second() > 0.);
+}
+
+#[test]
+fn concurrent_renders_count_only_their_own_rays() {
+    let scene = common::scene(Some(Arc::new(Lambertian::new(Vec3::new(0.5, 0.2, 0.2)))));
+    let render_with = |samples| {
+        let mut film = Film::new(WIDTH, HEIGHT);
+        render(
+            &scene,
+            &camera(),
+            &PathTracer::new(5),
+            &IndependentSampler::new(0),
+            &mut film,
+            samples,
+        )
+    };
+    let (few, many) = thread::scope(|s| {
+        let few = s.spawn(|| render_with(SAMPLES));
+        let many = s.spawn(|| render_with(4 * SAMPLES));
+        (few.join().unwrap(), many.join().unwrap())
+    });
+    assert_eq!(few.camera_rays, (WIDTH * HEIGHT * SAMPLES) as u64);
+    assert_eq!(many.camera_rays, (WIDTH * HEIGHT * 4 * SAMPLES) as u64);
+}
@@ -0,0 +1,81 @@
+use raytrace::{
+    camera::{Camera, EquirectangularCamera, PerspectiveCamera, StereoCamera, StereoLayout},
+    sampler::IndependentSampler,
+    types::{Ray, Vec3},
+};
+
+fn ray(camera: &dyn Camera, s: f64, t: f64) -> Ray {
+    camera
+        .get_ray(s, t, &mut IndependentSampler::new(0))
+        .unwrap()
+        .0
+}
+
+// Closest distance between the lines along two rays
+fn miss_distance(a: &Ray, b: &Ray) -> f64 {
+    let normal = Vec3::unit_vector(&a.direction.cross(&b.direction));
+    Vec3::dot(&(b.origin - a.origin), &normal).abs()
+}
+
+fn eye(offset: f64) -> PerspectiveCamera {
+    PerspectiveCamera::new(
+        Vec3::new(0, 0, 0),
+        Vec3::new(0, 0, -1),
+        Vec3::new(0, 1, 0),
+        40.,
+        1.5,
+        0.,
+        5.,
+    )
+    .with_stereo_eye(offset, 3.)
+}
+
+#[test]
+fn off_axis_eyes_converge() {
+    let (left, right) = (eye(-0.03), eye(0.03));
+    for (s, t) in [(0.5, 0.5), (0.1, 0.8), (0.9, 0.2)] {
+        let (a, b) = (ray(&left, s, t), ray(&right, s, t));
+        assert!((b.origin - a.origin - Vec3::new(0.06, 0., 0.)).length() < 1e-12);
+        assert!(miss_distance(&a, &b) < 1e-12);
+        // They meet on the plane at the convergence distance
+        let at = |ray: &Ray| ray.origin + (-3. / ray.direction.z()) * ray.direction;
+        assert!((at(&a) - at(&b)).length() < 1e-12);
+    }
+}
+
+#[test]
+fn layouts_split_film_between_eyes() {
+    let camera = |layout| StereoCamera::new(Box::new(eye(-0.03)), Box::new(eye(0.03)), layout);
+    let side_by_side = camera(StereoLayout::SideBySide);
+    assert_eq!(ray(&side_by_side, 0.25, 0.5).origin.x(), -0.03);
+    assert_eq!(ray(&side_by_side, 0.75, 0.5).origin.x(), 0.03);
+    let centre = ray(&eye(-0.03), 0.5, 0.5).direction;
+    assert!((ray(&side_by_side, 0.25, 0.5).direction - centre).length() < 1e-12);
+
+    let top_bottom = camera(StereoLayout::TopBottom);
+    assert_eq!(ray(&top_bottom, 0.5, 0.75).origin.x(), -0.03);
+    assert_eq!(ray(&top_bottom, 0.5, 0.25).origin.x(), 0.03);
+    assert!(!top_bottom.can_connect());
+}
+
+#[test]
+fn omnidirectional_eyes_circle_centre() {
+    let camera = |offset| {
+        EquirectangularCamera::new(Vec3::new(0, 1, 0), Vec3::new(0, 1, -1), Vec3::new(0, 1, 0))
+            .with_stereo_eye(offset, 2.)
+    };
+    let (left, right) = (camera(-0.032), camera(0.032));
+    for s in [0., 0.2, 0.5, 0.7, 0.95] {
+        let (a, b) = (ray(&left, s, 0.5), ray(&right, s, 0.5));
+        for eye in [&a, &b] {
+            let offset = eye.origin - Vec3::new(0, 1, 0);
+            assert!((offset.length() - 0.032).abs() < 1e-12);
+            assert!(offset.y().abs() < 1e-12);
+        }
+        // The eyes sit on either side of the direction the column looks in
+        let middle = a.direction + b.direction;
+        assert!(Vec3::dot(&(b.origin - a.origin), &middle).abs() < 1e-12);
+        assert!(middle.cross(&(b.origin - a.origin)).y() < 0.);
+        assert!(miss_distance(&a, &b) < 1e-12);
+    }
+}